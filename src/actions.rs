@@ -0,0 +1,305 @@
+use eframe::egui;
+use progetto_malnati::keybidings::{Action, Scope};
+
+use super::AppState;
+use super::MyApp;
+
+//------ How an action is run, shared by its button, its shortcut and the command palette
+pub struct ActionEntry {
+    pub action: Action,
+    // States the action is valid in, it is not offered and does nothing elsewhere
+    pub states: &'static [AppState],
+    // Still run by its shortcut while a text field has focus (otherwise the field gets the keys)
+    pub while_typing: bool,
+    pub run: fn(&mut MyApp, &egui::Context),
+}
+
+const EDITING: &[AppState] = &[AppState::MainApp, AppState::Annotate];
+
+pub static REGISTRY: [ActionEntry; 29] = [
+    ActionEntry {
+        action: Action::New,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, ctx| {
+            let result = app.set_new_capture_window(ctx);
+            if app.report(result).is_some() {
+                app.begin_area_selection(ctx);
+            }
+        },
+    },
+    ActionEntry {
+        action: Action::Fullscreen,
+        states: &[AppState::MainApp, AppState::NewCapture],
+        while_typing: true,
+        run: |app, ctx| {
+            if app.state == AppState::MainApp {
+                let result = app.set_new_capture_window(ctx);
+                if app.report(result).is_none() {
+                    return;
+                }
+            }
+            app.handle_fullscreen_capture(ctx);
+        },
+    },
+    ActionEntry {
+        action: Action::CaptureClipboard,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, ctx| {
+            let result = app.set_new_capture_window(ctx);
+            if app.report(result).is_some() {
+                app.copy_next_capture = true;
+                app.handle_fullscreen_capture(ctx);
+            }
+        },
+    },
+    ActionEntry {
+        action: Action::Delayed,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, ctx| {
+            let result = app.start_countdown(ctx, app.delay);
+            app.report(result);
+        },
+    },
+    ActionEntry {
+        action: Action::Record,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, ctx| app.begin_screen_recording(ctx),
+    },
+    ActionEntry {
+        action: Action::PauseRecording,
+        states: &[AppState::Recording],
+        while_typing: false,
+        run: |app, _ctx| app.toggle_recording_pause(),
+    },
+    ActionEntry {
+        action: Action::OpenFile,
+        states: &[AppState::MainApp, AppState::Gallery],
+        while_typing: true,
+        run: |app, ctx| app.open_files_dialog(ctx),
+    },
+    ActionEntry {
+        action: Action::Paste,
+        states: &[AppState::MainApp, AppState::Gallery],
+        while_typing: false,
+        run: |app, ctx| app.paste_from_clipboard(ctx),
+    },
+    ActionEntry {
+        action: Action::Area,
+        states: &[AppState::NewCapture],
+        while_typing: false,
+        // Hides the options, the next click starts the selection
+        run: |app, _ctx| app.area = true,
+    },
+    ActionEntry {
+        action: Action::Save,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.handle_save_request(),
+    },
+    ActionEntry {
+        action: Action::SaveAs,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.handle_save_as_request(),
+    },
+    ActionEntry {
+        action: Action::Clipboard,
+        states: &[AppState::MainApp],
+        while_typing: false,
+        run: |app, _ctx| app.handle_copy_request(),
+    },
+    ActionEntry {
+        action: Action::Crop,
+        states: &[AppState::MainApp],
+        while_typing: false,
+        run: |app, ctx| app.handle_crop_request(ctx),
+    },
+    ActionEntry {
+        action: Action::Annotate,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| {
+            if app.capture().is_some() {
+                app.state = AppState::Annotate;
+            }
+        },
+    },
+    ActionEntry {
+        action: Action::Pin,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, ctx| {
+            let result = app.pin_capture(ctx);
+            app.report(result);
+        },
+    },
+    ActionEntry {
+        action: Action::Undo,
+        states: EDITING,
+        while_typing: false,
+        run: |app, ctx| app.undo(ctx),
+    },
+    ActionEntry {
+        action: Action::Redo,
+        states: EDITING,
+        while_typing: false,
+        run: |app, ctx| app.redo(ctx),
+    },
+    ActionEntry {
+        action: Action::Clear,
+        states: &[AppState::Annotate],
+        while_typing: false,
+        run: |app, ctx| app.clear_edits(ctx),
+    },
+    ActionEntry {
+        action: Action::EditHistory,
+        states: EDITING,
+        while_typing: true,
+        run: |app, _ctx| app.show_edit_history = !app.show_edit_history,
+    },
+    ActionEntry {
+        action: Action::Log,
+        states: &[
+            AppState::MainApp,
+            AppState::Annotate,
+            AppState::Settings,
+            AppState::Gallery,
+        ],
+        while_typing: true,
+        run: |app, _ctx| app.notifications.show_history = !app.notifications.show_history,
+    },
+    ActionEntry {
+        action: Action::Settings,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.state = AppState::Settings,
+    },
+    ActionEntry {
+        action: Action::Gallery,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.state = AppState::Gallery,
+    },
+    ActionEntry {
+        action: Action::NextTab,
+        states: EDITING,
+        while_typing: true,
+        run: |app, _ctx| app.cycle_tab(1),
+    },
+    ActionEntry {
+        action: Action::PreviousTab,
+        states: EDITING,
+        while_typing: true,
+        run: |app, _ctx| app.cycle_tab(-1),
+    },
+    ActionEntry {
+        action: Action::CloseTab,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.close_tab(app.active_tab),
+    },
+    ActionEntry {
+        action: Action::CloseSavedTabs,
+        states: &[AppState::MainApp],
+        while_typing: true,
+        run: |app, _ctx| app.close_saved_tabs(),
+    },
+    ActionEntry {
+        action: Action::CommandPalette,
+        states: &[
+            AppState::MainApp,
+            AppState::Crop,
+            AppState::Annotate,
+            AppState::Settings,
+            AppState::Gallery,
+        ],
+        while_typing: true,
+        run: |app, _ctx| app.open_command_palette(),
+    },
+    ActionEntry {
+        action: Action::Confirm,
+        states: &[
+            AppState::NewCapture,
+            AppState::Crop,
+            AppState::Annotate,
+            AppState::Recording,
+        ],
+        while_typing: false,
+        run: |app, ctx| match app.state {
+            // Whole target, as the "Full screen" button of the overlay
+            AppState::NewCapture => app.handle_fullscreen_capture(ctx),
+            AppState::Crop => app.confirm_crop(ctx),
+            AppState::Recording => app.stop_screen_recording(ctx),
+            _ => app.finish_annotating(),
+        },
+    },
+    ActionEntry {
+        action: Action::Cancel,
+        states: &[
+            AppState::NewCapture,
+            AppState::Selection,
+            AppState::Crop,
+            AppState::Countdown,
+            AppState::Annotate,
+            AppState::Settings,
+            AppState::Gallery,
+            AppState::Recording,
+        ],
+        while_typing: false,
+        run: |app, ctx| match app.state {
+            AppState::NewCapture | AppState::Selection => app.abort_selection(ctx),
+            AppState::Countdown => app.cancel_countdown(ctx),
+            AppState::Recording => app.cancel_screen_recording(ctx),
+            AppState::Annotate => app.finish_annotating(),
+            AppState::Settings => app.leave_settings(),
+            AppState::Gallery => app.state = AppState::MainApp,
+            _ => app.state = AppState::MainApp,
+        },
+    },
+];
+
+pub fn entry(action: Action) -> &'static ActionEntry {
+    REGISTRY
+        .iter()
+        .find(|entry| entry.action == action)
+        .expect("every action is registered")
+}
+
+impl ActionEntry {
+    pub fn is_available(&self, state: &AppState) -> bool {
+        self.states.contains(state)
+    }
+}
+
+impl MyApp {
+    //------ Runs action if it is valid in the current state
+    pub fn run_action(&mut self, ctx: &egui::Context, action: Action) {
+        let entry = entry(action);
+        if entry.is_available(&self.state) {
+            (entry.run)(self, ctx);
+        }
+    }
+
+    //------ Runs the action of a pressed shortcut, text fields keep the keys they need
+    pub fn run_shortcut(&mut self, ctx: &egui::Context, action: Action) {
+        if ctx.wants_keyboard_input() && !entry(action).while_typing {
+            return;
+        }
+        self.run_action(ctx, action);
+    }
+
+    //------ Button of an action, with its shortcut in the tooltip
+    pub fn action_button(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, text: &str, action: Action) {
+        let mut response = ui.button(text);
+        if let Some(shortcut) = self.key_bindings.get(Scope::Window, action) {
+            response = response.on_hover_text(shortcut.to_string());
+        }
+        if response.clicked() {
+            self.run_action(ctx, action);
+        }
+    }
+}
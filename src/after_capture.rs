@@ -0,0 +1,212 @@
+use eframe::egui;
+use progetto_malnati::{desktop_notification, Capture, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+
+use super::MyApp;
+
+//------ How the capture was started, each kind has its own steps
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CaptureMode {
+    FullScreen,
+    #[default]
+    Area,
+    Delayed,
+}
+impl CaptureMode {
+    pub const ALL: [CaptureMode; 3] = [CaptureMode::FullScreen, CaptureMode::Area, CaptureMode::Delayed];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaptureMode::FullScreen => "Full screen",
+            CaptureMode::Area => "Area",
+            CaptureMode::Delayed => "Delayed",
+        }
+    }
+}
+
+//------ Steps run on a new capture, in the order of the fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AfterCapture {
+    pub copy: bool,
+    // In the output folder, named after the file template
+    pub save: bool,
+    // Run through the shell with the image path as $1 and $CAPTURE_PATH, empty for none
+    pub command: String,
+    pub notify: bool,
+    pub open_editor: bool,
+}
+
+impl Default for AfterCapture {
+    fn default() -> Self {
+        AfterCapture {
+            copy: false,
+            save: false,
+            command: String::new(),
+            notify: false,
+            open_editor: true,
+        }
+    }
+}
+
+impl AfterCapture {
+    // Steps turned on and off with a checkbox, by index in switch_mut
+    pub const SWITCHES: [&'static str; 4] = [
+        "Copy to clipboard",
+        "Save to the output folder",
+        "Show a desktop notification",
+        "Open the editor",
+    ];
+
+    pub fn switch_mut(&mut self, index: usize) -> &mut bool {
+        match index {
+            0 => &mut self.copy,
+            1 => &mut self.save,
+            2 => &mut self.notify,
+            _ => &mut self.open_editor,
+        }
+    }
+
+    // Used by "Capture to clipboard" whatever the settings
+    fn clipboard_only() -> Self {
+        AfterCapture {
+            copy: true,
+            open_editor: false,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AfterCaptureConfig {
+    pub full_screen: AfterCapture,
+    pub area: AfterCapture,
+    pub delayed: AfterCapture,
+}
+
+impl AfterCaptureConfig {
+    pub fn get(&self, mode: CaptureMode) -> &AfterCapture {
+        match mode {
+            CaptureMode::FullScreen => &self.full_screen,
+            CaptureMode::Area => &self.area,
+            CaptureMode::Delayed => &self.delayed,
+        }
+    }
+
+    pub fn get_mut(&mut self, mode: CaptureMode) -> &mut AfterCapture {
+        match mode {
+            CaptureMode::FullScreen => &mut self.full_screen,
+            CaptureMode::Area => &mut self.area,
+            CaptureMode::Delayed => &mut self.delayed,
+        }
+    }
+}
+
+impl MyApp {
+    //------ Opens a new capture in a tab and runs the steps chosen for its mode
+    pub fn finish_capture(&mut self, ctx: &egui::Context, capture: Capture) {
+        self.open_tab(ctx, capture);
+        self.add_to_gallery();
+        let steps = if std::mem::take(&mut self.copy_next_capture) {
+            AfterCapture::clipboard_only()
+        } else {
+            self.after_capture.get(self.capture_mode).clone()
+        };
+        let mut done = Vec::new();
+        if steps.copy {
+            let result = self.copy_to_clipboard();
+            if self.report(result).is_some() {
+                done.push("copied to clipboard".to_string());
+            }
+        }
+        let mut saved = None;
+        if steps.save {
+            let result = self.save_capture();
+            if let Some(path) = self.report(result) {
+                done.push(format!("saved to {}", path.display()));
+                saved = Some(path);
+            }
+        }
+        if !steps.command.trim().is_empty() {
+            // The command needs a file, a temporary one when the capture is not saved
+            let file = match &saved {
+                Some(path) => Some((path.clone(), false)),
+                None => {
+                    let result = self.save_temporary();
+                    self.report(result).map(|path| (path, true))
+                }
+            };
+            if let Some((path, temporary)) = file {
+                let result = run_command(&steps.command, path, temporary);
+                if self.report(result).is_some() {
+                    done.push(format!("passed to \"{}\"", steps.command.trim()));
+                }
+            }
+        }
+        if !done.is_empty() {
+            let message = format!("Capture {}", done.join(", "));
+            if steps.notify {
+                notify_desktop(message.clone());
+            }
+            self.notifications.info(message);
+        } else if steps.notify {
+            notify_desktop("Capture taken".to_string());
+        }
+        self.reveal_editor = Some(steps.open_editor);
+        self.answer_remote_capture_with(saved);
+    }
+}
+
+// Started in the background, the exit status is only logged.
+// A temporary file is removed when the command exits
+fn run_command(command: &str, path: PathBuf, temporary: bool) -> Result<()> {
+    #[cfg(unix)]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command).arg("sh").arg(&path);
+        shell
+    };
+    #[cfg(not(unix))]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    };
+    let mut child = match shell.env("CAPTURE_PATH", &path).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            if temporary {
+                let _ = fs::remove_file(&path);
+            }
+            return Err(e.into());
+        }
+    };
+    let command = command.to_string();
+    thread::spawn(move || {
+        match child.wait() {
+            Ok(status) if !status.success() => {
+                log::warn!("after-capture command \"{}\" exited with {}", command, status)
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("after-capture command \"{}\": {}", command, e),
+        }
+        if temporary {
+            let _ = fs::remove_file(&path);
+        }
+    });
+    Ok(())
+}
+
+// The notification server may be slow to answer, the interface must not wait for it
+fn notify_desktop(message: String) {
+    thread::spawn(move || {
+        if let Err(e) = desktop_notification::show("Screen Capture", &message) {
+            log::warn!("{}", e);
+        }
+    });
+}
@@ -0,0 +1,535 @@
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use ecolor::Color32;
+use emath::{Pos2, Rect, Vec2};
+use epaint::text::{FontDefinitions, FontFamily};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+// Points used to approximate ellipses, both on screen and in the exported image
+const ELLIPSE_SEGMENTS: usize = 64;
+
+//------ Drawing tools of the annotation editor
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Tool {
+    #[default]
+    Arrow,
+    Rectangle,
+    Ellipse,
+    Line,
+    Freehand,
+    Text,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 6] = [
+        Tool::Arrow,
+        Tool::Rectangle,
+        Tool::Ellipse,
+        Tool::Line,
+        Tool::Freehand,
+        Tool::Text,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Arrow => "Arrow",
+            Tool::Rectangle => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Line => "Line",
+            Tool::Freehand => "Freehand",
+            Tool::Text => "Text",
+        }
+    }
+}
+
+//------ Geometry of an annotation, in the virtual-desktop coordinates of the capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Arrow { from: Pos2, to: Pos2 },
+    Rectangle(Rect),
+    Ellipse(Rect),
+    Line { from: Pos2, to: Pos2 },
+    Freehand(Vec<Pos2>),
+    // pos is the top left corner of the first line
+    Text { pos: Pos2, text: String },
+}
+
+impl Shape {
+    //------ Shape created by pressing the pointer at `at` with tool
+    pub fn start(tool: Tool, at: Pos2, text: &str) -> Shape {
+        match tool {
+            Tool::Arrow => Shape::Arrow { from: at, to: at },
+            Tool::Rectangle => Shape::Rectangle(Rect::from_min_max(at, at)),
+            Tool::Ellipse => Shape::Ellipse(Rect::from_min_max(at, at)),
+            Tool::Line => Shape::Line { from: at, to: at },
+            Tool::Freehand => Shape::Freehand(vec![at]),
+            Tool::Text => Shape::Text {
+                pos: at,
+                text: text.to_string(),
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Arrow { .. } => "arrow",
+            Shape::Rectangle(_) => "rectangle",
+            Shape::Ellipse(_) => "ellipse",
+            Shape::Line { .. } => "line",
+            Shape::Freehand(_) => "freehand line",
+            Shape::Text { .. } => "text",
+        }
+    }
+
+    //------ Follows a drag that started at origin and is now at to
+    pub fn drag(&mut self, origin: Pos2, to: Pos2) {
+        match self {
+            Shape::Arrow { to: end, .. } | Shape::Line { to: end, .. } => *end = to,
+            Shape::Rectangle(rect) | Shape::Ellipse(rect) => *rect = Rect::from_two_pos(origin, to),
+            Shape::Freehand(points) => {
+                if points.last() != Some(&to) {
+                    points.push(to);
+                }
+            }
+            Shape::Text { pos, .. } => *pos = to,
+        }
+    }
+}
+
+//------ Look of an annotation, sizes are in points like the shape
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Style {
+    pub stroke_color: Color32,
+    pub stroke_width: f32,
+    // Only rectangles and ellipses are filled
+    pub fill: Option<Color32>,
+    pub font_size: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            stroke_color: Color32::RED,
+            stroke_width: 3.0,
+            fill: None,
+            font_size: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub shape: Shape,
+    pub style: Style,
+}
+
+impl Annotation {
+    //------ Polylines drawn with the stroke, a closed outline repeats its first point
+    pub fn strokes(&self) -> Vec<Vec<Pos2>> {
+        match &self.shape {
+            Shape::Arrow { from, to } => {
+                let [left, tip, right] = arrow_head(*from, *to, self.style.stroke_width);
+                vec![vec![*from, *to], vec![left, tip, right]]
+            }
+            Shape::Line { from, to } => vec![vec![*from, *to]],
+            Shape::Rectangle(rect) => vec![vec![
+                rect.left_top(),
+                rect.right_top(),
+                rect.right_bottom(),
+                rect.left_bottom(),
+                rect.left_top(),
+            ]],
+            Shape::Ellipse(rect) => {
+                let mut points = ellipse_points(*rect);
+                points.push(points[0]);
+                vec![points]
+            }
+            Shape::Freehand(points) => vec![points.clone()],
+            Shape::Text { .. } => Vec::new(),
+        }
+    }
+
+    //------ Polygon painted with the fill colour, if any
+    pub fn fill_polygon(&self) -> Option<Vec<Pos2>> {
+        self.style.fill?;
+        match &self.shape {
+            Shape::Rectangle(rect) => Some(vec![
+                rect.left_top(),
+                rect.right_top(),
+                rect.right_bottom(),
+                rect.left_bottom(),
+            ]),
+            Shape::Ellipse(rect) => Some(ellipse_points(*rect)),
+            _ => None,
+        }
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        match &mut self.shape {
+            Shape::Arrow { from, to } | Shape::Line { from, to } => {
+                *from += offset;
+                *to += offset;
+            }
+            Shape::Rectangle(rect) | Shape::Ellipse(rect) => *rect = rect.translate(offset),
+            Shape::Freehand(points) => points.iter_mut().for_each(|p| *p += offset),
+            Shape::Text { pos, .. } => *pos += offset,
+        }
+    }
+
+    //------ Moves every point through map, used by rotations and flips of the capture.
+    // Text stays horizontal, the centre of its box is moved.
+    pub fn map_points(&mut self, map: impl Fn(Pos2) -> Pos2) {
+        match &mut self.shape {
+            Shape::Arrow { from, to } | Shape::Line { from, to } => {
+                *from = map(*from);
+                *to = map(*to);
+            }
+            Shape::Rectangle(rect) | Shape::Ellipse(rect) => {
+                *rect = Rect::from_two_pos(map(rect.min), map(rect.max));
+            }
+            Shape::Freehand(points) => points.iter_mut().for_each(|p| *p = map(*p)),
+            Shape::Text { pos, text } => {
+                let half = text_size(text, self.style.font_size) / 2.0;
+                *pos = map(*pos + half) - half;
+            }
+        }
+    }
+
+    //------ Area covered by the annotation, text is estimated from the font size
+    pub fn bounds(&self) -> Rect {
+        let mut bounds = bounds_of(self.strokes().into_iter().flatten());
+        if let Shape::Text { pos, text } = &self.shape {
+            bounds = bounds.union(Rect::from_min_size(*pos, text_size(text, self.style.font_size)));
+        }
+        bounds.expand(self.style.stroke_width / 2.0)
+    }
+
+    //------ False for shapes too small to be seen, e.g. a click without dragging
+    pub fn is_visible(&self) -> bool {
+        match &self.shape {
+            Shape::Arrow { from, to } | Shape::Line { from, to } => from.distance(*to) >= 2.0,
+            Shape::Rectangle(rect) | Shape::Ellipse(rect) => {
+                rect.width() >= 2.0 || rect.height() >= 2.0
+            }
+            Shape::Freehand(points) => points.len() >= 2,
+            Shape::Text { text, .. } => !text.trim().is_empty(),
+        }
+    }
+}
+
+//------ Index of the topmost annotation under pos, tolerance is in the same unit as pos
+pub fn hit_test(annotations: &[Annotation], pos: Pos2, tolerance: f32) -> Option<usize> {
+    annotations
+        .iter()
+        .rposition(|annotation| annotation.bounds().expand(tolerance).contains(pos))
+}
+
+//------ Two barbs and the tip of an arrow, sized after the stroke width
+pub fn arrow_head(from: Pos2, to: Pos2, stroke_width: f32) -> [Pos2; 3] {
+    let direction = (to - from).normalized();
+    let length = (stroke_width * 4.0).max(10.0);
+    let back = to - direction * length;
+    let side = direction.rot90() * length * 0.5;
+    [back + side, to, back - side]
+}
+
+pub fn ellipse_points(rect: Rect) -> Vec<Pos2> {
+    let center = rect.center();
+    let radius = rect.size() / 2.0;
+    (0..ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + Vec2::new(radius.x * angle.cos(), radius.y * angle.sin())
+        })
+        .collect()
+}
+
+//------ Draws the annotations on img, which covers image_rect of the desktop
+pub fn render(img: &mut RgbaImage, image_rect: Rect, annotations: &[Annotation]) {
+    if annotations.is_empty() || image_rect.width() <= 0.0 {
+        return;
+    }
+    // Image may have more pixels than points on HiDPI monitors
+    let scale = img.width() as f32 / image_rect.width();
+    let to_pixels = |p: Pos2| ((p - image_rect.min) * scale).to_pos2();
+
+    for annotation in annotations {
+        let style = annotation.style;
+        if let (Some(polygon), Some(fill)) = (annotation.fill_polygon(), style.fill) {
+            let polygon: Vec<Pos2> = polygon.into_iter().map(to_pixels).collect();
+            fill_polygon(img, &polygon, fill);
+        }
+        let strokes: Vec<Vec<Pos2>> = annotation
+            .strokes()
+            .into_iter()
+            .map(|line| line.into_iter().map(to_pixels).collect())
+            .collect();
+        stroke_polylines(img, &strokes, style.stroke_width * scale, style.stroke_color);
+        if let Shape::Text { pos, text } = &annotation.shape {
+            draw_text(
+                img,
+                to_pixels(*pos),
+                text,
+                style.font_size * scale,
+                style.stroke_color,
+            );
+        }
+    }
+}
+
+// Coverage of the shape over a block of pixels, so overlapping segments blend once
+struct Mask {
+    left: i64,
+    top: i64,
+    width: i64,
+    height: i64,
+    coverage: Vec<f32>,
+}
+
+impl Mask {
+    fn new(img: &RgbaImage, bounds: Rect) -> Option<Mask> {
+        let left = (bounds.min.x.floor() as i64).max(0);
+        let top = (bounds.min.y.floor() as i64).max(0);
+        let right = (bounds.max.x.ceil() as i64).min(img.width() as i64);
+        let bottom = (bounds.max.y.ceil() as i64).min(img.height() as i64);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        let (width, height) = (right - left, bottom - top);
+        Some(Mask {
+            left,
+            top,
+            width,
+            height,
+            coverage: vec![0.0; (width * height) as usize],
+        })
+    }
+
+    fn apply(&self, img: &mut RgbaImage, color: Color32) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coverage = self.coverage[(y * self.width + x) as usize];
+                if coverage > 0.0 {
+                    blend(img, (self.left + x) as u32, (self.top + y) as u32, color, coverage);
+                }
+            }
+        }
+    }
+}
+
+fn bounds_of(points: impl IntoIterator<Item = Pos2>) -> Rect {
+    let mut bounds = Rect::NOTHING;
+    for point in points {
+        bounds.extend_with(point);
+    }
+    bounds
+}
+
+fn stroke_polylines(img: &mut RgbaImage, lines: &[Vec<Pos2>], width: f32, color: Color32) {
+    let half = width / 2.0;
+    let bounds = bounds_of(lines.iter().flatten().copied()).expand(half + 1.0);
+    let Some(mut mask) = Mask::new(img, bounds) else {
+        return;
+    };
+    for line in lines {
+        let segments: Vec<(Pos2, Pos2)> = match line.len() {
+            1 => vec![(line[0], line[0])],
+            _ => line.windows(2).map(|w| (w[0], w[1])).collect(),
+        };
+        for (a, b) in segments {
+            let area = bounds_of([a, b]).expand(half + 1.0);
+            let left = (area.min.x.floor() as i64).max(mask.left);
+            let top = (area.min.y.floor() as i64).max(mask.top);
+            let right = (area.max.x.ceil() as i64).min(mask.left + mask.width);
+            let bottom = (area.max.y.ceil() as i64).min(mask.top + mask.height);
+            for y in top..bottom {
+                for x in left..right {
+                    let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    // Antialiasing over one pixel at the border of the stroke
+                    let coverage = (half + 0.5 - distance_to_segment(center, a, b)).clamp(0.0, 1.0);
+                    let index = ((y - mask.top) * mask.width + (x - mask.left)) as usize;
+                    mask.coverage[index] = mask.coverage[index].max(coverage);
+                }
+            }
+        }
+    }
+    mask.apply(img, color);
+}
+
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let length_sq = ab.length_sq();
+    if length_sq == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / length_sq).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+fn fill_polygon(img: &mut RgbaImage, polygon: &[Pos2], color: Color32) {
+    let Some(mut mask) = Mask::new(img, bounds_of(polygon.iter().copied())) else {
+        return;
+    };
+    for y in 0..mask.height {
+        for x in 0..mask.width {
+            let center = Pos2::new(
+                (mask.left + x) as f32 + 0.5,
+                (mask.top + y) as f32 + 0.5,
+            );
+            if contains(polygon, center) {
+                mask.coverage[(y * mask.width + x) as usize] = 1.0;
+            }
+        }
+    }
+    mask.apply(img, color);
+}
+
+// Even-odd rule
+fn contains(polygon: &[Pos2], p: Pos2) -> bool {
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &current in polygon {
+        if (current.y > p.y) != (previous.y > p.y)
+            && p.x
+                < (previous.x - current.x) * (p.y - current.y) / (previous.y - current.y)
+                    + current.x
+        {
+            inside = !inside;
+        }
+        previous = current;
+    }
+    inside
+}
+
+// Box of a text annotation, estimated from the font size
+fn text_size(text: &str, font_size: f32) -> Vec2 {
+    let lines = text.lines().count().max(1);
+    let longest = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    Vec2::new(longest as f32 * font_size * 0.6, lines as f32 * font_size * 1.2)
+}
+
+//------ Text in the proportional font used by the interface, so the export looks like the editor
+fn draw_text(img: &mut RgbaImage, pos: Pos2, text: &str, size: f32, color: Color32) {
+    let fonts = FontDefinitions::default();
+    let Some(data) = fonts
+        .families
+        .get(&FontFamily::Proportional)
+        .and_then(|names| names.first())
+        .and_then(|name| fonts.font_data.get(name))
+    else {
+        return;
+    };
+    let Ok(font) = FontRef::try_from_slice_and_index(&data.font, data.index) else {
+        log::warn!("cannot load the annotation font");
+        return;
+    };
+    let font = font.as_scaled(PxScale::from(size));
+    let mut caret = Pos2::new(pos.x, pos.y + font.ascent());
+    let mut previous = None;
+    for c in text.chars() {
+        if c == '\n' {
+            caret = Pos2::new(pos.x, caret.y + font.height() + font.line_gap());
+            previous = None;
+            continue;
+        }
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret.x += font.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(font.scale(), ab_glyph::point(caret.x, caret.y));
+        caret.x += font.h_advance(id);
+        previous = Some(id);
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let x = bounds.min.x as i64 + x as i64;
+                let y = bounds.min.y as i64 + y as i64;
+                if x >= 0 && y >= 0 && x < img.width() as i64 && y < img.height() as i64 {
+                    blend(img, x as u32, y as u32, color, coverage);
+                }
+            });
+        }
+    }
+}
+
+// Source-over blending of color, weighted by coverage
+fn blend(img: &mut RgbaImage, x: u32, y: u32, color: Color32, coverage: f32) {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let alpha = (a as f32 / 255.0) * coverage.clamp(0.0, 1.0);
+    let pixel = img.get_pixel_mut(x, y);
+    let dst_alpha = pixel.0[3] as f32 / 255.0;
+    let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+    if out_alpha <= 0.0 {
+        return;
+    }
+    for (channel, src) in pixel.0.iter_mut().take(3).zip([r, g, b]) {
+        let value = (src as f32 * alpha + *channel as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+        *channel = value.round() as u8;
+    }
+    pixel.0[3] = (out_alpha * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::Capture;
+    use emath::pos2;
+    use image::Rgba;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    // White capture of 100 × 60 points at 10,20 of the desktop, scale pixels per point
+    fn rendered(scale: u32) -> RgbaImage {
+        let image = RgbaImage::from_pixel(100 * scale, 60 * scale, WHITE);
+        let mut capture = Capture::from_image(image, "test");
+        capture.image_rect = Rect::from_min_size(pos2(10.0, 20.0), Vec2::new(100.0, 60.0));
+        capture.region = capture.image_rect;
+        let style = Style::default();
+        let shapes = [
+            Shape::Arrow { from: pos2(20.0, 30.0), to: pos2(70.0, 30.0) },
+            Shape::Rectangle(Rect::from_min_max(pos2(20.0, 50.0), pos2(60.0, 70.0))),
+            Shape::Text { pos: pos2(80.0, 40.0), text: "Hi".to_string() },
+        ];
+        capture.annotations = shapes
+            .into_iter()
+            .map(|shape| Annotation { shape, style })
+            .collect();
+        capture.rendered().unwrap()
+    }
+
+    fn check(scale: u32) {
+        let img = rendered(scale);
+        assert_eq!(img.dimensions(), (100 * scale, 60 * scale));
+        let at = |x: u32, y: u32| *img.get_pixel(x * scale, y * scale);
+        // Arrow shaft along y = 10 of the image, its head ends at x = 60
+        assert_eq!(at(30, 10), RED);
+        assert_eq!(at(30, 10 + 3), WHITE);
+        assert_eq!(at(63, 10), WHITE);
+        // Rectangle outline from 10,30 to 50,50, not filled
+        assert_eq!(at(30, 30), RED);
+        assert_eq!(at(10, 40), RED);
+        assert_eq!(at(30, 40), WHITE);
+        // Text inside its estimated box from 70,20, in the stroke colour
+        let text_box = Rect::from_min_size(pos2(70.0, 20.0), text_size("Hi", 20.0)) * scale as f32;
+        let mut inked = 0;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            if *pixel != WHITE && x as f32 >= text_box.min.x && y as f32 >= text_box.min.y {
+                assert!(text_box.contains(pos2(x as f32, y as f32)), "ink at {x},{y}");
+                assert!(pixel.0[1] < 255 && pixel.0[0] == 255, "{pixel:?} at {x},{y}");
+                inked += 1;
+            }
+        }
+        assert!(inked > 20 * scale * scale);
+    }
+
+    #[test]
+    fn shapes_are_drawn_at_their_image_position() {
+        check(1);
+    }
+
+    #[test]
+    fn hidpi_shapes_are_scaled() {
+        check(2);
+    }
+}
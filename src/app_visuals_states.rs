@@ -1,52 +1,67 @@
 use eframe::egui::{
-    self, panel::TopBottomSide, pos2, Button, CentralPanel, Frame, Key, Pos2, Rect, Sense,
-    TopBottomPanel, ViewportCommand,
+    self, emath::RectTransform, panel::TopBottomSide, pos2, Button, CentralPanel, Frame, Key,
+    Pos2, Rect, Sense, TopBottomPanel, Ui,
 };
-use eframe::epaint::{vec2, Color32, Rounding, Stroke};
-use screenshots::Screen;
-use std::ops::{Add, Div};
-use std::{thread, time};
+use eframe::epaint::{vec2, Color32, Rounding, Stroke, Vec2};
+use std::time::{Duration, Instant};
 
+use progetto_malnati::ipc;
+use progetto_malnati::monitors;
+use progetto_malnati::naming::{self, NameFields};
+use progetto_malnati::export::{PngCompression, PngFilter};
+use progetto_malnati::recording::{self, RecordingFormat};
+use progetto_malnati::ExportFormat;
+use rfd::FileDialog;
+use progetto_malnati::redaction::Method;
+use progetto_malnati::{annotations, Annotation, Capture, CaptureTarget, Edit, Redaction, Shape, Tool, Transform};
+use super::after_capture::{AfterCapture, CaptureMode};
+use super::application::paint_annotation;
+use progetto_malnati::keybidings::{Action, Scope};
+use super::config::Config;
 use super::AppState;
+use super::DelayedMode;
+use super::EditorTool;
 use super::MyApp;
 impl MyApp {
     pub fn main_state_visuals(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("buttons navbar").show(ctx, |ui| {
             //Organize buttons in horizontal navbar
             ui.horizontal(|ui| {
-                if ui.button("New capture now").clicked() {
-                    self.delay = 0;
-                    self.set_new_capture_window(ctx);
-                    self.state = AppState::NewCapture;
-                }
+                self.action_button(ui, ctx, "New capture now", Action::New);
+                self.action_button(ui, ctx, "Record", Action::Record);
                 ui.add_space(20.0);
 
-                if ui.button("New capture after:").clicked() {
-                    self.set_new_capture_window(ctx);
-                    if self.delay == 0 {
-                        self.state = AppState::NewCapture;
-                    } else {
-                        ctx.send_viewport_cmd(ViewportCommand::Visible(false));
-                        ctx.request_repaint();
-                        self.state = AppState::Selection;
-                    }
-                }
+                self.action_button(ui, ctx, "New capture after:", Action::Delayed);
                 ui.add(egui::Slider::new(&mut self.delay, 0..=60).text("seconds"));
+                egui::ComboBox::from_id_source("delayed mode")
+                    .selected_text(self.delayed_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in [DelayedMode::FullScreen, DelayedMode::Area] {
+                            ui.selectable_value(&mut self.delayed_mode, mode, mode.label());
+                        }
+                    });
+                ui.add_space(20.0);
+                self.action_button(ui, ctx, "Open…", Action::OpenFile);
+                self.action_button(ui, ctx, "Paste", Action::Paste);
 
-                ui.add_space(ui.available_size().x - 50.0);
-                if ui.button("Settings").clicked() {
-                    self.delay = 0;
-                    self.state = AppState::Settings;
-                }
+                ui.add_space(ui.available_size().x - 210.0);
+                self.action_button(ui, ctx, "Gallery", Action::Gallery);
+                self.action_button(ui, ctx, "History", Action::EditHistory);
+                self.action_button(ui, ctx, "Log", Action::Log);
+                self.action_button(ui, ctx, "Settings", Action::Settings);
             });
         });
+        self.tab_bar(ctx);
         CentralPanel::default().show(ctx, |ui| {
-            if let Some(texture) = self.texture.clone() {
+            let shown = self
+                .tab()
+                .and_then(|tab| Some((tab.texture.clone()?, tab.capture.region_uv(), tab.capture.region)));
+            if let Some((texture, uv, region)) = shown {
                 egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
                     //IMAGE RENDERING
-                    let uv = self.calculate_uv(ctx);
-                    let space = self.calculate_space(ctx, ui);
+                    let space = self.calculate_space(ctx, ui, region);
                     ui.painter().image(texture.id(), space, uv, Color32::WHITE);
+                    self.paint_annotations(ui, region, space);
 
                     //OPTIONS
                     egui::Window::new("options")
@@ -56,15 +71,12 @@ impl MyApp {
                         .title_bar(false)
                         .show(ctx, |ui| {
                             ui.vertical(|ui| {
-                                if ui.button("Save ").clicked() {
-                                    self.save_capture(ctx);
-                                }
-                                if ui.button("Crop ").clicked() {
-                                    self.handle_crop_request(ctx);
-                                }
-                                if ui.button("Copy ").clicked() {
-                                    self.copy_to_clipboard(ctx);
-                                }
+                                self.action_button(ui, ctx, "Save ", Action::Save);
+                                self.action_button(ui, ctx, "Save as…", Action::SaveAs);
+                                self.action_button(ui, ctx, "Crop ", Action::Crop);
+                                self.action_button(ui, ctx, "Copy ", Action::Clipboard);
+                                self.action_button(ui, ctx, "Pin ", Action::Pin);
+                                self.action_button(ui, ctx, "Annotate ", Action::Annotate);
                             });
                         });
                 });
@@ -73,6 +85,8 @@ impl MyApp {
     }
     pub fn newcapture_state_visuals(&mut self, ctx: &egui::Context) {
         let pointer: egui::PointerState = ctx.input(|i| i.pointer.clone());
+        // Window covers the capture target, pointer positions are relative to its corner
+        let origin = self.target_rect().min;
 
         // Very little opacity for this frame, only to show area that its possible to capture
         let semi_transparent_frame = Frame::none().fill(Color32::WHITE.gamma_multiply(0.1));
@@ -80,6 +94,7 @@ impl MyApp {
         CentralPanel::default()
             .frame(semi_transparent_frame)
             .show(ctx, |ui| {
+                self.paint_frozen_backdrop(ui);
                 // Make pointer into crosshair if its hovering the selection area, if its on window pointer stays classic
                 if ui.ui_contains_pointer() {
                     ctx.output_mut(|o| {
@@ -97,27 +112,58 @@ impl MyApp {
                 .show(ctx, |ui| {
                     //Organize buttons in horizontal line
                     ui.horizontal(|ui| {
-                        if ui.button("Full screen").clicked() {
-                            self.handle_fullscreen_capture(ctx);
-                        }
+                        self.action_button(ui, ctx, "Full screen", Action::Fullscreen);
 
-                        if ui.button("Area").clicked() {
-                            self.area = true;
+                        self.action_button(ui, ctx, "Area", Action::Area);
+
+                        // Monitor picker, overlay is moved on the chosen monitor
+                        let previous_target = self.capture_target;
+                        egui::ComboBox::from_id_source("capture target")
+                            .selected_text(monitors::target_label(&self.monitors, self.capture_target))
+                            .show_ui(ui, |ui| {
+                                for (index, monitor) in self.monitors.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut self.capture_target,
+                                        CaptureTarget::Monitor(index),
+                                        monitor.label(index),
+                                    );
+                                }
+                                if self.monitors.len() > 1 {
+                                    ui.selectable_value(
+                                        &mut self.capture_target,
+                                        CaptureTarget::AllScreens,
+                                        "All screens",
+                                    );
+                                }
+                            });
+                        if previous_target != self.capture_target {
+                            let result = self.set_new_capture_window(ctx);
+                            if self.report(result).is_some() && self.freeze_frame {
+                                // Backdrop belongs to the previous monitor
+                                self.begin_area_selection(ctx);
+                            }
                         }
                     });
                     // Selection if button has been pressed, must do it this way otherwise button click is recorded as first point of selection
-                    if pointer.primary_clicked() && !ui.ui_contains_pointer() {
-                        self.area = true;
-                        self.selected_area[0] = ctx.input(|i| i.pointer.interact_pos().unwrap());
-                        self.state = AppState::Selection;
+                    if let Some(pos) = pointer.interact_pos() {
+                        if pointer.primary_clicked()
+                            && !ui.ui_contains_pointer()
+                            && !ctx.is_pointer_over_area()
+                        {
+                            self.area = true;
+                            self.selected_area[0] = origin + pos.to_vec2();
+                            self.state = AppState::Selection;
+                        }
                     }
                 });
         }
         // Selection if button has been pressed
-        if pointer.primary_clicked() && self.area {
-            self.area = true;
-            self.selected_area[0] = ctx.input(|i| i.pointer.interact_pos().unwrap());
-            self.state = AppState::Selection;
+        if let Some(pos) = pointer.interact_pos() {
+            if pointer.primary_clicked() && self.area {
+                self.area = true;
+                self.selected_area[0] = origin + pos.to_vec2();
+                self.state = AppState::Selection;
+            }
         }
     }
     pub fn selection_state_visuals(&mut self, ctx: &egui::Context) {
@@ -128,12 +174,7 @@ impl MyApp {
         CentralPanel::default()
             .frame(transparent_frame)
             .show(ctx, |ui| {
-                if self.delay > 0 {
-                    thread::sleep(time::Duration::from_secs(self.delay));
-                    self.delay = 0;
-                    ctx.send_viewport_cmd(ViewportCommand::Visible(true));
-                    self.state = AppState::NewCapture;
-                }
+                self.paint_frozen_backdrop(ui);
                 //Make pointer into crosshair
                 if ui.ui_contains_pointer() {
                     ctx.output_mut(|o| {
@@ -142,118 +183,195 @@ impl MyApp {
                 }
                 //Check for pointer changes
                 let pointer = ctx.input(|i| i.pointer.clone());
-                if pointer.is_decidedly_dragging() && !self.capture {
-                    let pointer_pos = pointer.hover_pos().unwrap();
-                    let rect = egui::Rect::from_two_pos(
-                        self.selected_area[0],
-                        pointer.hover_pos().unwrap(),
-                    );
-                    ui.painter()
-                        .rect_stroke(rect, Rounding::ZERO, Stroke::new(1.0, Color32::RED));
+                let origin = self.target_rect().min;
+                let hover_pos = pointer.hover_pos();
+                if pointer.is_decidedly_dragging() && !self.capture_requested {
+                    if let Some(hover_pos) = hover_pos {
+                        let pointer_pos = origin + hover_pos.to_vec2();
+                        let rect = egui::Rect::from_two_pos(
+                            self.selected_area[0] - origin.to_vec2(),
+                            hover_pos,
+                        );
+                        ui.painter()
+                            .rect_stroke(rect, Rounding::ZERO, Stroke::new(1.0, Color32::RED));
 
-                    if pointer.primary_released() {
-                        if pointer_pos == self.selected_area[0] {
-                            self.state = AppState::NewCapture;
-                        } else {
-                            self.selected_area[1] = pointer_pos;
-                            self.capture = true;
+                        if pointer.primary_released() {
+                            if pointer_pos == self.selected_area[0] {
+                                self.state = AppState::NewCapture;
+                            } else if self.frozen.is_some() {
+                                self.selected_area[1] = pointer_pos;
+                                let selection = egui::Rect::from_two_pos(
+                                    self.selected_area[0],
+                                    self.selected_area[1],
+                                );
+                                self.finish_frozen_selection(ctx, selection);
+                            } else {
+                                self.selected_area[1] = pointer_pos;
+                                self.capture_requested = true;
+                            }
+                            ctx.request_repaint();
                         }
-                        ctx.request_repaint();
                     }
                 }
-                if self.capture && !ctx.has_requested_repaint() {
-                    let screens = Screen::all().unwrap();
-
-                    //Possible to change screen to capture
-                    let primary_screen = screens[0];
-                    self.image = Some(primary_screen.capture().unwrap());
-                    self.capture = false;
-
-                    let image = self.image.clone().unwrap();
-                    // Conversion of screnshoots crate img to egui renderable img
-                    let pixels: Vec<Color32> = image
-                        .pixels()
-                        .map(|pixel| {
-                            Color32::from_rgba_unmultiplied(
-                                pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3],
-                            )
-                        })
-                        .collect();
-
-                    let img = egui::ColorImage {
-                        pixels: pixels,
-                        size: [image.width() as usize, image.height() as usize],
-                    };
-                    //Store texture of screenshot in MainApp
-                    self.texture =
-                        Some(ui.ctx().load_texture("screenshot", img, Default::default()));
+                if self.capture_requested && !ctx.has_requested_repaint() {
+                    let selection =
+                        egui::Rect::from_two_pos(self.selected_area[0], self.selected_area[1]);
+                    if self.record_next_selection {
+                        self.capture_requested = false;
+                        self.start_screen_recording(ctx, selection);
+                        return;
+                    }
+                    let result = Capture::take(
+                        self.capture_source.as_ref(),
+                        &self.monitors,
+                        self.capture_target,
+                    );
+                    self.capture_requested = false;
+                    if let Some(capture) = self.report(result) {
+                        self.finish_capture(ctx, capture.with_region(selection));
+                    }
 
                     // Reset window
-                    ctx.send_viewport_cmd(ViewportCommand::Decorations(true));
-                    ctx.send_viewport_cmd(ViewportCommand::Maximized(true));
-                    ctx.send_viewport_cmd(ViewportCommand::Focus);
-                    ctx.send_viewport_cmd(ViewportCommand::WindowLevel(egui::WindowLevel::Normal));
+                    self.restore_main_window(ctx);
 
                     //Change state to Main state
                     self.state = AppState::MainApp;
                 }
             });
     }
-    pub fn crop_state_visuals(&mut self, ctx: &egui::Context, monitor_rect: Rect) {
+    //------Nothing is drawn while the backdrop of a freeze frame selection is captured
+    pub fn freezing_state_visuals(&mut self, ctx: &egui::Context) {
+        CentralPanel::default()
+            .frame(Frame::none().fill(Color32::TRANSPARENT))
+            .show(ctx, |_ui| {});
+        if !ctx.has_requested_repaint() {
+            self.freeze_screen(ctx);
+        }
+    }
+    //------Frozen screen drawn under the selection overlay
+    pub fn paint_frozen_backdrop(&self, ui: &egui::Ui) {
+        if let Some(texture) = self.frozen_texture.as_ref() {
+            let rect = Rect::from_min_size(Pos2::ZERO, self.target_rect().size());
+            let uv = Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0));
+            ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+        }
+    }
+    //------Small always on top indicator shown while waiting for a delayed capture
+    pub fn countdown_state_visuals(&mut self, ctx: &egui::Context) {
+        let Some(end) = self.countdown_end else {
+            self.state = AppState::MainApp;
+            return;
+        };
+        let remaining = end.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            self.finish_countdown(ctx);
+            return;
+        }
+        CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                // Requests from the control socket bring their own kind of capture
+                let kind = match self.remote_capture {
+                    Some(_) => "Requested",
+                    None => self.delayed_mode.label(),
+                };
+                ui.heading(format!(
+                    "{} capture in {} s",
+                    kind,
+                    remaining.as_secs() + 1
+                ));
+                if ui
+                    .button(match self.key_bindings.get(Scope::Window, Action::Cancel) {
+                        Some(shortcut) => format!("Cancel ({})", shortcut),
+                        None => "Cancel".to_string(),
+                    })
+                    .clicked()
+                {
+                    self.cancel_countdown(ctx);
+                }
+            });
+        });
+        // Wake up for the next second, or right when the timer ends
+        ctx.request_repaint_after(remaining.min(Duration::from_millis(200)));
+    }
+    pub fn crop_state_visuals(&mut self, ctx: &egui::Context) {
+        let Some(image_rect) = self.capture().map(|capture| capture.image_rect) else {
+            self.state = AppState::MainApp;
+            return;
+        };
         egui::TopBottomPanel::top("buttons navbar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                if ui.button("CONFIRM").clicked() {
-                    self.button_position.x =
-                        (self.button_position.x - self.display_rect.left_top().x) /
-                        self.shrink_factor;
-                    self.button_position.y =
-                        (self.button_position.y - self.display_rect.left_top().y) /
-                        self.shrink_factor;
-                    self.selected_area[0] = self.button_position;
-                    self.dimensions = self.dimensions.div(self.shrink_factor);
-                    self.selected_area[1] = self.selected_area[0].add(self.dimensions);
-                    self.state = AppState::MainApp;
-                }
+                self.action_button(ui, ctx, "CONFIRM", Action::Confirm);
 
                 ui.add_space(ui.available_size().x / 3.3);
                 ui.label(
-                    "Click and drag any side to resize the selection, click confirm to save new selection"
+                    "Drag a side or corner to resize (shift keeps proportions), drag inside or use the arrows to move"
                 );
                 ui.add_space(ui.available_size().x - 50.0);
-                if ui.button("CANCEL").clicked() {
-                    self.state = AppState::MainApp;
+                self.action_button(ui, ctx, "CANCEL", Action::Cancel);
+            });
+            // Exact values in pixels of the captured image
+            let selection = self.selection_pixels();
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} × {} at {},{}",
+                    selection.width().round(),
+                    selection.height().round(),
+                    selection.min.x.round(),
+                    selection.min.y.round()
+                ));
+                ui.separator();
+                let mut x = selection.min.x.round();
+                let mut y = selection.min.y.round();
+                let mut w = selection.width().round();
+                let mut h = selection.height().round();
+                let mut changed = false;
+                for (label, value) in [("X", &mut x), ("Y", &mut y), ("W", &mut w), ("H", &mut h)] {
+                    ui.label(label);
+                    changed |= ui
+                        .add(egui::DragValue::new(value).speed(1.0).clamp_range(0.0..=f32::MAX))
+                        .changed();
+                }
+                if changed {
+                    self.set_selection_pixels(Rect::from_min_size(pos2(x, y), vec2(w, h)));
                 }
             });
         });
+        if !ctx.wants_keyboard_input() {
+            self.nudge_selection(ctx);
+        }
 
         CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::both()
                 .auto_shrink(true)
                 .drag_to_scroll(true)
                 .show(ui, |ui| {
-                    if let Some(texture) = self.texture.as_ref() {
+                    let Some(tab) = self.tabs.get_mut(self.active_tab) else {
+                        return;
+                    };
+                    if let Some(texture) = tab.texture.as_ref() {
                         let uv = egui::Rect::from_two_pos(Pos2::ZERO, pos2(1.0, 1.0));
                         let avheight = ui.available_rect_before_wrap().shrink(60.0).height();
-                        let avwidth = avheight * monitor_rect.aspect_ratio();
+                        let avwidth = avheight * image_rect.aspect_ratio();
                         let rect = Rect::from_center_size(
                             ui.available_rect_before_wrap().center(),
                             vec2(avwidth, avheight),
                         );
 
-                        if self.crop {
-                            self.shrink_factor = avwidth / monitor_rect.width();
-                            let selected_area = Rect::from_center_size(Pos2::ZERO, self.dimensions);
-                            let new_w = self.dimensions.x * self.shrink_factor;
-                            let new_h = (self.dimensions.x * self.shrink_factor)
+                        let crop = &mut tab.crop;
+                        if crop.rescale {
+                            self.shrink_factor = avwidth / image_rect.width();
+                            let selected_area = Rect::from_center_size(Pos2::ZERO, crop.dimensions);
+                            let new_w = crop.dimensions.x * self.shrink_factor;
+                            let new_h = (crop.dimensions.x * self.shrink_factor)
                                 / selected_area.aspect_ratio();
-                            let new_x = self.button_position.x * self.shrink_factor;
-                            let new_y = self.button_position.y * self.shrink_factor;
+                            let new_x = (crop.button_position.x - image_rect.min.x) * self.shrink_factor;
+                            let new_y = (crop.button_position.y - image_rect.min.y) * self.shrink_factor;
 
                             self.min_pos_top = rect.left_top();
-                            self.button_position = rect.left_top() + vec2(new_x, new_y);
-                            self.dimensions = vec2(new_w, new_h);
+                            crop.button_position = rect.left_top() + vec2(new_x, new_y);
+                            crop.dimensions = vec2(new_w, new_h);
                             self.display_rect = rect;
-                            self.crop = false;
+                            crop.rescale = false;
                         }
 
                         ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
@@ -265,8 +383,10 @@ impl MyApp {
             .frame(Frame::none().fill(Color32::TRANSPARENT))
             .show(ctx, |ui| {
                 // Draw the button element
-                let pos = self.button_position.clone();
-                let dimensions = self.dimensions.clone();
+                let Some(crop) = self.tab().map(|tab| tab.crop) else {
+                    return;
+                };
+                let (pos, dimensions) = (crop.button_position, crop.dimensions);
                 MyApp::drag(self, ui, ui.id(), |ui| {
                     let rect: Rect = Rect::from_min_size(pos, dimensions);
                     ui.put(
@@ -278,124 +398,513 @@ impl MyApp {
                 });
             });
     }
-    pub fn settings_state_visuals(&mut self, ctx: &egui::Context) {
-        TopBottomPanel::new(TopBottomSide::Top, "go back").show(ctx, |ui| {
+    pub fn annotate_state_visuals(&mut self, ctx: &egui::Context) {
+        let Some((region, uv)) = self.capture().map(|c| (c.region, c.region_uv())) else {
+            self.state = AppState::MainApp;
+            return;
+        };
+        TopBottomPanel::top("annotation tools").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.heading("Settings");
-                ui.add_space(ui.available_size().x - 50.0);
-                if ui.button("Go back").clicked() {
-                    self.state = AppState::MainApp;
-                    ctx.request_repaint()
+                self.action_button(ui, ctx, "DONE", Action::Confirm);
+                ui.separator();
+                ui.selectable_value(&mut self.editor_tool, EditorTool::Select, "Select");
+                for tool in Tool::ALL {
+                    ui.selectable_value(&mut self.editor_tool, EditorTool::Draw(tool), tool.name());
+                }
+                ui.separator();
+                ui.label("Redact:");
+                for method in Method::ALL {
+                    ui.selectable_value(
+                        &mut self.editor_tool,
+                        EditorTool::Redact(method),
+                        method.name(),
+                    );
+                }
+                ui.separator();
+                let (can_undo, can_redo) = self
+                    .tab()
+                    .map_or((false, false), |tab| (tab.history.can_undo(), tab.history.can_redo()));
+                if ui.add_enabled(can_undo, Button::new("Undo")).clicked() {
+                    self.run_action(ctx, Action::Undo);
+                }
+                if ui.add_enabled(can_redo, Button::new("Redo")).clicked() {
+                    self.run_action(ctx, Action::Redo);
+                }
+                self.action_button(ui, ctx, "History", Action::EditHistory);
+                self.action_button(ui, ctx, "Clear", Action::Clear);
+                ui.separator();
+                for transform in Transform::ALL {
+                    if ui.button(transform.name()).clicked() {
+                        self.push_edit(ctx, Edit::Transform(transform));
+                    }
+                }
+            });
+            // Options of the chosen tool
+            ui.horizontal(|ui| match self.editor_tool {
+                EditorTool::Select => {
+                    ui.label("Click an annotation to select it, drag to move it");
+                    let selected = self.tab().is_some_and(|tab| tab.selected_annotation.is_some());
+                    if ui
+                        .add_enabled(selected, Button::new("Delete"))
+                        .clicked()
+                    {
+                        self.delete_selected_annotation(ctx);
+                    }
+                }
+                EditorTool::Draw(tool) => {
+                    let style = &mut self.annotation_style;
+                    ui.label("Stroke");
+                    ui.color_edit_button_srgba(&mut style.stroke_color);
+                    if tool == Tool::Text {
+                        ui.add(egui::Slider::new(&mut style.font_size, 8.0..=96.0).text("size"));
+                        ui.label("Text");
+                        ui.text_edit_singleline(&mut self.annotation_text);
+                        ui.label("click on the image to place it");
+                        return;
+                    }
+                    ui.add(egui::Slider::new(&mut style.stroke_width, 1.0..=20.0).text("width"));
+                    let mut filled = style.fill.is_some();
+                    if ui.checkbox(&mut filled, "Fill").changed() {
+                        style.fill = filled.then(|| style.stroke_color.gamma_multiply(0.5));
+                    }
+                    if let Some(fill) = style.fill.as_mut() {
+                        ui.color_edit_button_srgba(fill);
+                    }
+                }
+                EditorTool::Redact(Method::Pixelate) => {
+                    ui.add(
+                        egui::Slider::new(&mut self.redaction_style.block_size, 4..=64)
+                            .text("block size (px)"),
+                    );
+                }
+                EditorTool::Redact(Method::Blur) => {
+                    ui.add(
+                        egui::Slider::new(&mut self.redaction_style.blur_sigma, 2.0..=30.0)
+                            .text("blur strength"),
+                    );
+                }
+                EditorTool::Redact(Method::Fill) => {
+                    ui.label("Colour");
+                    egui::color_picker::color_edit_button_srgba(
+                        ui,
+                        &mut self.redaction_style.fill,
+                        egui::color_picker::Alpha::Opaque,
+                    );
                 }
             });
         });
+
         CentralPanel::default().show(ctx, |ui| {
-            ui.label(
-                "Modify keybiding by hovering on key with mouse and pressing new desidered key on keyboard, new choosen key must not be already assigned"
-            );
-            ui.horizontal(|ui| {
-                ui.label("New fullscreen capture: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.fullscreen)).hovered() {
-                    ui.input(|i| {
-                        for key in Key::ALL {
-                            if
-                                i.key_pressed(key.to_owned()) &&
-                                !self.key_bindings.is_key_assigned(key.to_owned())
-                            {
-                                self.key_bindings.fullscreen = key.to_owned();
-                            }
+            let space = self.calculate_space(ctx, ui, region);
+            if let Some(texture) = self.tab().and_then(|tab| tab.texture.as_ref()) {
+                ui.painter().image(texture.id(), space, uv, Color32::WHITE);
+            }
+            let to_capture = RectTransform::from_to(space, region);
+            let response = ui.interact(space, ui.id().with("annotation canvas"), Sense::click_and_drag());
+            if response.hovered() {
+                ctx.set_cursor_icon(match self.editor_tool {
+                    EditorTool::Draw(Tool::Text) => egui::CursorIcon::Text,
+                    _ => egui::CursorIcon::Crosshair,
+                });
+            }
+
+            let origin = ctx.input(|i| i.pointer.press_origin());
+            let pointer = response.interact_pointer_pos();
+            if let (Some(origin), Some(pointer)) = (origin, pointer) {
+                let origin = to_capture.transform_pos(space.clamp(origin));
+                let pointer = to_capture.transform_pos(space.clamp(pointer));
+                let editor_tool = self.editor_tool;
+                let started = response.drag_started() || response.clicked();
+                let drawing = match editor_tool {
+                    EditorTool::Draw(tool) if started => Some(Annotation {
+                        shape: Shape::start(tool, origin, &self.annotation_text),
+                        style: self.annotation_style,
+                    }),
+                    _ => None,
+                };
+                let redaction_style = self.redaction_style;
+                let Some(tab) = self.tab_mut() else {
+                    return;
+                };
+                if started {
+                    match editor_tool {
+                        EditorTool::Select => {
+                            let tolerance = 4.0 * to_capture.scale().x;
+                            tab.selected_annotation =
+                                annotations::hit_test(&tab.capture.annotations, origin, tolerance);
+                            tab.moving = tab.selected_annotation.map(|_| Vec2::ZERO);
                         }
-                    })
-                }
-            });
-            ui.horizontal(|ui| {
-                ui.label("New capture: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.new)).hovered() {
-                    ui.input(|i| {
-                        for key in Key::ALL {
-                            if
-                                i.key_pressed(key.to_owned()) &&
-                                !self.key_bindings.is_key_assigned(key.to_owned())
-                            {
-                                self.key_bindings.new = key.to_owned();
-                            }
+                        EditorTool::Draw(_) => tab.drawing = drawing,
+                        EditorTool::Redact(method) => {
+                            tab.redacting = Some(Redaction {
+                                area: Rect::from_min_max(origin, origin),
+                                method,
+                                style: redaction_style,
+                            });
                         }
-                    })
+                    }
                 }
-            });
-            ui.horizontal(|ui| {
-                ui.label("Save current capture: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.save)).hovered() {
-                    ui.input(|i| {
-                        for key in Key::ALL {
-                            if
-                                i.key_pressed(key.to_owned()) &&
-                                !self.key_bindings.is_key_assigned(key.to_owned())
-                            {
-                                self.key_bindings.save = key.to_owned();
-                            }
-                        }
-                    })
+                if let Some(drawing) = tab.drawing.as_mut() {
+                    drawing.shape.drag(origin, pointer);
                 }
-            });
-            ui.horizontal(|ui| {
-                ui.label("Cancel selection resizing: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.cancel)).hovered() {
-                    let input = ui.input(|i| i.clone());
-                    for key in Key::ALL {
-                        if
-                            input.key_pressed(key.to_owned()) &&
-                            !self.key_bindings.is_key_assigned(key.to_owned())
-                        {
-                            self.key_bindings.cancel = key.to_owned();
-                        }
+                if let Some(redacting) = tab.redacting.as_mut() {
+                    redacting.area = Rect::from_two_pos(origin, pointer);
+                }
+                if let Some(moving) = tab.moving.as_mut() {
+                    *moving = pointer - origin;
+                }
+            }
+            if response.drag_released() || response.clicked() {
+                let Some(tab) = self.tab_mut() else {
+                    return;
+                };
+                let drawing = tab.drawing.take().filter(|d| d.is_visible());
+                let redacting = tab
+                    .redacting
+                    .take()
+                    .filter(|r| r.area.width() >= 1.0 && r.area.height() >= 1.0);
+                let moved = tab.selected_annotation.zip(tab.moving.take());
+                if let Some(drawing) = drawing {
+                    self.push_edit(ctx, Edit::AddAnnotation(drawing));
+                }
+                if let Some(redacting) = redacting {
+                    self.push_edit(ctx, Edit::AddRedaction(redacting));
+                }
+                if let Some((index, offset)) = moved {
+                    if offset != Vec2::ZERO {
+                        self.push_edit(ctx, Edit::MoveAnnotation { index, offset });
                     }
                 }
-            });
+            }
+            if self.tab().is_some_and(|tab| tab.selected_annotation.is_some())
+                && !ctx.wants_keyboard_input()
+                && ctx.input(|i| i.key_pressed(Key::Delete))
+            {
+                self.delete_selected_annotation(ctx);
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("Resize selection: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.crop)).hovered() {
-                    ui.input(|i| {
-                        for key in Key::ALL {
-                            if
-                                i.key_pressed(key.to_owned()) &&
-                                !self.key_bindings.is_key_assigned(key.to_owned())
-                            {
-                                self.key_bindings.crop = key.to_owned();
-                            }
+            // Redaction being dragged, applied to the texture once released
+            if let Some(redacting) = self.tab().and_then(|tab| tab.redacting.as_ref()) {
+                let to_screen = RectTransform::from_to(region, space);
+                let area = to_screen.transform_rect(redacting.area);
+                ui.painter_at(space).rect(
+                    area,
+                    0.0,
+                    Color32::BLACK.gamma_multiply(0.4),
+                    Stroke::new(1.0, Color32::WHITE),
+                );
+            }
+            self.paint_annotations(ui, region, space);
+        });
+    }
+
+    //------ Annotations of the capture and the one being drawn, region is shown in space
+    fn paint_annotations(&self, ui: &Ui, region: Rect, space: Rect) {
+        let to_screen = RectTransform::from_to(region, space);
+        let painter = ui.painter_at(space);
+        let Some(tab) = self.tab() else {
+            return;
+        };
+        for (index, annotation) in tab.capture.annotations.iter().enumerate() {
+            if tab.selected_annotation != Some(index) {
+                paint_annotation(&painter, annotation, &to_screen);
+                continue;
+            }
+            // Selected one follows the pointer while it is moved
+            let mut annotation = annotation.clone();
+            annotation.translate(tab.moving.unwrap_or(Vec2::ZERO));
+            paint_annotation(&painter, &annotation, &to_screen);
+            painter.rect_stroke(
+                to_screen.transform_rect(annotation.bounds()).expand(2.0),
+                0.0,
+                Stroke::new(1.0, Color32::LIGHT_BLUE),
+            );
+        }
+        if let Some(drawing) = tab.drawing.as_ref() {
+            paint_annotation(&painter, drawing, &to_screen);
+        }
+    }
+
+    //------ List of the edits of the capture, clicking one brings the capture back to that step
+    pub fn edit_history_window(&mut self, ctx: &egui::Context) {
+        let Some(history) = self.tab().map(|tab| &tab.history) else {
+            return;
+        };
+        let mut open = self.show_edit_history;
+        let mut jump = None;
+        egui::Window::new("Edit history")
+            .open(&mut open)
+            .default_size([250.0, 300.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(history.can_undo(), Button::new("Undo")).clicked() {
+                        jump = Some(history.applied() - 1);
+                    }
+                    if ui.add_enabled(history.can_redo(), Button::new("Redo")).clicked() {
+                        jump = Some(history.applied() + 1);
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let applied = history.applied();
+                    if ui.selectable_label(applied == 0, "Original capture").clicked() {
+                        jump = Some(0);
+                    }
+                    for (index, edit) in history.edits().iter().enumerate() {
+                        let step = index + 1;
+                        // Undone edits are greyed out until they are redone or replaced
+                        let mut text = egui::RichText::new(edit.description());
+                        if step > applied {
+                            text = text.weak();
                         }
-                    })
-                }
+                        if ui.selectable_label(step == applied, text).clicked() {
+                            jump = Some(step);
+                        }
+                    }
+                });
             });
+        self.show_edit_history = open;
+        if let Some(step) = jump {
+            self.jump_to_edit(ctx, step);
+        }
+    }
 
+    pub fn settings_state_visuals(&mut self, ctx: &egui::Context) {
+        TopBottomPanel::new(TopBottomSide::Top, "go back").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.label("Copy image to clipboard: ");
-                ui.add_enabled(false, Button::new("Ctrl"));
-                ui.label("+");
-                if ui.button(format!("{:?}", self.key_bindings.clipboard)).hovered() {
-                    ui.input(|i| {
-                        for key in Key::ALL {
-                            if
-                                i.key_pressed(key.to_owned()) &&
-                                !self.key_bindings.is_key_assigned(key.to_owned())
-                            {
-                                self.key_bindings.clipboard = key.to_owned();
+                ui.heading("Settings");
+                ui.add_space(ui.available_size().x - 160.0);
+                if ui.button("Reset to defaults").clicked() {
+                    // Window stays where it is, only its level follows the setting
+                    self.apply_config(Config::default());
+                    self.apply_window_level(ctx);
+                    self.notifications.info("Settings reset to defaults");
+                }
+                if ui.button("Go back").clicked() {
+                    self.run_action(ctx, Action::Cancel);
+                    ctx.request_repaint()
+                }
+            });
+        });
+        CentralPanel::default().show(ctx, |ui| {
+            // Long list, it must fit small windows too
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if ui
+                    .checkbox(&mut self.always_on_top, "Keep the main window above the others")
+                    .changed()
+                {
+                    self.apply_window_level(ctx);
+                }
+                ui.checkbox(&mut self.daemon, "Run in the background with a tray icon")
+                    .on_hover_text("Takes effect on the next start, the window then only shows up to capture and edit");
+                ui.checkbox(&mut self.control_socket, "Accept capture requests from other programs")
+                    .on_hover_text(format!(
+                        "Takes effect on the next start, always on in the background. Socket: {}",
+                        ipc::socket_path().display()
+                    ));
+                ui.checkbox(
+                    &mut self.freeze_frame,
+                    "Freeze the screen before selecting an area (what you select is exactly what you get)",
+                );
+                ui.separator();
+                ui.heading("Saving");
+                ui.horizontal(|ui| {
+                    ui.label("Output folder:");
+                    ui.monospace(self.save_dir.display().to_string());
+                    if ui.button("Choose…").clicked() {
+                        if let Some(dir) = FileDialog::new().set_directory(&self.save_dir).pick_folder() {
+                            self.save_dir = dir;
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("File name:");
+                    ui.text_edit_singleline(&mut self.file_template);
+                    if ui.button("Default").clicked() {
+                        self.file_template = naming::DEFAULT_TEMPLATE.to_string();
+                    }
+                });
+                ui.label("Placeholders: {date} {time} {monitor} {w} {h} {counter} {ext}");
+                if let Some(capture) = self.capture() {
+                    let pixels_per_point = capture.image.width() as f32 / capture.image_rect.width();
+                    let fields = NameFields {
+                        taken_at: capture.metadata.taken_at,
+                        monitor: naming::source_name(&capture.metadata),
+                        // Size of the exported region, in pixels
+                        width: (capture.region.width() * pixels_per_point).round() as u32,
+                        height: (capture.region.height() * pixels_per_point).round() as u32,
+                        format: self.save_format,
+                    };
+                    ui.weak(format!(
+                        "e.g. {}",
+                        naming::expand_template(&self.file_template, &fields, 1)
+                    ));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    egui::ComboBox::from_id_source("save format")
+                        .selected_text(self.save_format.name())
+                        .show_ui(ui, |ui| {
+                            for format in ExportFormat::ALL {
+                                ui.selectable_value(&mut self.save_format, format, format.name());
+                            }
+                        });
+                });
+                ui.collapsing("Encoder options", |ui| {
+                    let options = &mut self.export_options;
+                    ui.horizontal(|ui| {
+                        ui.label("PNG compression:");
+                        egui::ComboBox::from_id_source("png compression")
+                            .selected_text(options.png_compression.name())
+                            .show_ui(ui, |ui| {
+                                for level in PngCompression::ALL {
+                                    ui.selectable_value(&mut options.png_compression, level, level.name());
+                                }
+                            });
+                        ui.label("filter:");
+                        egui::ComboBox::from_id_source("png filter")
+                            .selected_text(options.png_filter.name())
+                            .show_ui(ui, |ui| {
+                                for filter in PngFilter::ALL {
+                                    ui.selectable_value(&mut options.png_filter, filter, filter.name());
+                                }
+                            });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut options.jpeg_quality, 1..=100).text("JPG quality"));
+                        ui.label("background for transparency:");
+                        egui::color_picker::color_edit_button_srgba(
+                            ui,
+                            &mut options.jpeg_background,
+                            egui::color_picker::Alpha::Opaque,
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut options.gif_palette_size, 2..=256)
+                                .text("GIF colours"),
+                        );
+                        ui.checkbox(&mut options.gif_dithering, "dithering");
+                    });
+                });
+                ui.separator();
+                ui.heading("Gallery");
+                ui.checkbox(&mut self.gallery_options.redacted_only, "Store captures with the redactions applied")
+                    .on_hover_text("The hidden pixels never reach the disk, but the redactions of a reopened capture cannot be removed");
+                ui.horizontal(|ui| {
+                    ui.label("Keep at most");
+                    ui.add(egui::DragValue::new(&mut self.gallery_options.max_entries).speed(1.0));
+                    ui.label("captures, the oldest are deleted (0 keeps them all)");
+                });
+                ui.separator();
+                ui.heading("Screen recording");
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    egui::ComboBox::from_id_source("recording format")
+                        .selected_text(self.recording.format.name())
+                        .show_ui(ui, |ui| {
+                            for format in RecordingFormat::ALL {
+                                ui.selectable_value(&mut self.recording.format, format, format.name());
                             }
+                        });
+                    ui.add(egui::Slider::new(&mut self.recording.fps, 1..=recording::MAX_FPS).text("frames per second"));
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::Slider::new(&mut self.recording.max_duration, 5..=600)
+                            .text("seconds at most"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.recording.memory_limit, 64..=4096)
+                            .logarithmic(true)
+                            .text("MB in memory"),
+                    )
+                    .on_hover_text("Frames beyond it are written to a temporary directory");
+                });
+                ui.separator();
+                ui.heading("After capture");
+                egui::Grid::new("after capture").num_columns(4).show(ui, |ui| {
+                    ui.label("");
+                    for mode in CaptureMode::ALL {
+                        ui.strong(mode.label());
+                    }
+                    ui.end_row();
+                    for (index, label) in AfterCapture::SWITCHES.iter().enumerate() {
+                        ui.label(*label);
+                        for mode in CaptureMode::ALL {
+                            ui.checkbox(self.after_capture.get_mut(mode).switch_mut(index), "");
                         }
-                    })
+                        ui.end_row();
+                    }
+                    ui.label("Run a command").on_hover_text(
+                        "Run by the shell with the image path as $1 and in $CAPTURE_PATH, a temporary file when the capture is not saved",
+                    );
+                    for mode in CaptureMode::ALL {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.after_capture.get_mut(mode).command)
+                                .hint_text("none")
+                                .desired_width(140.0),
+                        );
+                    }
+                    ui.end_row();
+                });
+                ui.separator();
+                ui.label(
+                    "Click a shortcut and press the new combination, \"Chord\" takes two in a row. A combination already used by another action is refused. Every action is also in the command palette"
+                );
+                egui::Grid::new("key bindings")
+                    .num_columns(4)
+                    .show(ui, |ui| self.shortcut_rows(ui, Scope::Window));
+                ui.separator();
+                ui.checkbox(
+                    &mut self.global_hotkeys,
+                    "Global shortcuts, they work while another application has focus (X11)",
+                )
+                // egui does not report Super outside macOS, so it cannot be recorded here
+                .on_hover_text("Shortcuts with Super can be written in the settings file");
+                ui.add_enabled_ui(self.global_hotkeys, |ui| {
+                    egui::Grid::new("global hotkeys")
+                        .num_columns(3)
+                        .show(ui, |ui| self.shortcut_rows(ui, Scope::Global));
+                });
+                if self.recording_shortcut.is_some() && ui.button("Stop recording").clicked() {
+                    self.recording_shortcut = None;
+                    self.recorded_first = None;
                 }
             });
         });
     }
+
+    // Shortcut, chord and clear buttons of every action of the scope, global ones cannot be chords
+    fn shortcut_rows(&mut self, ui: &mut egui::Ui, scope: Scope) {
+        for action in scope.actions() {
+            let action = *action;
+            ui.label(action.name());
+            let recording = self
+                .recording_shortcut
+                .filter(|(s, a, _)| *s == scope && *a == action);
+            let text = match (recording, self.recorded_first) {
+                (Some(_), Some(first)) => format!("{} …", first),
+                (Some(_), None) => "press keys…".to_string(),
+                (None, _) => match self.key_bindings.get(scope, action) {
+                    Some(shortcut) => shortcut.to_string(),
+                    None => "none".to_string(),
+                },
+            };
+            if ui.button(text).clicked() {
+                self.start_recording(scope, action, false);
+            }
+            if scope == Scope::Window && ui.button("Chord").clicked() {
+                self.start_recording(scope, action, true);
+            }
+            // Buttons and the command palette still run it
+            if ui
+                .add_enabled(self.key_bindings.get(scope, action).is_some(), Button::new("Clear"))
+                .clicked()
+            {
+                self.key_bindings.unbind(scope, action);
+            }
+            ui.end_row();
+        }
+    }
 }
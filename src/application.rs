@@ -1,36 +1,162 @@
 use super::MyApp;
 use eframe::egui::{
-    self, Event, Id, Pos2, Rect, Sense, Ui,
+    self, emath::RectTransform, CursorIcon, Event, Id, Key, Pos2, Rect, Sense, Ui,
     Vec2, ViewportCommand
 };
 use eframe::epaint::{ Color32,  Stroke};
-use image::{codecs::gif::GifEncoder, imageops};
 use rfd::FileDialog;
-use std::borrow::Cow;
-use std::fs::OpenOptions;
 use std::ops::Add;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::ops::Div;
+use std::time::{Duration, Instant};
 
-use super::TouchedFrame;
+use image::imageops::{self, FilterType};
+use progetto_malnati::export;
+use progetto_malnati::hotkeys::HotkeyListener;
+use progetto_malnati::ipc::Reply;
+use progetto_malnati::keybidings::{Action, KeyCombo, KeyMatch, ModifierSet, Scope, Shortcut};
+use progetto_malnati::naming::{self, NameFields};
+use progetto_malnati::temp;
+use progetto_malnati::tray::TrayCommand;
+use progetto_malnati::monitors::{self, CaptureTarget};
+use progetto_malnati::{Annotation, Capture, Edit, Error, Shape, ExportFormat, Result};
+use super::after_capture::CaptureMode;
+use super::config::{self, CaptureConfig, Config, GlobalHotkeysConfig, SaveConfig, WindowConfig};
 use super::AppState;
+use super::DelayedMode;
+use super::TouchedFrame;
+
+// Longest pause between the two combinations of a chord
+const CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
 
 impl MyApp {
-    pub fn copy_to_clipboard(&self, ctx: &egui::Context) {
-        if let Some(_texture) = self.texture.clone() {
-            let img: image::ImageBuffer<image::Rgba<u8>, Vec<u8>> = self.crop_image(ctx);
-            let a = img.clone().into_raw();
-            let img_to_save = arboard::ImageData {
-                width: img.width() as usize,
-                height: img.height() as usize,
-                bytes: Cow::from(a),
-            };
-            let mut clipboard = arboard::Clipboard::new().unwrap();
-            clipboard.set_image(img_to_save).unwrap();
+    pub fn copy_to_clipboard(&self) -> Result<()> {
+        self.capture()
+            .ok_or(Error::NothingCaptured)?
+            .copy_to_clipboard()
+    }
+    pub fn handle_copy_request(&mut self) {
+        let result = self.copy_to_clipboard();
+        if self.report(result).is_some() {
+            self.notifications.info("Capture copied to clipboard");
+        }
+    }
+    //------Applies an edit to the capture of the active tab and records it so it can be undone
+    pub fn push_edit(&mut self, ctx: &egui::Context, edit: Edit) {
+        let Some(tab) = self.tab_mut() else {
+            return;
+        };
+        let pixels_changed = matches!(
+            edit,
+            Edit::AddRedaction(_) | Edit::Clear { .. } | Edit::Transform(_)
+        );
+        tab.history.push(&mut tab.capture, edit);
+        tab.gallery_dirty = true;
+        tab.saved = false;
+        if pixels_changed {
+            tab.refresh_texture(ctx);
+        }
+    }
+    pub fn undo(&mut self, ctx: &egui::Context) {
+        if let Some(tab) = self.tab_mut() {
+            if tab.history.undo(&mut tab.capture) {
+                tab.after_history_change(ctx);
+            }
+        }
+    }
+    pub fn redo(&mut self, ctx: &egui::Context) {
+        if let Some(tab) = self.tab_mut() {
+            if tab.history.redo(&mut tab.capture) {
+                tab.after_history_change(ctx);
+            }
+        }
+    }
+    //------Goes back (or forward) to the capture as it was after `step` edits
+    pub fn jump_to_edit(&mut self, ctx: &egui::Context, step: usize) {
+        if let Some(tab) = self.tab_mut() {
+            tab.history.jump_to(&mut tab.capture, step);
+            tab.after_history_change(ctx);
+        }
+    }
+    pub fn delete_selected_annotation(&mut self, ctx: &egui::Context) {
+        let Some(tab) = self.tab_mut() else {
+            return;
+        };
+        let Some(index) = tab.selected_annotation.take() else {
+            return;
+        };
+        if let Some(annotation) = tab.capture.annotations.get(index).cloned() {
+            self.push_edit(ctx, Edit::RemoveAnnotation { index, annotation });
+        }
+    }
+    // Steps of a delayed capture apply whatever is then captured
+    pub fn set_capture_mode(&mut self, mode: CaptureMode) {
+        if self.capture_mode != CaptureMode::Delayed {
+            self.capture_mode = mode;
+        }
+    }
+    //------Area selection, on a still copy of the screen when freeze frame is enabled
+    pub fn begin_area_selection(&mut self, ctx: &egui::Context) {
+        self.set_capture_mode(CaptureMode::Area);
+        self.frozen = None;
+        self.frozen_texture = None;
+        if self.freeze_frame {
+            self.state = AppState::Freezing;
+            //Request repaint in order to wait until window is transparent
+            ctx.request_repaint();
+        } else {
+            self.state = AppState::NewCapture;
+        }
+    }
+    //------Captures the backdrop the area is then selected on
+    pub fn freeze_screen(&mut self, ctx: &egui::Context) {
+        let result = Capture::take(
+            self.capture_source.as_ref(),
+            &self.monitors,
+            self.capture_target,
+        );
+        match self.report(result) {
+            Some(frozen) => {
+                self.frozen_texture = Some(load_texture(ctx, "frozen screen", &frozen.image));
+                self.frozen = Some(frozen);
+                self.state = AppState::NewCapture;
+            }
+            None => {
+                self.restore_main_window(ctx);
+                self.state = AppState::MainApp;
+            }
+        }
+    }
+    //------Selection done on the frozen backdrop, no need to capture again
+    pub fn finish_frozen_selection(&mut self, ctx: &egui::Context, selection: Rect) {
+        if self.record_next_selection {
+            self.start_screen_recording(ctx, selection);
+            return;
+        }
+        if let Some(frozen) = self.frozen.take() {
+            self.frozen_texture = None;
+            self.finish_capture(ctx, frozen.with_region(selection));
+        }
+        self.restore_main_window(ctx);
+        self.state = AppState::MainApp;
+    }
+    //------Shows the error of a failed operation to the user instead of stopping the app
+    pub fn report<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.notifications.error(e.to_string());
+                None
+            }
         }
     }
     //------ Calculates dimensions, center of rectangle where image is going to rendered
-    pub fn calculate_space(&self, ctx: &egui::Context, ui: &mut Ui) -> Rect {
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-        let selection = egui::Rect::from_two_pos(self.selected_area[0], self.selected_area[1]);
+    pub fn calculate_space(&self, ctx: &egui::Context, ui: &mut Ui, selection: Rect) -> Rect {
+        let monitor_size: Vec2 = ctx
+            .input(|i| i.viewport().monitor_size)
+            .unwrap_or(ctx.screen_rect().size());
         let center = Pos2::new(monitor_size.x / 2.0, monitor_size.y / 2.0);
         let mut space = egui::Rect::from_center_size(center, selection.size());
 
@@ -51,299 +177,860 @@ impl MyApp {
                 space.set_center(Pos2::new(space.center().x, center.y));
             }
         }
-        return space;
+        space
     }
 
-    //-----Calculates area of image to render, aka part of image selected by user
-    pub fn calculate_uv(&self, ctx: &egui::Context) -> Rect {
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-
-        let selection = egui::Rect::from_two_pos(self.selected_area[0], self.selected_area[1]);
-
-        let uv = egui::Rect::from_two_pos(
-            Pos2::new(
-                selection.left_top().x / monitor_size.x,
-                selection.left_top().y / monitor_size.y,
-            ),
-            Pos2::new(
-                selection.right_bottom().x / monitor_size.x,
-                selection.right_bottom().y / monitor_size.y,
-            ),
+    //------Virtual-desktop area the overlay has to cover for the chosen capture target
+    pub fn target_rect(&self) -> Rect {
+        monitors::target_rect(&self.monitors, self.capture_target)
+    }
+    //------Monitors may have been plugged or moved since the last capture
+    pub fn refresh_monitors(&mut self) -> Result<()> {
+        self.monitors = self.capture_source.monitors()?;
+        if self.monitors.is_empty() {
+            return Err(Error::NoMonitor);
+        }
+        if let CaptureTarget::Monitor(index) = self.capture_target {
+            if index >= self.monitors.len() {
+                self.capture_target = CaptureTarget::default();
+            }
+        }
+        Ok(())
+    }
+    //------Puts the window back to normal after capture or cancel
+    pub fn restore_main_window(&mut self, ctx: &egui::Context) {
+        // A clipboard capture or a recording that failed or was cancelled
+        self.copy_next_capture = false;
+        self.record_next_selection = false;
+        self.capture_mode = CaptureMode::default();
+        self.answer_remote_capture(Reply::failure("the capture was cancelled or failed"));
+        match (self.reveal_editor.take(), self.tray.is_some()) {
+            (Some(true), _) | (None, false) => self.show_main_window(ctx),
+            // In the tray the window only stays to edit a new capture
+            (Some(false), true) | (None, true) => {
+                ctx.send_viewport_cmd(ViewportCommand::Visible(false))
+            }
+            // Out of the way, the capture is still there when the window is brought back
+            (Some(false), false) => {
+                self.show_main_window(ctx);
+                ctx.send_viewport_cmd(ViewportCommand::Minimized(true));
+            }
+        }
+    }
+    pub fn show_main_window(&self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(ViewportCommand::Decorations(true));
+        ctx.send_viewport_cmd(ViewportCommand::Maximized(true));
+        ctx.send_viewport_cmd(ViewportCommand::Focus);
+        self.apply_window_level(ctx);
+    }
+    pub fn apply_window_level(&self, ctx: &egui::Context) {
+        let level = if self.always_on_top {
+            egui::WindowLevel::AlwaysOnTop
+        } else {
+            egui::WindowLevel::Normal
+        };
+        ctx.send_viewport_cmd(ViewportCommand::WindowLevel(level));
+    }
+    //------Current settings, as they are written to the settings file
+    pub fn config(&self) -> Config {
+        Config {
+            version: config::CONFIG_VERSION,
+            key_bindings: self.key_bindings.clone(),
+            global_hotkeys: GlobalHotkeysConfig {
+                enabled: self.global_hotkeys,
+            },
+            capture: CaptureConfig {
+                delay: self.delay,
+                delayed_mode: self.delayed_mode,
+                freeze_frame: self.freeze_frame,
+            },
+            save: SaveConfig {
+                dir: self.save_dir.clone(),
+                file_template: self.file_template.clone(),
+                format: self.save_format,
+                last_dir: self.last_save_dir.clone(),
+            },
+            export: self.export_options,
+            after_capture: self.after_capture.clone(),
+            recording: self.recording.clone(),
+            gallery: self.gallery_options,
+            window: WindowConfig {
+                always_on_top: self.always_on_top,
+                daemon: self.daemon,
+                control_socket: self.control_socket,
+            },
+        }
+    }
+    pub fn apply_config(&mut self, config: Config) {
+        self.key_bindings = config.key_bindings.clone();
+        self.global_hotkeys = config.global_hotkeys.enabled;
+        self.delay = config.capture.delay;
+        self.delayed_mode = config.capture.delayed_mode;
+        self.freeze_frame = config.capture.freeze_frame;
+        self.save_dir = config.save.dir.clone();
+        self.file_template = config.save.file_template.clone();
+        self.save_format = config.save.format;
+        self.after_capture = config.after_capture.clone();
+        self.recording = config.recording.clone();
+        self.gallery_options = config.gallery;
+        self.last_save_dir = config.save.last_dir.clone();
+        self.export_options = config.export;
+        self.always_on_top = config.window.always_on_top;
+        self.daemon = config.window.daemon;
+        self.control_socket = config.window.control_socket;
+    }
+    //------Writes the settings file when something changed since the last write
+    pub fn persist_config(&mut self) {
+        let config = self.config();
+        if config == self.saved_config {
+            return;
+        }
+        // Remembered even on failure, the error is shown once and not at every frame
+        let result = config::save(&config);
+        self.saved_config = config;
+        self.report(result);
+    }
+    //------Starts the timer of a delayed capture, the window shrinks to a small indicator
+    pub fn start_countdown(&mut self, ctx: &egui::Context, delay: u64) -> Result<()> {
+        self.refresh_monitors()?;
+        let target = self.target_rect();
+        let size = Vec2::new(220.0, 70.0);
+        self.show_indicator_window(
+            ctx,
+            Rect::from_min_size(target.right_top() + Vec2::new(-size.x - 20.0, 20.0), size),
         );
-        return uv;
+        self.countdown_end = Some(Instant::now() + Duration::from_secs(delay));
+        self.state = AppState::Countdown;
+        Ok(())
+    }
+    //------Shrinks the window to a small always on top panel covering rect
+    pub fn show_indicator_window(&self, ctx: &egui::Context, rect: Rect) {
+        ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(ViewportCommand::Decorations(false));
+        ctx.send_viewport_cmd(ViewportCommand::Maximized(false));
+        ctx.send_viewport_cmd(ViewportCommand::InnerSize(rect.size()));
+        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(rect.min));
+        ctx.send_viewport_cmd(ViewportCommand::WindowLevel(
+            egui::WindowLevel::AlwaysOnTop,
+        ));
+    }
+    pub fn cancel_countdown(&mut self, ctx: &egui::Context) {
+        self.countdown_end = None;
+        self.restore_main_window(ctx);
+        self.state = AppState::MainApp;
+    }
+    //------Timer expired, continue as an immediate capture of the chosen kind
+    pub fn finish_countdown(&mut self, ctx: &egui::Context) {
+        self.countdown_end = None;
+        self.capture_mode = CaptureMode::Delayed;
+        if self.remote_capture.is_some() {
+            self.run_remote_capture(ctx);
+            return;
+        }
+        let result = self.set_new_capture_window(ctx);
+        if self.report(result).is_none() {
+            self.restore_main_window(ctx);
+            self.state = AppState::MainApp;
+            return;
+        }
+        match self.delayed_mode {
+            DelayedMode::FullScreen => self.handle_fullscreen_capture(ctx),
+            DelayedMode::Area => {
+                self.area = true;
+                self.begin_area_selection(ctx);
+            }
+        }
     }
     //------Sets the window to optimal configuration for screen capture
-    pub fn set_new_capture_window(&mut self, ctx: &egui::Context) {
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(Pos2::ZERO));
+    pub fn set_new_capture_window(&mut self, ctx: &egui::Context) -> Result<()> {
+        self.refresh_monitors()?;
+        let target = self.target_rect();
+        ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(target.min));
         ctx.send_viewport_cmd(ViewportCommand::Decorations(false));
         ctx.send_viewport_cmd(ViewportCommand::InnerSize(
-            monitor_size.add(Vec2::new(1.0, 1.0)),
+            target.size().add(Vec2::new(1.0, 1.0)),
         ));
         /*         ctx.send_viewport_cmd(ViewportCommand::WindowLevel(
             egui::WindowLevel::AlwaysOnTop,
         )); */
         ctx.send_viewport_cmd(ViewportCommand::Focus);
+        Ok(())
     }
     //------Checks if any shortcut has been pressed
     pub fn check_shortcut_press(&mut self, ctx: &egui::Context) {
         let input = ctx.input(|i| i.clone());
-        input
-            .events
-            .iter()
-            .for_each(|event| match event.to_owned() {
-                Event::Key {
-                    key,
-                    physical_key: _,
-                    pressed,
-                    repeat,
-                    modifiers,
-                } => {
-                    if key == self.key_bindings.save && modifiers.ctrl && !repeat && pressed {
-                        self.save_capture(ctx);
-                    } else if key == self.key_bindings.cancel
-                        && modifiers.ctrl
-                        && !repeat
-                        && pressed
-                        && matches!(self.state, AppState::Crop)
-                    {
-                        self.delay = 0;
-                        self.state = AppState::MainApp;
-                    } else if key == self.key_bindings.fullscreen
-                        && modifiers.ctrl
-                        && !repeat
-                        && pressed
-                        && matches!(self.state, AppState::MainApp)
-                    {
-                        self.delay = 0;
-                        self.set_new_capture_window(ctx);
-                        self.handle_fullscreen_capture(ctx);
-                    } else if key == self.key_bindings.new
-                        && modifiers.ctrl
-                        && !repeat
-                        && pressed
-                        && matches!(self.state, AppState::MainApp)
-                    {
-                        self.delay = 0;
-                        self.set_new_capture_window(ctx);
-                        self.area = true;
-                        self.state = AppState::NewCapture;
-                    } else if key == self.key_bindings.crop
-                        && modifiers.ctrl
-                        && !repeat
-                        && matches!(self.state, AppState::MainApp)
-                    {
-                        self.delay = 0;
-                        self.handle_crop_request(ctx);
-                    } else if key == self.key_bindings.clipboard
-                        && modifiers.ctrl
-                        && !repeat
-                        && matches!(self.state, AppState::MainApp)
-                    {
-                        self.delay = 0;
-                        self.copy_to_clipboard(ctx);
-                    }
+        // The settings page is recording a new shortcut, keys are not actions
+        if self.recording_shortcut.is_some() {
+            self.record_shortcut(&input.events, input.modifiers);
+            return;
+        }
+        // The palette takes the keys while it is open
+        if self.command_palette.is_some() {
+            return;
+        }
+        // Second half of a chord must follow quickly
+        if self
+            .pending_chord
+            .is_some_and(|(_, at)| at.elapsed() > CHORD_TIMEOUT)
+        {
+            self.pending_chord = None;
+        }
+        for event in &input.events {
+            let Some(combo) = pressed_combo(event, input.modifiers) else {
+                continue;
+            };
+            let pending = self.pending_chord.take().map(|(first, _)| first);
+            match self.key_bindings.resolve(pending, combo) {
+                KeyMatch::Action(action) => self.run_shortcut(ctx, action),
+                KeyMatch::Pending(first) => self.pending_chord = Some((first, Instant::now())),
+                KeyMatch::None => {}
+            }
+        }
+    }
+    //------(Re)grabs the global shortcuts when they changed, released while one is being recorded
+    pub fn refresh_hotkeys(&mut self, ctx: &egui::Context) {
+        let wanted = (self.recording_shortcut.is_none() && self.global_hotkeys)
+            .then(|| self.key_bindings.scope(Scope::Global).collect::<Vec<_>>());
+        if wanted == self.grabbed_bindings {
+            return;
+        }
+        // Old grabs must be released before the same keys are grabbed again
+        self.hotkeys = None;
+        self.grabbed_bindings = wanted.clone();
+        let Some(bindings) = wanted else {
+            return;
+        };
+        let repaint = ctx.clone();
+        let result = HotkeyListener::start(&bindings, move || repaint.request_repaint());
+        if let Some((listener, failures)) = self.report(result) {
+            self.hotkeys = Some(listener);
+            for failure in failures {
+                self.notifications.error(format!(
+                    "Global shortcut {} for \"{}\" not set: {}",
+                    failure.shortcut,
+                    failure.action.name(),
+                    failure.reason
+                ));
+            }
+        }
+    }
+    //------Actions of global shortcuts pressed while another application had focus
+    pub fn check_hotkey_press(&mut self, ctx: &egui::Context) {
+        while let Some(action) = self.hotkeys.as_ref().and_then(|h| h.try_recv()) {
+            self.run_action(ctx, action);
+        }
+    }
+    //------Entries picked in the tray menu
+    pub fn check_tray_commands(&mut self, ctx: &egui::Context) {
+        while let Some(command) = self.tray.as_ref().and_then(|t| t.try_recv()) {
+            match command {
+                TrayCommand::Run(action) => self.run_action(ctx, action),
+                // Not in the middle of a capture, the overlay would become a normal window
+                TrayCommand::Show if self.state == AppState::MainApp => self.show_main_window(ctx),
+                TrayCommand::Show => {}
+                TrayCommand::Quit => {
+                    self.quitting = true;
+                    ctx.send_viewport_cmd(ViewportCommand::Close);
                 }
-                _ => {}
-            });
+            }
+        }
+    }
+    //------Closing the window only hides it while the tray icon is there
+    pub fn hide_on_close(&mut self, ctx: &egui::Context) {
+        if self.tray.is_none() || self.quitting {
+            return;
+        }
+        if ctx.input(|i| i.viewport().close_requested()) {
+            ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(ViewportCommand::Visible(false));
+        }
+    }
+    //------Leaves the overlay without capturing anything
+    pub fn abort_selection(&mut self, ctx: &egui::Context) {
+        self.area = false;
+        self.capture_requested = false;
+        self.frozen = None;
+        self.frozen_texture = None;
+        self.restore_main_window(ctx);
+        self.state = AppState::MainApp;
+    }
+    //------Back from the annotation editor, a shape still being drawn is dropped
+    pub fn finish_annotating(&mut self) {
+        if let Some(tab) = self.tab_mut() {
+            tab.drawing = None;
+            tab.redacting = None;
+        }
+        self.state = AppState::MainApp;
+    }
+    //------Removes every annotation and redaction, as one undoable edit
+    pub fn clear_edits(&mut self, ctx: &egui::Context) {
+        let Some(tab) = self.tab_mut() else {
+            return;
+        };
+        let capture = &tab.capture;
+        if capture.annotations.is_empty() && capture.redactions.is_empty() {
+            return;
+        }
+        let edit = Edit::Clear {
+            annotations: capture.annotations.clone(),
+            redactions: capture.redactions.clone(),
+        };
+        tab.selected_annotation = None;
+        self.push_edit(ctx, edit);
+    }
+    pub fn leave_settings(&mut self) {
+        self.recording_shortcut = None;
+        self.recorded_first = None;
+        self.state = AppState::MainApp;
+    }
+    //------Turns the edited selection back into image coordinates and crops
+    pub fn confirm_crop(&mut self, ctx: &egui::Context) {
+        let (display_rect, shrink_factor) = (self.display_rect, self.shrink_factor);
+        let Some(tab) = self.tab_mut() else {
+            self.state = AppState::MainApp;
+            return;
+        };
+        let (before, image_rect) = (tab.capture.region, tab.capture.image_rect);
+        let crop = &mut tab.crop;
+        crop.button_position.x =
+            (crop.button_position.x - display_rect.left_top().x) / shrink_factor;
+        crop.button_position.y =
+            (crop.button_position.y - display_rect.left_top().y) / shrink_factor;
+        crop.dimensions = crop.dimensions.div(shrink_factor);
+        let after = Rect::from_min_size(
+            crop.button_position + image_rect.min.to_vec2(),
+            crop.dimensions,
+        );
+        self.push_edit(ctx, Edit::Crop { before, after });
+        self.state = AppState::MainApp;
+    }
+    //------Starts listening for the new shortcut of action, two combinations for a chord
+    pub fn start_recording(&mut self, scope: Scope, action: Action, chord: bool) {
+        self.recording_shortcut = Some((scope, action, chord));
+        self.recorded_first = None;
+    }
+    // Takes the pressed combinations, the binding changes only when no other action owns it
+    fn record_shortcut(&mut self, events: &[Event], modifiers: egui::Modifiers) {
+        for event in events {
+            let Some((scope, action, chord)) = self.recording_shortcut else {
+                return;
+            };
+            let Some(combo) = pressed_combo(event, modifiers) else {
+                continue;
+            };
+            let shortcut = match (chord, self.recorded_first) {
+                (true, None) => {
+                    self.recorded_first = Some(combo);
+                    continue;
+                }
+                (true, Some(first)) => Shortcut::chord(first, combo),
+                (false, _) => Shortcut {
+                    first: combo,
+                    then: None,
+                },
+            };
+            self.recording_shortcut = None;
+            self.recorded_first = None;
+            if let Err(e) = self.key_bindings.assign(scope, action, shortcut) {
+                self.notifications.error(format!("Cannot bind {}: {}", shortcut, e));
+            }
+        }
     }
     //--------
     pub fn handle_crop_request(&mut self, _ctx: &egui::Context) {
-        if let Some(_texture) = self.texture.clone() {
-            let a = egui::Rect::from_two_pos(self.selected_area[0], self.selected_area[1]);
-            self.button_position = a.min;
-            self.crop = true;
-            self.dimensions = egui::vec2(a.width(), a.height());
+        if let Some(tab) = self.tab_mut() {
+            let a = tab.capture.region;
+            tab.crop.button_position = a.min;
+            tab.crop.rescale = true;
+            tab.crop.dimensions = egui::vec2(a.width(), a.height());
             self.state = AppState::Crop;
         }
     }
     pub fn handle_fullscreen_capture(&mut self, ctx: &egui::Context) {
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-        let monitor_rect = Rect::from_min_size(Pos2::ZERO, monitor_size);
+        self.set_capture_mode(CaptureMode::FullScreen);
+        let target = self.target_rect();
+        if self.frozen.is_some() {
+            self.finish_frozen_selection(ctx, target);
+            return;
+        }
         // Store full screen selection
-        self.selected_area[0] = Pos2::ZERO;
-        self.selected_area[1] = monitor_rect.right_bottom();
+        self.selected_area[0] = target.left_top();
+        self.selected_area[1] = target.right_bottom();
         //Go to Selection state
         self.state = AppState::Selection;
 
         // This makes it skip selection of second point in selection state of app
-        self.capture = true;
+        self.capture_requested = true;
 
         //Request repaint in order to wait until window is transparent
         ctx.request_repaint();
     }
-    fn crop_image(&self, ctx: &egui::Context) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-        let monitor_rect = Rect::from_min_size(Pos2::ZERO, monitor_size);
-        let selection = egui::Rect::from_two_pos(self.selected_area[0], self.selected_area[1]);
-        let mut img = self.image.clone().unwrap();
-        let shrink = monitor_rect.width() / (img.width() as f32);
-        let top_left_x = (selection.left_top().x - Pos2::ZERO.x) / shrink;
-        let top_left_y = (selection.left_top().y - Pos2::ZERO.y) / shrink;
-        let width = selection.width() / shrink;
-        let height = selection.height() / shrink;
-
-        let img_crop = imageops::crop(
-            &mut img,
-            top_left_x as u32,
-            top_left_y as u32,
-            width as u32,
-            height as u32,
-        );
+    //------Saves the capture in the output folder, named after the template
+    pub fn save_capture(&mut self) -> Result<PathBuf> {
+        let path = self.save_capture_in(&self.save_dir)?;
+        self.mark_saved();
+        Ok(path)
+    }
+    //------Saves the capture in the private temporary directory, for programs that need a file.
+    // The caller removes the file once it is not needed anymore
+    pub fn save_temporary(&self) -> Result<PathBuf> {
+        self.save_capture_in(&temp::dir()?)
+    }
+    pub fn save_capture_in(&self, dir: &Path) -> Result<PathBuf> {
+        let capture = self.capture().ok_or(Error::NothingCaptured)?;
+        let image = capture.rendered()?;
+        fs::create_dir_all(dir)?;
+        let fields = NameFields::for_capture(capture, &image, self.save_format);
+        let (path, file) = naming::create_free_file(dir, &self.file_template, &fields)?;
+        let written =
+            export::write_image(&image, BufWriter::new(file), self.save_format, &self.export_options);
+        if written.is_err() {
+            // No half-written file is left behind
+            let _ = fs::remove_file(&path);
+        }
+        written.map(|()| path)
+    }
+    //------Asks where to save, starting from the folder used last time
+    pub fn save_capture_as(&mut self) -> Result<Option<PathBuf>> {
+        let capture = self.capture().ok_or(Error::NothingCaptured)?;
+        let saved = self.save_as_dialog(capture)?;
+        if let Some(path) = &saved {
+            self.last_save_dir = path.parent().map(Path::to_path_buf);
+            self.mark_saved();
+        }
+        Ok(saved)
+    }
+    //------Save dialog for any capture, named after the template
+    pub fn save_as_dialog(&self, capture: &Capture) -> Result<Option<PathBuf>> {
+        let image = capture.rendered()?;
+        let fields = NameFields::for_capture(capture, &image, self.save_format);
+        let mut dialog = FileDialog::new();
+        // The configured format is offered first
+        let formats = std::iter::once(self.save_format)
+            .chain(ExportFormat::ALL.into_iter().filter(|f| *f != self.save_format));
+        for format in formats {
+            dialog = dialog.add_filter(format.name(), format.extensions());
+        }
+        let directory = self.last_save_dir.as_ref().unwrap_or(&self.save_dir);
+        let files = dialog
+            .set_file_name(naming::expand_template(&self.file_template, &fields, 1))
+            .set_directory(directory)
+            .save_file();
 
-        img_crop.to_image()
+        match files {
+            Some(save_path) => {
+                let format = export::resolve_format(&save_path, None)?;
+                export::save_image(&image, &save_path, format, &self.export_options)?;
+                Ok(Some(save_path))
+            }
+            None => Ok(None),
+        }
     }
-    pub fn save_capture(&self, ctx: &egui::Context) {
-        if !matches!(self.state, AppState::MainApp) {
-            return;
+    pub fn handle_save_request(&mut self) {
+        let result = self.save_capture();
+        if let Some(path) = self.report(result) {
+            self.notifications
+                .info(format!("Capture saved to {}", path.display()));
+        }
+    }
+    pub fn handle_save_as_request(&mut self) {
+        let result = self.save_capture_as();
+        if let Some(Some(path)) = self.report(result) {
+            self.notifications
+                .info(format!("Capture saved to {}", path.display()));
         }
-        if let Some(_texture) = self.texture.clone() {
-            let files = FileDialog::new()
-                .add_filter("PNG", &["png"])
-                .add_filter("JPG", &["jpg"])
-                .add_filter("GIF", &["gif"])
-                .set_file_name("screenshot")
-                .set_directory("/")
-                .save_file();
-            let ext = files.clone();
+    }
 
-            if let Some(mut _img) = self.image.clone() {
-                let img = self.crop_image(ctx);
-                if let Some(save_path) = ext.as_ref() {
-                    if let Some(extension) = save_path.extension() {
-                        let extension_str = extension.to_string_lossy().to_lowercase();
-                        match extension.to_str() {
-                            Some("jpg") | Some("jpeg") | Some("png") => {
-                                img.save(files.unwrap().as_path()).unwrap();
-                            }
-                            Some("gif") => {
-                                let file = OpenOptions::new()
-                                    .create(true)
-                                    .read(true)
-                                    .write(true)
-                                    .open(save_path.as_path())
-                                    .unwrap();
+    pub fn drag(&mut self, ui: &mut Ui, id: Id, body: impl FnOnce(&mut Ui)) {
+        let response = ui.scope(body).response;
+        // Handles stick out of the outline, they must be grabbable too
+        let response = ui.interact(response.rect.expand(HANDLE_SIZE), id, Sense::drag());
+        let Some(mut crop) = self.tab().map(|tab| tab.crop) else {
+            return;
+        };
+        let outline = Rect::from_min_size(crop.button_position, crop.dimensions);
 
-                                let frame = image::Frame::new(img);
+        let painter = ui.painter();
+        painter.rect_stroke(outline, 0.0, Stroke::new(1.0, Color32::RED));
+        for handle in TouchedFrame::HANDLES {
+            painter.rect_filled(
+                Rect::from_center_size(handle.anchor(outline), Vec2::splat(HANDLE_SIZE)),
+                0.0,
+                Color32::RED,
+            );
+        }
 
-                                let mut encoder = GifEncoder::new_with_speed(file, 30);
-                                encoder.encode_frame(frame).unwrap();
-                            }
-                            _ => println!("Unsupported file extension: {}", extension_str),
-                        }
-                    }
+        let hovered = ui
+            .ctx()
+            .pointer_hover_pos()
+            .map_or(TouchedFrame::None, |pointer| {
+                TouchedFrame::at(outline, pointer)
+            });
+        if response.drag_started() {
+            crop.frame = hovered;
+            crop.resizing = crop.frame != TouchedFrame::None;
+            crop.drag_aspect_ratio = outline.aspect_ratio();
+        }
+        let shown = if crop.resizing { crop.frame } else { hovered };
+        if let Some(icon) = shown.cursor_icon() {
+            ui.output_mut(|o| o.cursor_icon = icon);
+        }
+
+        if crop.resizing && response.dragged() {
+            let keep_ratio = ui.input(|i| i.modifiers.shift);
+            let selection = match (crop.frame, response.interact_pointer_pos()) {
+                (TouchedFrame::Inside, _) => {
+                    Some(self.keep_in_display(outline.translate(response.drag_delta())))
+                }
+                (frame, Some(pointer)) => {
+                    let pointer = self.display_rect.clamp(pointer);
+                    let ratio = keep_ratio.then_some(crop.drag_aspect_ratio);
+                    Some(frame.resize(outline, pointer, ratio))
                 }
+                _ => None,
+            };
+            // Shapes that would leave the image or collapse are refused
+            if let Some(selection) = selection.filter(|s| {
+                s.width() >= MIN_SELECTION
+                    && s.height() >= MIN_SELECTION
+                    && self.display_rect.expand(0.5).contains_rect(*s)
+            }) {
+                crop.button_position = selection.min;
+                crop.dimensions = selection.size();
             }
         }
+        if response.drag_released() {
+            crop.resizing = false;
+            crop.frame = TouchedFrame::None;
+        }
+        if let Some(tab) = self.tab_mut() {
+            tab.crop = crop;
+        }
     }
 
-    pub fn drag(&mut self, ui: &mut Ui, id: Id, body: impl FnOnce(&mut Ui)) {
-        let response = ui.scope(body).response;
-        let response = ui.interact(response.rect, id, Sense::drag());
-        let outline = Rect::from_min_size(self.button_position, self.dimensions);
+    //------ Moves the selection with the arrow keys, one image pixel at a time (10 with shift)
+    pub fn nudge_selection(&mut self, ctx: &egui::Context) {
+        let (direction, shift) = ctx.input(|i| {
+            let mut direction = Vec2::ZERO;
+            if i.key_pressed(Key::ArrowLeft) {
+                direction.x -= 1.0;
+            }
+            if i.key_pressed(Key::ArrowRight) {
+                direction.x += 1.0;
+            }
+            if i.key_pressed(Key::ArrowUp) {
+                direction.y -= 1.0;
+            }
+            if i.key_pressed(Key::ArrowDown) {
+                direction.y += 1.0;
+            }
+            (direction, i.modifiers.shift)
+        });
+        if direction == Vec2::ZERO {
+            return;
+        }
+        let step = if shift { 10.0 } else { 1.0 };
+        let mut selection = self.selection_pixels();
+        selection = selection.translate(direction * step);
+        self.set_selection_pixels(selection);
+    }
+
+    //------ Selection of the crop editor in pixels of the captured image
+    pub fn selection_pixels(&self) -> Rect {
+        let Some(crop) = self.tab().map(|tab| tab.crop) else {
+            return Rect::NOTHING;
+        };
+        let scale = self.pixels_per_display_point();
+        Rect::from_min_size(
+            ((crop.button_position - self.display_rect.min) * scale).to_pos2(),
+            crop.dimensions * scale,
+        )
+    }
 
-        let mut resize_frame = outline.clone().shrink2(Vec2::new(5.0, 5.0));
-        resize_frame.set_center(outline.center());
-        let dx = Rect::from_two_pos(resize_frame.right_bottom(), outline.right_top());
-        let top = Rect::from_two_pos(resize_frame.right_top(), outline.left_top());
-        let down = Rect::from_two_pos(resize_frame.right_bottom(), outline.left_bottom());
-        let sx = Rect::from_two_pos(resize_frame.left_top(), outline.left_bottom());
+    pub fn set_selection_pixels(&mut self, selection: Rect) {
+        let scale = self.pixels_per_display_point();
+        if scale <= 0.0 {
+            return;
+        }
+        let min_size = Vec2::splat(MIN_SELECTION);
+        let size = (selection.size() / scale)
+            .max(min_size)
+            .min(self.display_rect.size());
+        let selection = Rect::from_min_size(
+            self.display_rect.min + selection.min.to_vec2() / scale,
+            size,
+        );
+        let selection = self.keep_in_display(selection);
+        if let Some(tab) = self.tab_mut() {
+            tab.crop.button_position = selection.min;
+            tab.crop.dimensions = selection.size();
+        }
+    }
 
-        ui.painter()
-            .rect_stroke(outline, 0.0, Stroke::new(1.0, Color32::RED));
-        if response.hovered()
-            && ui.rect_contains_pointer(outline)
-            && !ui.rect_contains_pointer(resize_frame)
-            && !response.dragged()
-        {
-            if ui.rect_contains_pointer(down) {
-                ui.output_mut(|o| {
-                    o.cursor_icon = egui::CursorIcon::ResizeNorth;
-                });
-            } else if ui.rect_contains_pointer(top) {
-                ui.output_mut(|o| {
-                    o.cursor_icon = egui::CursorIcon::ResizeNorth;
-                });
-            } else if ui.rect_contains_pointer(dx) {
-                ui.output_mut(|o| {
-                    o.cursor_icon = egui::CursorIcon::ResizeEast;
-                });
-            } else if ui.rect_contains_pointer(sx) {
-                ui.output_mut(|o| {
-                    o.cursor_icon = egui::CursorIcon::ResizeEast;
-                });
+    fn pixels_per_display_point(&self) -> f32 {
+        match self.capture() {
+            Some(capture) if self.display_rect.width() > 0.0 => {
+                capture.image.width() as f32 / self.display_rect.width()
             }
+            _ => 0.0,
         }
+    }
 
-        if response.drag_started()
-            && ui.rect_contains_pointer(outline)
-            && !ui.rect_contains_pointer(resize_frame)
-        {
-            if ui.rect_contains_pointer(down) {
-                self.frame = TouchedFrame::Bottom;
-            } else if ui.rect_contains_pointer(top) {
-                self.frame = TouchedFrame::Top;
-            } else if ui.rect_contains_pointer(dx) {
-                self.frame = TouchedFrame::Right;
-            } else if ui.rect_contains_pointer(sx) {
-                self.frame = TouchedFrame::Left;
-            } else {
-                self.frame = TouchedFrame::None;
-            }
-            self.resizing = true;
+    // Shifts the selection back inside the displayed image without resizing it
+    fn keep_in_display(&self, selection: Rect) -> Rect {
+        let bounds = self.display_rect;
+        let min = Pos2::new(
+            selection
+                .min
+                .x
+                .min(bounds.max.x - selection.width())
+                .max(bounds.min.x),
+            selection
+                .min
+                .y
+                .min(bounds.max.y - selection.height())
+                .max(bounds.min.y),
+        );
+        Rect::from_min_size(min, selection.size())
+    }
+}
+
+// Side of the square handles and smallest selection, in display points
+const HANDLE_SIZE: f32 = 8.0;
+const MIN_SELECTION: f32 = 15.0;
+
+impl TouchedFrame {
+    const HANDLES: [TouchedFrame; 8] = [
+        TouchedFrame::TopLeft,
+        TouchedFrame::Top,
+        TouchedFrame::TopRight,
+        TouchedFrame::Right,
+        TouchedFrame::BottomRight,
+        TouchedFrame::Bottom,
+        TouchedFrame::BottomLeft,
+        TouchedFrame::Left,
+    ];
+
+    fn anchor(&self, outline: Rect) -> Pos2 {
+        match self {
+            TouchedFrame::TopLeft => outline.left_top(),
+            TouchedFrame::Top => outline.center_top(),
+            TouchedFrame::TopRight => outline.right_top(),
+            TouchedFrame::Right => outline.right_center(),
+            TouchedFrame::BottomRight => outline.right_bottom(),
+            TouchedFrame::Bottom => outline.center_bottom(),
+            TouchedFrame::BottomLeft => outline.left_bottom(),
+            TouchedFrame::Left => outline.left_center(),
+            TouchedFrame::Inside | TouchedFrame::None => outline.center(),
         }
-        let new_y = self.button_position.y + response.drag_delta().y;
-        let new_x = self.button_position.x + response.drag_delta().x;
+    }
 
-        match self.frame {
-            TouchedFrame::Top => {
-                if response.drag_delta().y < 0.0 && new_y > self.display_rect.left_top().y {
-                    self.button_position.y += response.drag_delta().y;
-                    self.dimensions.y -= response.drag_delta().y;
-                } else if response.drag_delta().y >= 0.0
-                    && self.dimensions.y - response.drag_delta().y > 15.0
-                {
-                    self.dimensions.y -= response.drag_delta().y;
-                    self.button_position.y += response.drag_delta().y;
-                }
+    //------ Part of the selection under the pointer, corners win over edges
+    fn at(outline: Rect, pointer: Pos2) -> TouchedFrame {
+        let margin = HANDLE_SIZE;
+        if !outline.expand(margin).contains(pointer) {
+            return TouchedFrame::None;
+        }
+        let left = (pointer.x - outline.left()).abs() <= margin;
+        let right = (pointer.x - outline.right()).abs() <= margin;
+        let top = (pointer.y - outline.top()).abs() <= margin;
+        let bottom = (pointer.y - outline.bottom()).abs() <= margin;
+        match (left, right, top, bottom) {
+            (true, _, true, _) => TouchedFrame::TopLeft,
+            (_, true, true, _) => TouchedFrame::TopRight,
+            (true, _, _, true) => TouchedFrame::BottomLeft,
+            (_, true, _, true) => TouchedFrame::BottomRight,
+            (_, _, true, _) => TouchedFrame::Top,
+            (_, _, _, true) => TouchedFrame::Bottom,
+            (true, _, _, _) => TouchedFrame::Left,
+            (_, true, _, _) => TouchedFrame::Right,
+            _ => TouchedFrame::Inside,
+        }
+    }
+
+    fn cursor_icon(&self) -> Option<CursorIcon> {
+        match self {
+            TouchedFrame::Top | TouchedFrame::Bottom => Some(CursorIcon::ResizeVertical),
+            TouchedFrame::Left | TouchedFrame::Right => Some(CursorIcon::ResizeHorizontal),
+            TouchedFrame::TopLeft | TouchedFrame::BottomRight => Some(CursorIcon::ResizeNwSe),
+            TouchedFrame::TopRight | TouchedFrame::BottomLeft => Some(CursorIcon::ResizeNeSw),
+            TouchedFrame::Inside => Some(CursorIcon::Move),
+            TouchedFrame::None => None,
+        }
+    }
+
+    //------ Outline after dragging this handle to pointer, ratio keeps width/height fixed
+    fn resize(&self, outline: Rect, pointer: Pos2, ratio: Option<f32>) -> Rect {
+        // With a locked ratio the pointer stays on its side of the fixed corner or edge,
+        // crossing it would flip the outline
+        let pointer = if ratio.is_some() {
+            Pos2::new(
+                match self {
+                    TouchedFrame::Left | TouchedFrame::TopLeft | TouchedFrame::BottomLeft => {
+                        pointer.x.min(outline.right())
+                    }
+                    TouchedFrame::Right | TouchedFrame::TopRight | TouchedFrame::BottomRight => {
+                        pointer.x.max(outline.left())
+                    }
+                    _ => pointer.x,
+                },
+                match self {
+                    TouchedFrame::Top | TouchedFrame::TopLeft | TouchedFrame::TopRight => {
+                        pointer.y.min(outline.bottom())
+                    }
+                    TouchedFrame::Bottom | TouchedFrame::BottomLeft | TouchedFrame::BottomRight => {
+                        pointer.y.max(outline.top())
+                    }
+                    _ => pointer.y,
+                },
+            )
+        } else {
+            pointer
+        };
+        let mut r = outline;
+        match self {
+            TouchedFrame::Top => r.min.y = pointer.y,
+            TouchedFrame::Bottom => r.max.y = pointer.y,
+            TouchedFrame::Left => r.min.x = pointer.x,
+            TouchedFrame::Right => r.max.x = pointer.x,
+            TouchedFrame::TopLeft => r.min = pointer,
+            TouchedFrame::TopRight => {
+                r.min.y = pointer.y;
+                r.max.x = pointer.x;
             }
-            TouchedFrame::Bottom => {
-                if response.drag_delta().y < 0.0
-                    && self.dimensions.y + response.drag_delta().y > 15.0
-                {
-                    self.dimensions.y += response.drag_delta().y;
-                } else if response.drag_delta().y >= 0.0
-                    && new_y + self.dimensions.y < self.display_rect.right_bottom().y
-                {
-                    self.dimensions.y += response.drag_delta().y;
-                }
+            TouchedFrame::BottomLeft => {
+                r.min.x = pointer.x;
+                r.max.y = pointer.y;
             }
-            TouchedFrame::Right => {
-                if response.drag_delta().x < 0.0
-                    && self.dimensions.x + response.drag_delta().x > 15.0
-                {
-                    self.dimensions.x += response.drag_delta().x;
-                } else if response.drag_delta().x >= 0.0
-                    && new_x + self.dimensions.x < self.display_rect.right_top().x
-                {
-                    self.dimensions.x += response.drag_delta().x;
-                }
+            TouchedFrame::BottomRight => r.max = pointer,
+            TouchedFrame::Inside | TouchedFrame::None => {}
+        }
+        let Some(ratio) = ratio else {
+            return r;
+        };
+        match self {
+            // Edges drive the other side around the centre line
+            TouchedFrame::Top | TouchedFrame::Bottom => {
+                Rect::from_center_size(
+                    Pos2::new(outline.center().x, r.center().y),
+                    Vec2::new(r.height() * ratio, r.height()),
+                )
             }
-            TouchedFrame::Left => {
-                if response.drag_delta().x < 0.0 && new_x > self.display_rect.left_bottom().x {
-                    self.button_position.x += response.drag_delta().x;
-                    self.dimensions.x -= response.drag_delta().x;
-                } else if response.drag_delta().x >= 0.0
-                    && self.dimensions.x - response.drag_delta().x > 15.0
-                {
-                    self.button_position.x += response.drag_delta().x;
-                    self.dimensions.x -= response.drag_delta().x;
-                }
+            TouchedFrame::Left | TouchedFrame::Right => {
+                Rect::from_center_size(
+                    Pos2::new(r.center().x, outline.center().y),
+                    Vec2::new(r.width(), r.width() / ratio),
+                )
             }
-            _ => {}
-        }
-        if response.drag_released() {
-            self.resizing = false;
+            // Corners keep the opposite corner still
+            TouchedFrame::TopLeft
+            | TouchedFrame::TopRight
+            | TouchedFrame::BottomLeft
+            | TouchedFrame::BottomRight => {
+                let width = r.width().max(r.height() * ratio);
+                let size = Vec2::new(width, width / ratio);
+                let fixed = match self {
+                    TouchedFrame::TopLeft => outline.right_bottom(),
+                    TouchedFrame::TopRight => outline.left_bottom(),
+                    TouchedFrame::BottomLeft => outline.right_top(),
+                    _ => outline.left_top(),
+                };
+                let grown = Pos2::new(
+                    if fixed.x == outline.left() { fixed.x + size.x } else { fixed.x - size.x },
+                    if fixed.y == outline.top() { fixed.y + size.y } else { fixed.y - size.y },
+                );
+                Rect::from_two_pos(fixed, grown)
+            }
+            TouchedFrame::Inside | TouchedFrame::None => r,
         }
     }
 }
+
+//------ Draws an annotation over the texture, to_screen maps the capture region to the image space
+pub fn paint_annotation(painter: &egui::Painter, annotation: &Annotation, to_screen: &RectTransform) {
+    let style = annotation.style;
+    let scale = to_screen.scale().x;
+    if let (Some(polygon), Some(fill)) = (annotation.fill_polygon(), style.fill) {
+        let points = polygon.into_iter().map(|p| to_screen.transform_pos(p)).collect();
+        painter.add(egui::Shape::convex_polygon(points, fill, Stroke::NONE));
+    }
+    let stroke = Stroke::new(style.stroke_width * scale, style.stroke_color);
+    for line in annotation.strokes() {
+        let points = line.into_iter().map(|p| to_screen.transform_pos(p)).collect();
+        painter.add(egui::Shape::line(points, stroke));
+    }
+    if let Shape::Text { pos, text } = &annotation.shape {
+        painter.text(
+            to_screen.transform_pos(*pos),
+            egui::Align2::LEFT_TOP,
+            text,
+            egui::FontId::proportional(style.font_size * scale),
+            style.stroke_color,
+        );
+    }
+}
+
+//------Conversion of screnshoots crate img to egui renderable img.
+// Images larger than the GPU accepts are shrunk for display only, exports keep every pixel.
+pub fn load_texture(
+    ctx: &egui::Context,
+    name: &str,
+    image: &image::RgbaImage,
+) -> egui::TextureHandle {
+    let max_side = ctx.input(|i| i.max_texture_side) as u32;
+    let longest = image.width().max(image.height());
+    let shrunk;
+    let image = if longest > max_side {
+        let scale = max_side as f64 / longest as f64;
+        let width = ((image.width() as f64 * scale) as u32).clamp(1, max_side);
+        let height = ((image.height() as f64 * scale) as u32).clamp(1, max_side);
+        shrunk = imageops::resize(image, width, height, FilterType::Triangle);
+        &shrunk
+    } else {
+        image
+    };
+    let pixels: Vec<Color32> = image
+        .pixels()
+        .map(|pixel| {
+            Color32::from_rgba_unmultiplied(pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3])
+        })
+        .collect();
+
+    let img = egui::ColorImage {
+        pixels,
+        size: [image.width() as usize, image.height() as usize],
+    };
+    ctx.load_texture(name, img, Default::default())
+}
+
+//------Combination pressed by a key event.
+// Ctrl+C, Ctrl+X and Ctrl+V reach the app as copy, cut and paste events instead of keys.
+fn pressed_combo(event: &Event, modifiers: egui::Modifiers) -> Option<KeyCombo> {
+    let (key, modifiers) = match event {
+        Event::Key {
+            key,
+            pressed: true,
+            repeat: false,
+            modifiers,
+            ..
+        } => (*key, *modifiers),
+        Event::Copy => (Key::C, modifiers),
+        Event::Cut => (Key::X, modifiers),
+        Event::Paste(_) => (Key::V, modifiers),
+        _ => return None,
+    };
+    let key = progetto_malnati::Key::from_name(key.name())?;
+    Some(KeyCombo::new(modifier_set(modifiers), key))
+}
+
+// egui reports Super only on macOS, as the command key
+fn modifier_set(modifiers: egui::Modifiers) -> ModifierSet {
+    ModifierSet {
+        ctrl: modifiers.ctrl,
+        shift: modifiers.shift,
+        alt: modifiers.alt,
+        super_key: modifiers.mac_cmd,
+    }
+}
@@ -0,0 +1,558 @@
+use emath::{Pos2, Rect, Vec2};
+use image::{imageops, RgbaImage};
+use screenshots::Screen;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::annotations::{self, Annotation};
+use crate::clipboard;
+use crate::error::{Error, Result};
+use crate::export::{self, ExportFormat, ExportOptions};
+use crate::monitors::{self, CaptureTarget, Monitor};
+use crate::redaction::{self, Redaction};
+
+//------ Everything the app needs from the system to take a screenshot.
+// Areas are in virtual-desktop coordinates, returned images are in physical pixels.
+pub trait CaptureSource {
+    fn monitors(&self) -> Result<Vec<Monitor>>;
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage>;
+    fn capture_rect(&self, monitor: &Monitor, area: Rect) -> Result<RgbaImage>;
+}
+
+//------ Environment variable listing image fixtures to use instead of the real screens
+pub const FIXTURES_ENV: &str = "SCREEN_CAPTURE_FIXTURES";
+
+//------ Real screens when nothing else is requested, fixtures otherwise
+pub fn default_source() -> Box<dyn CaptureSource> {
+    if let Some(paths) = env::var_os(FIXTURES_ENV) {
+        let paths: Vec<PathBuf> = env::split_paths(&paths).collect();
+        match FileSource::from_files(&paths) {
+            Ok(source) => return Box::new(source),
+            Err(e) => log::warn!("ignoring {}: {}", FIXTURES_ENV, e),
+        }
+    }
+    Box::new(ScreenshotsSource)
+}
+
+//------ Captures real screens through the screenshots crate
+pub struct ScreenshotsSource;
+
+impl ScreenshotsSource {
+    fn screen(&self, monitor: &Monitor) -> Result<Screen> {
+        Screen::all()
+            .map_err(capture_error)?
+            .into_iter()
+            .find(|screen| screen.display_info.id == monitor.id)
+            .ok_or_else(|| Error::Capture(format!("display {} is not connected", monitor.id)))
+    }
+}
+
+impl CaptureSource for ScreenshotsSource {
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        let monitors: Vec<Monitor> = Screen::all()
+            .map_err(capture_error)?
+            .iter()
+            .map(|screen| {
+                let info = screen.display_info;
+                Monitor {
+                    id: info.id,
+                    x: info.x,
+                    y: info.y,
+                    width: info.width,
+                    height: info.height,
+                    scale_factor: info.scale_factor,
+                    is_primary: info.is_primary,
+                }
+            })
+            .collect();
+        if monitors.is_empty() {
+            return Err(Error::NoMonitor);
+        }
+        Ok(monitors)
+    }
+
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage> {
+        self.screen(monitor)?.capture().map_err(capture_error)
+    }
+
+    fn capture_rect(&self, monitor: &Monitor, area: Rect) -> Result<RgbaImage> {
+        let area = area.intersect(monitor.rect());
+        if !area.is_positive() {
+            return Err(Error::EmptySelection);
+        }
+        self.screen(monitor)?
+            .capture_area(
+                (area.min.x - monitor.rect().min.x) as i32,
+                (area.min.y - monitor.rect().min.y) as i32,
+                area.width() as u32,
+                area.height() as u32,
+            )
+            .map_err(capture_error)
+    }
+}
+
+// The screenshots crate reports its failures through anyhow
+fn capture_error(e: impl std::fmt::Display) -> Error {
+    Error::Capture(e.to_string())
+}
+
+//------ Serves images read from files (PNG, PPM or anything the image crate decodes), used for
+// headless runs where there is no display to capture
+#[derive(Default)]
+pub struct FileSource {
+    monitors: Vec<(Monitor, PathBuf)>,
+}
+
+impl FileSource {
+    pub fn new() -> Self {
+        FileSource::default()
+    }
+
+    pub fn with_monitor(mut self, monitor: Monitor, path: impl Into<PathBuf>) -> Self {
+        self.monitors.push((monitor, path.into()));
+        self
+    }
+
+    //------ One monitor per file, placed left to right like a row of screens
+    pub fn from_files(paths: &[PathBuf]) -> Result<Self> {
+        if paths.is_empty() {
+            return Err(Error::Capture("no fixture files given".into()));
+        }
+        let mut source = FileSource::new();
+        let mut x = 0;
+        for (index, path) in paths.iter().enumerate() {
+            let (width, height) = image::image_dimensions(path)?;
+            let monitor = Monitor {
+                id: index as u32,
+                x,
+                y: 0,
+                width,
+                height,
+                scale_factor: 1.0,
+                is_primary: index == 0,
+            };
+            x += width as i32;
+            source = source.with_monitor(monitor, path);
+        }
+        Ok(source)
+    }
+}
+
+impl CaptureSource for FileSource {
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        Ok(self.monitors.iter().map(|(monitor, _)| *monitor).collect())
+    }
+
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage> {
+        let (_, path) = self
+            .monitors
+            .iter()
+            .find(|(known, _)| known.id == monitor.id)
+            .ok_or_else(|| Error::Capture(format!("no fixture for display {}", monitor.id)))?;
+        Ok(image::open(path)?.to_rgba8())
+    }
+
+    fn capture_rect(&self, monitor: &Monitor, area: Rect) -> Result<RgbaImage> {
+        let area = area.intersect(monitor.rect());
+        if !area.is_positive() {
+            return Err(Error::EmptySelection);
+        }
+        let mut img = self.capture_display(monitor)?;
+        let scale = img.width() as f32 / monitor.width as f32;
+        Ok(imageops::crop(
+            &mut img,
+            ((area.min.x - monitor.rect().min.x) * scale) as u32,
+            ((area.min.y - monitor.rect().min.y) * scale) as u32,
+            (area.width() * scale) as u32,
+            (area.height() * scale) as u32,
+        )
+        .to_image())
+    }
+}
+
+//------ Cuts the selection out of an image covering image_rect, both rects in virtual-desktop coordinates
+pub fn crop_image(img: &RgbaImage, image_rect: Rect, selection: Rect) -> Result<RgbaImage> {
+    let selection = selection.intersect(image_rect);
+    if !selection.is_positive() {
+        return Err(Error::EmptySelection);
+    }
+    // Image may have more pixels than points on HiDPI monitors
+    let shrink = image_rect.width() / (img.width() as f32);
+    let top_left_x = (selection.left_top().x - image_rect.left_top().x) / shrink;
+    let top_left_y = (selection.left_top().y - image_rect.left_top().y) / shrink;
+    let width = selection.width() / shrink;
+    let height = selection.height() / shrink;
+
+    let cropped = imageops::crop_imm(
+        img,
+        top_left_x.max(0.0) as u32,
+        top_left_y.max(0.0) as u32,
+        width as u32,
+        height as u32,
+    )
+    .to_image();
+    if cropped.width() == 0 || cropped.height() == 0 {
+        return Err(Error::EmptySelection);
+    }
+    Ok(cropped)
+}
+
+//------ Rotations and flips of a whole capture, each one is undone by its inverse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    RotateClockwise,
+    RotateCounterClockwise,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 4] = [
+        Transform::RotateCounterClockwise,
+        Transform::RotateClockwise,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::RotateClockwise => "Rotate right",
+            Transform::RotateCounterClockwise => "Rotate left",
+            Transform::FlipHorizontal => "Flip horizontally",
+            Transform::FlipVertical => "Flip vertically",
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        match self {
+            Transform::RotateClockwise => Transform::RotateCounterClockwise,
+            Transform::RotateCounterClockwise => Transform::RotateClockwise,
+            flip => *flip,
+        }
+    }
+
+    // Where a point of a rect of the given size ends up, relative to its top left corner
+    fn map(&self, point: Vec2, size: Vec2) -> Vec2 {
+        match self {
+            Transform::RotateClockwise => Vec2::new(size.y - point.y, point.x),
+            Transform::RotateCounterClockwise => Vec2::new(point.y, size.x - point.x),
+            Transform::FlipHorizontal => Vec2::new(size.x - point.x, point.y),
+            Transform::FlipVertical => Vec2::new(point.x, size.y - point.y),
+        }
+    }
+}
+
+//------ Information stored together with a capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureMetadata {
+    pub taken_at: SystemTime,
+    pub target: CaptureTarget,
+    // Label of the captured monitor, "All screens" for stitched captures
+    pub target_label: String,
+    // File name, or "clipboard", of an image opened instead of captured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>,
+}
+
+//------ A screenshot with the part of it chosen by the user.
+// Rects are in virtual-desktop coordinates, image is in physical pixels.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub image: RgbaImage,
+    // Area of the desktop covered by image
+    pub image_rect: Rect,
+    // Part of image_rect that gets exported
+    pub region: Rect,
+    // Drawn over image when exporting, in virtual-desktop coordinates
+    pub annotations: Vec<Annotation>,
+    // Applied to a copy of image when exporting, image itself keeps the original pixels
+    pub redactions: Vec<Redaction>,
+    pub metadata: CaptureMetadata,
+}
+
+impl Capture {
+    //------ Captures the whole target, region starts as everything
+    pub fn take(
+        source: &dyn CaptureSource,
+        monitors: &[Monitor],
+        target: CaptureTarget,
+    ) -> Result<Capture> {
+        let image = monitors::capture_target(source, monitors, target)?;
+        let image_rect = monitors::target_rect(monitors, target);
+        Ok(Capture {
+            image,
+            image_rect,
+            region: image_rect,
+            annotations: Vec::new(),
+            redactions: Vec::new(),
+            metadata: CaptureMetadata {
+                taken_at: SystemTime::now(),
+                target,
+                target_label: monitors::target_label(monitors, target),
+                imported_from: None,
+            },
+        })
+    }
+
+    //------ Image that was not captured, placed at the origin with one point per pixel.
+    // source names it in the editor and in file names.
+    pub fn from_image(image: RgbaImage, source: &str) -> Capture {
+        let image_rect = Rect::from_min_size(
+            Pos2::ZERO,
+            Vec2::new(image.width() as f32, image.height() as f32),
+        );
+        Capture {
+            image,
+            image_rect,
+            region: image_rect,
+            annotations: Vec::new(),
+            redactions: Vec::new(),
+            metadata: CaptureMetadata {
+                taken_at: SystemTime::now(),
+                target: CaptureTarget::default(),
+                target_label: source.to_string(),
+                imported_from: Some(source.to_string()),
+            },
+        }
+    }
+
+    //------ Image file in any format the image crate decodes
+    pub fn open(path: &Path) -> Result<Capture> {
+        let image = image::open(path)?.to_rgba8();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Ok(Capture::from_image(image, &name))
+    }
+
+    //------ Image currently on the system clipboard
+    pub fn paste() -> Result<Capture> {
+        Ok(Capture::from_image(clipboard::paste_image()?, "clipboard"))
+    }
+
+    pub fn with_region(mut self, region: Rect) -> Self {
+        self.region = region;
+        self
+    }
+
+    //------ Region as texture coordinates of image, between 0 and 1
+    pub fn region_uv(&self) -> Rect {
+        let origin = self.image_rect.min;
+        let size = self.image_rect.size();
+        Rect::from_two_pos(
+            Pos2::new(
+                (self.region.left_top().x - origin.x) / size.x,
+                (self.region.left_top().y - origin.y) / size.y,
+            ),
+            Pos2::new(
+                (self.region.right_bottom().x - origin.x) / size.x,
+                (self.region.right_bottom().y - origin.y) / size.y,
+            ),
+        )
+    }
+
+    //------ Rotates or flips the image together with the region, annotations and redactions.
+    // The image keeps its top left corner in the desktop.
+    pub fn transform(&mut self, transform: Transform) {
+        self.image = match transform {
+            Transform::RotateClockwise => imageops::rotate90(&self.image),
+            Transform::RotateCounterClockwise => imageops::rotate270(&self.image),
+            Transform::FlipHorizontal => imageops::flip_horizontal(&self.image),
+            Transform::FlipVertical => imageops::flip_vertical(&self.image),
+        };
+        let origin = self.image_rect.min;
+        let size = self.image_rect.size();
+        let map = |p: Pos2| origin + transform.map(p - origin, size);
+        let map_rect = |r: Rect| Rect::from_two_pos(map(r.min), map(r.max));
+        self.image_rect = map_rect(self.image_rect);
+        self.region = map_rect(self.region);
+        for annotation in &mut self.annotations {
+            annotation.map_points(map);
+        }
+        for redaction in &mut self.redactions {
+            redaction.area = map_rect(redaction.area);
+        }
+    }
+
+    pub fn cropped(&self) -> Result<RgbaImage> {
+        crop_image(&self.image, self.image_rect, self.region)
+    }
+
+    //------ Copy of image with the redactions applied
+    pub fn redacted_image(&self) -> RgbaImage {
+        let mut image = self.image.clone();
+        redaction::apply(&mut image, self.image_rect, &self.redactions);
+        image
+    }
+
+    //------ Exported image: the region, redacted, with the annotations drawn in
+    pub fn rendered(&self) -> Result<RgbaImage> {
+        if self.annotations.is_empty() && self.redactions.is_empty() {
+            return self.cropped();
+        }
+        let mut image = self.redacted_image();
+        annotations::render(&mut image, self.image_rect, &self.annotations);
+        crop_image(&image, self.image_rect, self.region)
+    }
+
+    pub fn save(&self, path: &Path, format: ExportFormat, options: &ExportOptions) -> Result<()> {
+        export::save_image(&self.rendered()?, path, format, options)
+    }
+
+    pub fn copy_to_clipboard(&self) -> Result<()> {
+        clipboard::copy_image(&self.rendered()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::fs;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    // Fresh directory per test, tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "progetto_malnati-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn fixture(dir: &Path, name: &str, width: u32, height: u32, color: Rgba<u8>) -> PathBuf {
+        let path = dir.join(name);
+        RgbaImage::from_pixel(width, height, color).save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn fixtures_are_captured_cropped_and_saved() {
+        let dir = test_dir("pipeline");
+        let paths = [
+            fixture(&dir, "left.png", 40, 30, RED),
+            fixture(&dir, "right.png", 20, 30, BLUE),
+        ];
+        let source = FileSource::from_files(&paths).unwrap();
+        let monitors = source.monitors().unwrap();
+        assert_eq!(
+            monitors[1].rect(),
+            Rect::from_min_size(Pos2::new(40.0, 0.0), Vec2::new(20.0, 30.0))
+        );
+
+        let capture = Capture::take(&source, &monitors, CaptureTarget::AllScreens).unwrap();
+        assert_eq!(capture.image.dimensions(), (60, 30));
+        assert_eq!(
+            capture.image_rect,
+            Rect::from_min_size(Pos2::ZERO, Vec2::new(60.0, 30.0))
+        );
+
+        // Selection crossing the border between the two fixtures
+        let region = Rect::from_min_max(Pos2::new(30.0, 5.0), Pos2::new(50.0, 15.0));
+        let cropped = crop_image(&capture.image, capture.image_rect, region).unwrap();
+        assert_eq!(cropped.dimensions(), (20, 10));
+        assert_eq!(*cropped.get_pixel(0, 0), RED);
+        assert_eq!(*cropped.get_pixel(19, 9), BLUE);
+
+        let path = dir.join("out.png");
+        export::save_image(&cropped, &path, ExportFormat::Png, &ExportOptions::default()).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(saved, cropped);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn single_monitor_capture_matches_fixture() {
+        let dir = test_dir("single");
+        let paths = [
+            fixture(&dir, "left.png", 40, 30, RED),
+            fixture(&dir, "right.png", 20, 30, BLUE),
+        ];
+        let source = FileSource::from_files(&paths).unwrap();
+        let monitors = source.monitors().unwrap();
+        let capture = Capture::take(&source, &monitors, CaptureTarget::Monitor(1)).unwrap();
+        assert_eq!(capture.image, RgbaImage::from_pixel(20, 30, BLUE));
+        assert_eq!(capture.region, monitors[1].rect());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fixtures_are_required() {
+        assert!(matches!(FileSource::from_files(&[]), Err(Error::Capture(_))));
+    }
+
+    #[test]
+    fn area_outside_the_monitor_is_empty() {
+        let dir = test_dir("area");
+        let paths = [fixture(&dir, "screen.png", 40, 30, RED)];
+        let source = FileSource::from_files(&paths).unwrap();
+        let monitors = source.monitors().unwrap();
+        let outside = Rect::from_min_size(Pos2::new(100.0, 100.0), Vec2::splat(10.0));
+        assert!(matches!(
+            monitors::capture_area(&source, &monitors, CaptureTarget::Monitor(0), outside),
+            Err(Error::EmptySelection)
+        ));
+        let inside = Rect::from_min_size(Pos2::new(35.0, 25.0), Vec2::splat(10.0));
+        let image =
+            monitors::capture_area(&source, &monitors, CaptureTarget::Monitor(0), inside).unwrap();
+        assert_eq!(image.dimensions(), (5, 5));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn transforms_are_undone_by_their_inverse() {
+        let mut image = RgbaImage::from_pixel(4, 2, RED);
+        image.put_pixel(0, 0, BLUE);
+        let mut capture = Capture::from_image(image, "test").with_region(Rect::from_min_max(
+            Pos2::new(0.0, 0.0),
+            Pos2::new(1.0, 2.0),
+        ));
+        let original = capture.clone();
+
+        capture.transform(Transform::RotateClockwise);
+        assert_eq!(capture.image.dimensions(), (2, 4));
+        assert_eq!(*capture.image.get_pixel(1, 0), BLUE);
+        assert_eq!(capture.image_rect.size(), Vec2::new(2.0, 4.0));
+        assert_eq!(capture.region, Rect::from_min_max(Pos2::ZERO, Pos2::new(2.0, 1.0)));
+        assert_eq!(*capture.cropped().unwrap().get_pixel(1, 0), BLUE);
+
+        for transform in Transform::ALL {
+            capture.transform(transform);
+            capture.transform(transform.inverse());
+        }
+        capture.transform(Transform::RotateCounterClockwise);
+        assert_eq!(capture.image, original.image);
+        assert_eq!(capture.image_rect, original.image_rect);
+        assert_eq!(capture.region, original.region);
+    }
+
+    #[test]
+    fn crop_outside_the_image_is_empty() {
+        let image = RgbaImage::from_pixel(10, 10, RED);
+        let image_rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(10.0));
+        let outside = Rect::from_min_size(Pos2::new(20.0, 20.0), Vec2::splat(5.0));
+        assert!(matches!(
+            crop_image(&image, image_rect, outside),
+            Err(Error::EmptySelection)
+        ));
+    }
+
+    #[test]
+    fn crop_uses_physical_pixels_on_hidpi_images() {
+        // 10x10 points captured at scale factor 2
+        let mut image = RgbaImage::from_pixel(20, 20, RED);
+        image.put_pixel(10, 10, BLUE);
+        let image_rect = Rect::from_min_size(Pos2::new(-10.0, 0.0), Vec2::splat(10.0));
+        let selection = Rect::from_min_size(Pos2::new(-5.0, 5.0), Vec2::splat(2.0));
+        let cropped = crop_image(&image, image_rect, selection).unwrap();
+        assert_eq!(cropped.dimensions(), (4, 4));
+        assert_eq!(*cropped.get_pixel(0, 0), BLUE);
+    }
+}
@@ -0,0 +1,210 @@
+use clap::{Parser, Subcommand};
+use eframe::egui::{Pos2, Rect, Vec2};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::{thread, time};
+
+use progetto_malnati::capture;
+use progetto_malnati::export;
+use progetto_malnati::monitors::{self, CaptureTarget};
+use progetto_malnati::{Error, ExportOptions};
+
+use super::config;
+
+//------ Exit codes of the headless commands
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_CAPTURE_FAILED: u8 = 3;
+pub const EXIT_SAVE_FAILED: u8 = 4;
+
+#[derive(Parser, Debug)]
+#[command(about = "Screen capture utility, opens the window when no command is given")]
+pub struct Cli {
+    /// Start hidden with a tray icon, the window only shows up to capture and edit
+    #[arg(long)]
+    pub daemon: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Takes a screenshot without opening the window
+    Capture {
+        /// Monitor to capture, numbered from 1 as in list-monitors (default: primary)
+        #[arg(long, conflicts_with = "all")]
+        monitor: Option<usize>,
+        /// Capture every monitor stitched together
+        #[arg(long)]
+        all: bool,
+        /// Area to keep as x,y,w,h relative to the captured monitor (or desktop with --all)
+        #[arg(long, value_parser = parse_region)]
+        region: Option<Rect>,
+        /// Seconds to wait before capturing
+        #[arg(long, default_value_t = 0)]
+        delay: u64,
+        /// File to write
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Image format, guessed from the output extension when missing
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Prints the connected monitors as JSON
+    ListMonitors,
+}
+
+fn parse_region(value: &str) -> Result<Rect, String> {
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| format!("invalid region: {}", e))?;
+    match numbers[..] {
+        [x, y, w, h] if w > 0.0 && h > 0.0 => {
+            Ok(Rect::from_min_size(Pos2::new(x, y), Vec2::new(w, h)))
+        }
+        _ => Err("region must be x,y,w,h with positive width and height".to_string()),
+    }
+}
+
+pub fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Capture {
+            monitor,
+            all,
+            region,
+            delay,
+            output,
+            format,
+        } => capture(monitor, all, region, delay, output, format),
+        Command::ListMonitors => list_monitors(),
+    }
+}
+
+fn capture(
+    monitor: Option<usize>,
+    all: bool,
+    region: Option<Rect>,
+    delay: u64,
+    output: PathBuf,
+    format: Option<String>,
+) -> ExitCode {
+    let format = match export::resolve_format(&output, format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("{}, use --format or a known output extension", e);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let source = capture::default_source();
+    let monitors = match source.monitors() {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            eprintln!("Cannot list monitors: {}", e);
+            return ExitCode::from(exit_code(&e));
+        }
+    };
+    let target = match monitor {
+        _ if all => CaptureTarget::AllScreens,
+        Some(number) if number >= 1 && number <= monitors.len() => {
+            CaptureTarget::Monitor(number - 1)
+        }
+        Some(number) => {
+            eprintln!("Monitor {} does not exist, see list-monitors", number);
+            return ExitCode::from(EXIT_USAGE);
+        }
+        None => CaptureTarget::Monitor(monitors.iter().position(|m| m.is_primary).unwrap_or(0)),
+    };
+
+    thread::sleep(time::Duration::from_secs(delay));
+
+    let target_rect = monitors::target_rect(&monitors, target);
+    let result = match region {
+        Some(region) => monitors::capture_area(
+            source.as_ref(),
+            &monitors,
+            target,
+            region.translate(target_rect.min.to_vec2()),
+        ),
+        None => monitors::capture_target(source.as_ref(), &monitors, target),
+    };
+    let img = match result {
+        Ok(img) if img.width() > 0 && img.height() > 0 => img,
+        Ok(_) => {
+            eprintln!("Region is outside of the captured area");
+            return ExitCode::from(EXIT_USAGE);
+        }
+        Err(e) => {
+            eprintln!("Capture failed: {}", e);
+            return ExitCode::from(exit_code(&e));
+        }
+    };
+
+    if let Err(e) = export::save_image(&img, &output, format, &export_options()) {
+        eprintln!("Cannot write {}: {}", output.display(), e);
+        return ExitCode::from(exit_code(&e));
+    }
+    ExitCode::SUCCESS
+}
+
+// Encoder settings of the settings file, as the window would save with them
+fn export_options() -> ExportOptions {
+    match config::load() {
+        Ok((config, rejected)) => {
+            if !rejected.is_empty() {
+                eprintln!("Invalid settings ignored: {}", rejected.join(", "));
+            }
+            config.export
+        }
+        Err(e) => {
+            eprintln!("Settings not loaded, {}", e);
+            ExportOptions::default()
+        }
+    }
+}
+
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::EmptySelection
+        | Error::MonitorNotFound(_)
+        | Error::UnsupportedFormat(_)
+        | Error::Config(_)
+        | Error::Hotkeys(_)
+        | Error::Tray(_)
+        | Error::Notification(_)
+        | Error::Ipc(_) => EXIT_USAGE,
+        Error::Io(_) | Error::Image(_) | Error::Clipboard(_) => EXIT_SAVE_FAILED,
+        Error::NoMonitor | Error::Capture(_) | Error::NothingCaptured | Error::Recording(_) => {
+            EXIT_CAPTURE_FAILED
+        }
+    }
+}
+
+fn list_monitors() -> ExitCode {
+    let monitors = match capture::default_source().monitors() {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            eprintln!("Cannot list monitors: {}", e);
+            return ExitCode::from(exit_code(&e));
+        }
+    };
+    let list: Vec<serde_json::Value> = monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| {
+            serde_json::json!({
+                "number": index + 1,
+                "id": monitor.id,
+                "x": monitor.x,
+                "y": monitor.y,
+                "width": monitor.width,
+                "height": monitor.height,
+                "scale_factor": monitor.scale_factor,
+                "primary": monitor.is_primary,
+            })
+        })
+        .collect();
+    println!("{}", serde_json::Value::Array(list));
+    ExitCode::SUCCESS
+}
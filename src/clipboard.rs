@@ -0,0 +1,28 @@
+use image::RgbaImage;
+use std::borrow::Cow;
+
+use crate::error::Result;
+
+//------ Puts the image on the system clipboard
+pub fn copy_image(img: &RgbaImage) -> Result<()> {
+    let img_to_save = arboard::ImageData {
+        width: img.width() as usize,
+        height: img.height() as usize,
+        bytes: Cow::from(img.as_raw()),
+    };
+    let mut clipboard = arboard::Clipboard::new()?;
+    clipboard.set_image(img_to_save)?;
+    Ok(())
+}
+
+//------ Image on the system clipboard, an error when it holds none
+pub fn paste_image() -> Result<RgbaImage> {
+    let image = arboard::Clipboard::new()?.get_image()?;
+    RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    // Sizes that do not match the pixels
+    .ok_or(arboard::Error::ConversionFailure.into())
+}
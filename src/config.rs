@@ -0,0 +1,437 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+use progetto_malnati::gallery::GalleryOptions;
+use progetto_malnati::naming;
+use progetto_malnati::recording::RecordingFormat;
+use progetto_malnati::{Error, ExportFormat, ExportOptions, KeyBindings, Result};
+
+use super::after_capture::AfterCaptureConfig;
+use super::DelayedMode;
+
+// Bumped when the meaning of a key changes, older files are migrated when loaded
+pub const CONFIG_VERSION: u32 = 4;
+const FILE_NAME: &str = "config.toml";
+
+//------ Settings kept between launches.
+// Missing keys take their default value, unknown keys are ignored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub version: u32,
+    // Window and global shortcuts
+    pub key_bindings: KeyBindings,
+    pub global_hotkeys: GlobalHotkeysConfig,
+    pub capture: CaptureConfig,
+    pub save: SaveConfig,
+    pub export: ExportOptions,
+    // Steps run on a new capture, per capture mode
+    pub after_capture: AfterCaptureConfig,
+    pub recording: RecordingConfig,
+    pub gallery: GalleryOptions,
+    pub window: WindowConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            key_bindings: KeyBindings::default(),
+            global_hotkeys: GlobalHotkeysConfig::default(),
+            capture: CaptureConfig::default(),
+            save: SaveConfig::default(),
+            export: ExportOptions::default(),
+            after_capture: AfterCaptureConfig::default(),
+            recording: RecordingConfig::default(),
+            gallery: GalleryOptions::default(),
+            window: WindowConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    // Seconds of "New capture after"
+    pub delay: u64,
+    pub delayed_mode: DelayedMode,
+    pub freeze_frame: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveConfig {
+    pub dir: PathBuf,
+    pub file_template: String,
+    pub format: ExportFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_dir: Option<PathBuf>,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        SaveConfig {
+            dir: default_save_dir(),
+            file_template: naming::DEFAULT_TEMPLATE.to_string(),
+            format: ExportFormat::Png,
+            last_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalHotkeysConfig {
+    // Global scope of the key bindings is grabbed for the whole desktop (X11)
+    pub enabled: bool,
+}
+
+impl Default for GlobalHotkeysConfig {
+    fn default() -> Self {
+        GlobalHotkeysConfig { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub fps: u32,
+    pub format: RecordingFormat,
+    // Seconds, paused time is not counted
+    pub max_duration: u64,
+    // Megabytes of frames kept in memory, the next ones go to a temporary directory
+    pub memory_limit: u64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig {
+            fps: 10,
+            format: RecordingFormat::default(),
+            max_duration: 60,
+            memory_limit: 512,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub always_on_top: bool,
+    // Start hidden in the tray, as with --daemon
+    pub daemon: bool,
+    // Listen on the control socket without running in the background
+    pub control_socket: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            always_on_top: true,
+            daemon: false,
+            control_socket: false,
+        }
+    }
+}
+
+//------ Screenshots folder inside the user's pictures, or home when there is none
+pub fn default_save_dir() -> PathBuf {
+    dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("Screenshots")
+}
+
+//------ config.toml in the XDG config directory ($XDG_CONFIG_HOME or ~/.config)
+pub fn path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join(env!("CARGO_PKG_NAME"))
+            .join(FILE_NAME),
+    )
+}
+
+//------ Reads the settings file, defaults when there is none yet.
+// Keys with invalid values keep their default and are returned so they can be reported.
+pub fn load() -> Result<(Config, Vec<String>)> {
+    let Some(path) = path() else {
+        return Ok((Config::default(), Vec::new()));
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Config::default(), Vec::new())),
+        Err(e) => return Err(e.into()),
+    };
+    parse(&text).map_err(|e| match e {
+        Error::Config(message) => Error::Config(format!("{}: {}", path.display(), message)),
+        e => e,
+    })
+}
+
+// Settings of a file of any version, see load
+fn parse(text: &str) -> Result<(Config, Vec<String>)> {
+    let mut user: Table = toml::from_str(text).map_err(|e| Error::Config(e.to_string()))?;
+    upgrade_key_bindings(&mut user);
+    upgrade_save_immediately(&mut user);
+    upgrade_global_hotkeys(&mut user);
+    let (config, rejected) = merge(user)?;
+    Ok((migrate(config), rejected))
+}
+
+//------ Writes the settings, through a temporary file so a crash never leaves half a file
+pub fn save(config: &Config) -> Result<()> {
+    let path = path().ok_or(Error::Config("no configuration directory".to_string()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = toml::to_string_pretty(config).map_err(|e| Error::Config(e.to_string()))?;
+    let temporary = path.with_extension("toml.tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, &path)?;
+    Ok(())
+}
+
+// Applies the user values one by one over the defaults, a value that does not fit is skipped
+fn merge(user: Table) -> Result<(Config, Vec<String>)> {
+    let mut table = Table::try_from(Config::default()).map_err(|e| Error::Config(e.to_string()))?;
+    let mut rejected = Vec::new();
+    for (key, value) in user {
+        match (value, table.get(&key)) {
+            (Value::Table(section), Some(Value::Table(_))) => {
+                for (field, value) in section {
+                    try_set(&mut table, &key, Some(&field), value, &mut rejected);
+                }
+            }
+            (value, _) => try_set(&mut table, &key, None, value, &mut rejected),
+        }
+    }
+    let config = Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| Error::Config(e.to_string()))?;
+    Ok((config, rejected))
+}
+
+fn try_set(table: &mut Table, key: &str, field: Option<&str>, value: Value, rejected: &mut Vec<String>) {
+    let mut candidate = table.clone();
+    match (field, candidate.get_mut(key)) {
+        (Some(field), Some(Value::Table(section))) => {
+            section.insert(field.to_string(), value);
+        }
+        _ => {
+            candidate.insert(key.to_string(), value);
+        }
+    }
+    if Value::Table(candidate.clone()).try_into::<Config>().is_ok() {
+        *table = candidate;
+    } else {
+        rejected.push(match field {
+            Some(field) => format!("{}.{}", key, field),
+            None => key.to_string(),
+        });
+    }
+}
+
+// Files without a version are taken as written by this build
+fn file_version(user: &Table) -> i64 {
+    user.get("version")
+        .and_then(Value::as_integer)
+        .unwrap_or(CONFIG_VERSION as i64)
+}
+
+// Version 1 stored only the key, Ctrl was implied (Ctrl+Shift for redo).
+// Cancel on Escape becomes plain Escape, the overlays take it without modifiers.
+fn upgrade_key_bindings(user: &mut Table) {
+    if file_version(user) >= 2 {
+        return;
+    }
+    let Some(Value::Table(bindings)) = user.get_mut("key_bindings") else {
+        return;
+    };
+    for (action, value) in bindings.iter_mut() {
+        let Value::String(key) = value else {
+            continue;
+        };
+        *key = match (action.as_str(), key.as_str()) {
+            ("cancel", "Escape") => key.clone(),
+            ("redo", _) => format!("Ctrl+Shift+{}", key),
+            _ => format!("Ctrl+{}", key),
+        };
+    }
+}
+
+// Up to version 2 "save immediately" applied to every capture, it is now a step of each mode
+fn upgrade_save_immediately(user: &mut Table) {
+    if file_version(user) >= 3 {
+        return;
+    }
+    let save_immediately = match user.get_mut("save") {
+        Some(Value::Table(save)) => save.remove("save_immediately"),
+        _ => None,
+    };
+    if save_immediately.and_then(|v| v.as_bool()) != Some(true) {
+        return;
+    }
+    let mut steps = Table::new();
+    steps.insert("save".to_string(), Value::Boolean(true));
+    let after_capture: Table = ["full_screen", "area", "delayed"]
+        .into_iter()
+        .map(|mode| (mode.to_string(), Value::Table(steps.clone())))
+        .collect();
+    user.insert("after_capture".to_string(), Value::Table(after_capture));
+}
+
+// Up to version 3 global shortcuts had their own section, they are now the global scope of the key bindings
+fn upgrade_global_hotkeys(user: &mut Table) {
+    if file_version(user) >= 4 {
+        return;
+    }
+    let Some(Value::Table(hotkeys)) = user.get_mut("global_hotkeys") else {
+        return;
+    };
+    let renamed = [
+        ("new", "new"),
+        ("fullscreen", "fullscreen"),
+        ("clipboard", "capture_clipboard"),
+    ];
+    let global: Table = renamed
+        .into_iter()
+        .filter_map(|(old, id)| Some((id.to_string(), hotkeys.remove(old)?)))
+        .collect();
+    if global.is_empty() {
+        return;
+    }
+    let bindings = user
+        .entry("key_bindings")
+        .or_insert_with(|| Value::Table(Table::new()));
+    if let Value::Table(bindings) = bindings {
+        bindings.insert("global".to_string(), Value::Table(global));
+    }
+}
+
+// Files from newer versions are read as far as possible
+fn migrate(mut config: Config) -> Config {
+    if config.version > CONFIG_VERSION {
+        log::warn!(
+            "settings file has version {}, this build knows up to {}",
+            config.version,
+            CONFIG_VERSION
+        );
+    }
+    config.version = CONFIG_VERSION;
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::after_capture::CaptureMode;
+    use crate::MyApp;
+    use progetto_malnati::keybidings::{Action, Shortcut};
+    use progetto_malnati::Scope;
+
+    fn shortcut(text: &str) -> Option<Shortcut> {
+        Some(text.parse().unwrap())
+    }
+
+    #[test]
+    fn version_1_files_are_migrated() {
+        let text = r#"
+            version = 1
+            [key_bindings]
+            clipboard = "C"
+            redo = "Z"
+            cancel = "Escape"
+            [save]
+            save_immediately = true
+            format = "Jpg"
+        "#;
+        let (config, rejected) = parse(text).unwrap();
+        assert!(rejected.is_empty(), "{:?}", rejected);
+        assert_eq!(config.version, CONFIG_VERSION);
+        let bindings = &config.key_bindings;
+        assert_eq!(bindings.get(Scope::Window, Action::Clipboard), shortcut("Ctrl+C"));
+        assert_eq!(bindings.get(Scope::Window, Action::Redo), shortcut("Ctrl+Shift+Z"));
+        assert_eq!(bindings.get(Scope::Window, Action::Cancel), shortcut("Escape"));
+        assert_eq!(config.save.format, ExportFormat::Jpg);
+        for mode in CaptureMode::ALL {
+            assert!(config.after_capture.get(mode).save);
+        }
+    }
+
+    #[test]
+    fn version_3_global_hotkeys_become_the_global_scope() {
+        let text = r#"
+            version = 3
+            [global_hotkeys]
+            enabled = false
+            clipboard = "Super+Alt+C"
+        "#;
+        let (config, rejected) = parse(text).unwrap();
+        assert!(rejected.is_empty(), "{:?}", rejected);
+        assert!(!config.global_hotkeys.enabled);
+        assert_eq!(
+            config.key_bindings.get(Scope::Global, Action::CaptureClipboard),
+            shortcut("Super+Alt+C")
+        );
+        // The others keep their default
+        assert_eq!(
+            config.key_bindings.get(Scope::Global, Action::New),
+            KeyBindings::default().get(Scope::Global, Action::New)
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let text = r#"
+            mystery = 3
+            [window]
+            daemon = true
+            colour = "blue"
+            [plugins]
+            enabled = true
+        "#;
+        let (config, rejected) = parse(text).unwrap();
+        assert!(rejected.is_empty(), "{:?}", rejected);
+        assert!(config.window.daemon);
+    }
+
+    #[test]
+    fn bad_values_keep_their_default_only() {
+        let text = r#"
+            [capture]
+            delay = "soon"
+            freeze_frame = true
+            [recording]
+            fps = 25
+            format = "Mpeg"
+        "#;
+        let (config, rejected) = parse(text).unwrap();
+        assert_eq!(rejected, vec!["capture.delay", "recording.format"]);
+        assert_eq!(config.capture.delay, CaptureConfig::default().delay);
+        assert!(config.capture.freeze_frame);
+        assert_eq!(config.recording.fps, 25);
+        assert_eq!(config.recording.format, RecordingFormat::default());
+    }
+
+    #[test]
+    fn written_settings_are_read_back() {
+        let mut config = Config::default();
+        config.capture.delay = 7;
+        config.window.control_socket = true;
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(parse(&text).unwrap(), (config, Vec::new()));
+    }
+
+    #[test]
+    fn reset_to_defaults() {
+        let mut app = MyApp::default();
+        let (changed, _) = parse("[capture]\ndelay = 9\n[window]\nalways_on_top = false").unwrap();
+        app.apply_config(changed.clone());
+        assert_eq!(app.config(), changed);
+        app.apply_config(Config::default());
+        assert_eq!(app.config(), Config::default());
+    }
+}
@@ -0,0 +1,43 @@
+use crate::error::Result;
+
+//------ Pops up a notification through the desktop notification server
+#[cfg(target_os = "linux")]
+pub fn show(summary: &str, body: &str) -> Result<()> {
+    use std::collections::HashMap;
+    use zbus::blocking::Connection;
+    use zbus::zvariant::Value;
+
+    use crate::error::Error;
+
+    let notify_error = |e: zbus::Error| Error::Notification(e.to_string());
+    let connection = Connection::session().map_err(notify_error)?;
+    let hints: HashMap<&str, Value> = HashMap::new();
+    connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "Screen Capture",
+                // Not replacing an earlier notification
+                0u32,
+                "camera-photo",
+                summary,
+                body,
+                Vec::<&str>::new(),
+                hints,
+                // Server default timeout
+                -1i32,
+            ),
+        )
+        .map_err(notify_error)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn show(_summary: &str, _body: &str) -> Result<()> {
+    Err(crate::error::Error::Notification(
+        "only available on Linux".to_string(),
+    ))
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::io;
+
+//------ Everything that can go wrong while capturing and exporting
+#[derive(Debug)]
+pub enum Error {
+    // The system did not report any monitor (e.g. some Wayland sessions)
+    NoMonitor,
+    MonitorNotFound(usize),
+    Capture(String),
+    // Selection does not overlap the captured image
+    EmptySelection,
+    NothingCaptured,
+    UnsupportedFormat(String),
+    // Settings file that cannot be read or written
+    Config(String),
+    // Global shortcuts cannot be registered (no X11 display)
+    Hotkeys(String),
+    // No tray to show the icon in (no StatusNotifierWatcher on the session bus)
+    Tray(String),
+    // No desktop notification server reachable
+    Notification(String),
+    // Control socket that cannot be opened
+    Ipc(String),
+    // Screen recording that cannot go on or be encoded
+    Recording(String),
+    Clipboard(arboard::Error),
+    Image(image::ImageError),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoMonitor => write!(f, "no monitor found"),
+            Error::MonitorNotFound(index) => write!(f, "monitor {} is not connected", index + 1),
+            Error::Capture(message) => write!(f, "screen capture failed: {}", message),
+            Error::EmptySelection => write!(f, "the selection is empty"),
+            Error::NothingCaptured => write!(f, "there is no capture yet"),
+            Error::UnsupportedFormat(name) => write!(f, "unsupported image format: {}", name),
+            Error::Config(message) => write!(f, "settings file: {}", message),
+            Error::Hotkeys(message) => write!(f, "global shortcuts: {}", message),
+            Error::Tray(message) => write!(f, "tray icon: {}", message),
+            Error::Notification(message) => write!(f, "desktop notification: {}", message),
+            Error::Ipc(message) => write!(f, "control socket: {}", message),
+            Error::Recording(message) => write!(f, "screen recording: {}", message),
+            Error::Clipboard(e) => write!(f, "clipboard error: {}", e),
+            Error::Image(e) => write!(f, "image error: {}", e),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Clipboard(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<arboard::Error> for Error {
+    fn from(e: arboard::Error) -> Self {
+        Error::Clipboard(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(e) => Error::Io(e),
+            e => Error::Image(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
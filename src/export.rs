@@ -0,0 +1,490 @@
+use color_quant::NeuQuant;
+use ecolor::Color32;
+use image::codecs::{
+    jpeg::JpegEncoder,
+    png::{CompressionType, FilterType, PngEncoder},
+    webp::WebPEncoder,
+};
+use image::{imageops, ColorType, ImageEncoder, ImageOutputFormat, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Error, Result};
+
+//------ Formats a capture can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Png,
+    Jpg,
+    Gif,
+    WebP,
+    Bmp,
+    Tiff,
+    Qoi,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 7] = [
+        ExportFormat::Png,
+        ExportFormat::Jpg,
+        ExportFormat::Gif,
+        ExportFormat::WebP,
+        ExportFormat::Bmp,
+        ExportFormat::Tiff,
+        ExportFormat::Qoi,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpg => "JPG",
+            ExportFormat::Gif => "GIF",
+            ExportFormat::WebP => "WebP (lossless)",
+            ExportFormat::Bmp => "BMP",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Qoi => "QOI",
+        }
+    }
+
+    //------ Extensions accepted for the format, the first one is used for new files
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Png => &["png"],
+            ExportFormat::Jpg => &["jpg", "jpeg"],
+            ExportFormat::Gif => &["gif"],
+            ExportFormat::WebP => &["webp"],
+            ExportFormat::Bmp => &["bmp"],
+            ExportFormat::Tiff => &["tiff", "tif"],
+            ExportFormat::Qoi => &["qoi"],
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<ExportFormat> {
+        let extension = extension.to_lowercase();
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        ExportFormat::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+//------ Speed/size trade-off of the PNG encoder
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        }
+    }
+}
+
+//------ Row filter applied before PNG compression, adaptive picks one per row
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    #[default]
+    Adaptive,
+}
+
+impl PngFilter {
+    pub const ALL: [PngFilter; 6] = [
+        PngFilter::None,
+        PngFilter::Sub,
+        PngFilter::Up,
+        PngFilter::Average,
+        PngFilter::Paeth,
+        PngFilter::Adaptive,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngFilter::None => "None",
+            PngFilter::Sub => "Sub",
+            PngFilter::Up => "Up",
+            PngFilter::Average => "Average",
+            PngFilter::Paeth => "Paeth",
+            PngFilter::Adaptive => "Adaptive",
+        }
+    }
+}
+
+//------ Per-format encoder settings, formats not listed have none
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    // 1 to 100
+    pub jpeg_quality: u8,
+    // JPEG has no alpha, transparent pixels are blended onto this colour
+    pub jpeg_background: Color32,
+    // 2 to 256 colours
+    pub gif_palette_size: u16,
+    pub gif_dithering: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            png_compression: PngCompression::default(),
+            png_filter: PngFilter::default(),
+            jpeg_quality: 90,
+            jpeg_background: Color32::WHITE,
+            gif_palette_size: 256,
+            gif_dithering: true,
+        }
+    }
+}
+
+//------ Encodes the image in the given format and writes it to path.
+// A file already there is only replaced once the new one is complete.
+pub fn save_image(
+    img: &RgbaImage,
+    path: &Path,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<()> {
+    write_atomically(path, |out| write_image(img, out, format, options))
+}
+
+//------ Encodes the image in the given format into an already open file
+pub fn write_image<W: Write + Seek>(
+    img: &RgbaImage,
+    mut out: W,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<()> {
+    match format {
+        ExportFormat::Png => {
+            let compression = match options.png_compression {
+                PngCompression::Fast => CompressionType::Fast,
+                PngCompression::Default => CompressionType::Default,
+                PngCompression::Best => CompressionType::Best,
+            };
+            let filter = match options.png_filter {
+                PngFilter::None => FilterType::NoFilter,
+                PngFilter::Sub => FilterType::Sub,
+                PngFilter::Up => FilterType::Up,
+                PngFilter::Average => FilterType::Avg,
+                PngFilter::Paeth => FilterType::Paeth,
+                PngFilter::Adaptive => FilterType::Adaptive,
+            };
+            PngEncoder::new_with_quality(&mut out, compression, filter).write_image(
+                img.as_raw(),
+                img.width(),
+                img.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        ExportFormat::Jpg => {
+            let flat = flatten(img, options.jpeg_background);
+            JpegEncoder::new_with_quality(&mut out, options.jpeg_quality.clamp(1, 100))
+                .encode_image(&flat)?;
+        }
+        ExportFormat::Gif => write_gif(img, &mut out, options)?,
+        ExportFormat::WebP => {
+            WebPEncoder::new_lossless(&mut out).encode(
+                img.as_raw(),
+                img.width(),
+                img.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        ExportFormat::Bmp => img.write_to(&mut out, ImageOutputFormat::Bmp)?,
+        ExportFormat::Tiff => img.write_to(&mut out, ImageOutputFormat::Tiff)?,
+        ExportFormat::Qoi => img.write_to(&mut out, ImageOutputFormat::Qoi)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+//------ Runs write on a temporary file next to path, then renames it over path.
+// When writing fails the temporary file is removed and path is left as it was.
+pub(crate) fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    static PARTS: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let part = path.with_file_name(format!(
+        ".{}.{}-{}.part",
+        name,
+        process::id(),
+        PARTS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = OpenOptions::new().write(true).create_new(true).open(&part)?;
+    let mut out = BufWriter::new(file);
+    let written = write(&mut out)
+        .and_then(|()| out.flush().map_err(Error::from))
+        .and_then(|()| fs::rename(&part, path).map_err(Error::from));
+    if written.is_err() {
+        let _ = fs::remove_file(&part);
+    }
+    written
+}
+
+//------ Blends every pixel over background, dropping the alpha channel
+pub fn flatten(img: &RgbaImage, background: Color32) -> RgbImage {
+    let [br, bg, bb, _] = background.to_srgba_unmultiplied();
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let alpha = a as f32 / 255.0;
+        let mix = |c: u8, back: u8| (c as f32 * alpha + back as f32 * (1.0 - alpha)).round() as u8;
+        image::Rgb([mix(r, br), mix(g, bg), mix(b, bb)])
+    })
+}
+
+fn write_gif(img: &RgbaImage, out: impl Write, options: &ExportOptions) -> Result<()> {
+    let frame = gif_frame(img, options)?;
+    let mut encoder = gif::Encoder::new(out, frame.width, frame.height, &[]).map_err(from_gif)?;
+    encoder.write_frame(&frame).map_err(from_gif)?;
+    Ok(())
+}
+
+//------ Palette of palette_size colours computed with NeuQuant, fully transparent pixels get their own entry
+pub(crate) fn gif_frame(img: &RgbaImage, options: &ExportOptions) -> Result<gif::Frame<'static>> {
+    let width = u16::try_from(img.width()).map_err(|_| gif_too_large())?;
+    let height = u16::try_from(img.height()).map_err(|_| gif_too_large())?;
+    let has_transparency = img.pixels().any(|p| p.0[3] == 0);
+    let max_colors = options.gif_palette_size.clamp(2, 256) as usize;
+    // One slot is kept for transparency
+    let colors = if has_transparency { max_colors - 1 } else { max_colors };
+
+    // Images with few colours, like the changed part of a recording frame, keep them exactly:
+    // NeuQuant learns poorly from a handful of pixels
+    if let Some(index_of) = exact_palette(img, colors) {
+        let mut palette = vec![0; index_of.len() * 3];
+        for (rgb, &index) in &index_of {
+            palette[index as usize * 3..][..3].copy_from_slice(rgb);
+        }
+        let transparent = has_transparency.then_some(index_of.len() as u8);
+        let indices: Vec<u8> = img
+            .pixels()
+            .map(|pixel| match transparent {
+                Some(index) if pixel.0[3] == 0 => index,
+                _ => index_of[&[pixel.0[0], pixel.0[1], pixel.0[2]]],
+            })
+            .collect();
+        if transparent.is_some() {
+            palette.extend_from_slice(&[0, 0, 0]);
+        }
+        return Ok(gif::Frame::from_palette_pixels(width, height, indices, palette, transparent));
+    }
+
+    let quantizer = NeuQuant::new(10, colors.max(1), img.as_raw());
+    let mut quantized = img.clone();
+    // Dithering spreads the error to the next column, a single one has none
+    if options.gif_dithering && img.width() > 1 {
+        imageops::dither(&mut quantized, &quantizer);
+    }
+    let transparent = has_transparency.then_some(colors as u8);
+    let indices: Vec<u8> = quantized
+        .pixels()
+        .zip(img.pixels())
+        .map(|(pixel, original)| match transparent {
+            Some(index) if original.0[3] == 0 => index,
+            _ => quantizer.index_of(&pixel.0) as u8,
+        })
+        .collect();
+    let mut palette: Vec<u8> = quantizer
+        .color_map_rgba()
+        .chunks(4)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    if transparent.is_some() {
+        palette.extend_from_slice(&[0, 0, 0]);
+    }
+    Ok(gif::Frame::from_palette_pixels(width, height, indices, palette, transparent))
+}
+
+// Palette index of every visible colour, None when there are more than colors
+fn exact_palette(img: &RgbaImage, colors: usize) -> Option<HashMap<[u8; 3], u8>> {
+    let mut index_of = HashMap::new();
+    for pixel in img.pixels().filter(|pixel| pixel.0[3] != 0) {
+        let rgb = [pixel.0[0], pixel.0[1], pixel.0[2]];
+        if !index_of.contains_key(&rgb) {
+            if index_of.len() == colors {
+                return None;
+            }
+            index_of.insert(rgb, index_of.len() as u8);
+        }
+    }
+    Some(index_of)
+}
+
+pub(crate) fn from_gif(e: gif::EncodingError) -> Error {
+    match e {
+        gif::EncodingError::Io(e) => Error::Io(e),
+        e => gif_error(e),
+    }
+}
+
+fn gif_too_large() -> Error {
+    gif_error("GIF images are limited to 65535 pixels per side")
+}
+
+fn gif_error(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::Image(image::ImageError::Encoding(image::error::EncodingError::new(
+        image::ImageFormat::Gif.into(),
+        e,
+    )))
+}
+
+//------ Format for a path, explicit name wins over the extension
+pub fn resolve_format(path: &Path, name: Option<&str>) -> Result<ExportFormat> {
+    match name {
+        Some(name) => {
+            ExportFormat::from_extension(name).ok_or(Error::UnsupportedFormat(name.to_string()))
+        }
+        None => ExportFormat::from_path(path).ok_or_else(|| {
+            Error::UnsupportedFormat(
+                path.extension()
+                    .map(|e| e.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "no extension".to_string()),
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::io::Cursor;
+    use std::{env, process};
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    // Four solid quadrants, large enough for JPEG blocks to keep their colour inside
+    fn quadrants() -> RgbaImage {
+        RgbaImage::from_fn(32, 32, |x, y| match (x < 16, y < 16) {
+            (true, true) => RED,
+            (false, true) => Rgba([0, 255, 0, 255]),
+            (true, false) => Rgba([0, 0, 255, 255]),
+            (false, false) => Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    fn encoded(img: &RgbaImage, format: ExportFormat, options: &ExportOptions) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        write_image(img, &mut out, format, options).unwrap();
+        out.into_inner()
+    }
+
+    fn close(a: Rgba<u8>, b: Rgba<u8>, tolerance: u8) -> bool {
+        a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= tolerance)
+    }
+
+    #[test]
+    fn every_format_decodes_back() {
+        let img = quadrants();
+        let options = ExportOptions {
+            gif_dithering: false,
+            ..ExportOptions::default()
+        };
+        for format in ExportFormat::ALL {
+            let bytes = encoded(&img, format, &options);
+            let decoded = image::load_from_memory(&bytes)
+                .unwrap_or_else(|e| panic!("{}: {}", format.name(), e))
+                .to_rgba8();
+            assert_eq!(decoded.dimensions(), img.dimensions(), "{}", format.name());
+            match format {
+                ExportFormat::Jpg | ExportFormat::Gif => {
+                    for (x, y) in [(4, 4), (27, 4), (4, 27), (27, 27)] {
+                        let (got, expected) = (*decoded.get_pixel(x, y), *img.get_pixel(x, y));
+                        let at = format!("{} at {},{}", format.name(), x, y);
+                        assert!(close(got, expected, 16), "{}: {:?}", at, got);
+                    }
+                }
+                _ => assert_eq!(decoded, img, "{}", format.name()),
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_flattens_transparency_onto_the_background() {
+        let img = RgbaImage::from_pixel(16, 16, CLEAR);
+        let options = ExportOptions {
+            jpeg_background: Color32::from_rgb(0, 128, 255),
+            ..ExportOptions::default()
+        };
+        let bytes = encoded(&img, ExportFormat::Jpg, &options);
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert!(decoded.pixels().all(|pixel| close(*pixel, Rgba([0, 128, 255, 255]), 4)));
+    }
+
+    #[test]
+    fn two_colour_gif_keeps_a_slot_for_transparency() {
+        // More shades than the single colour left, they are quantized
+        let img = RgbaImage::from_fn(8, 8, |x, y| match x < 4 {
+            true => Rgba([200 + y as u8 * 5, 0, 0, 255]),
+            false => CLEAR,
+        });
+        let options = ExportOptions {
+            gif_palette_size: 2,
+            ..ExportOptions::default()
+        };
+        let bytes = encoded(&img, ExportFormat::Gif, &options);
+        let mut decoder = gif::DecodeOptions::new().read_info(Cursor::new(bytes)).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        // One colour for the image, the last index for transparency
+        assert_eq!(frame.transparent, Some(1));
+        assert_eq!(frame.palette.as_ref().map(Vec::len), Some(2 * 3));
+        assert_eq!(frame.buffer[0], 0);
+        assert_eq!(frame.buffer[7], 1);
+    }
+
+    #[test]
+    fn few_colours_are_kept_exactly() {
+        // A handful of pixels, as in the changed part of a recording frame
+        let img = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { RED } else { Rgba([1, 2, 3, 255]) });
+        let bytes = encoded(&img, ExportFormat::Gif, &ExportOptions::default());
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), img);
+    }
+
+    #[test]
+    fn failed_save_keeps_the_existing_file() {
+        let dir = env::temp_dir().join(format!("progetto_malnati-test-{}-atomic", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.gif");
+        fs::write(&path, "previous").unwrap();
+        // Too wide for GIF, encoding fails
+        let img = RgbaImage::from_pixel(70_000, 1, RED);
+        assert!(save_image(&img, &path, ExportFormat::Gif, &ExportOptions::default()).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        save_image(&quadrants(), &path, ExportFormat::Gif, &ExportOptions::default()).unwrap();
+        assert_eq!(image::open(&path).unwrap().to_rgba8().dimensions(), (32, 32));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
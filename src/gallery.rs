@@ -0,0 +1,372 @@
+use emath::Rect;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+use crate::annotations::Annotation;
+use crate::capture::{Capture, CaptureMetadata};
+use crate::error::{Error, Result};
+use crate::redaction::Redaction;
+
+// Longest side of the thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+const IMAGE_FILE: &str = "capture.png";
+const THUMBNAIL_FILE: &str = "thumbnail.png";
+const METADATA_FILE: &str = "capture.json";
+
+// Everything of a capture but the pixels, the edits stay editable when it is reopened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredCapture {
+    image_rect: Rect,
+    region: Rect,
+    annotations: Vec<Annotation>,
+    redactions: Vec<Redaction>,
+    metadata: CaptureMetadata,
+}
+
+//------ What the gallery keeps of the captures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GalleryOptions {
+    // Pixels are stored with the redactions applied, they cannot be removed from a reopened capture
+    pub redacted_only: bool,
+    // The oldest entries are deleted past this count, 0 keeps them all
+    pub max_entries: usize,
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        GalleryOptions {
+            redacted_only: true,
+            max_entries: 200,
+        }
+    }
+}
+
+// Edits of an entry waiting for the writer thread
+struct Update {
+    id: String,
+    folder: PathBuf,
+    capture: Capture,
+    redacted_only: bool,
+}
+
+//------ A past capture as listed in the gallery
+#[derive(Debug, Clone)]
+pub struct GalleryEntry {
+    // Name of its folder
+    pub id: String,
+    pub metadata: CaptureMetadata,
+    pub region: Rect,
+    // Annotations and redactions
+    pub edits: usize,
+}
+
+//------ Every capture taken, one folder each with the image, a thumbnail and the metadata.
+// Entries are listed newest first. Edits are written by a thread of the gallery, the pending
+// ones are finished when it is dropped.
+pub struct Gallery {
+    dir: PathBuf,
+    entries: Vec<GalleryEntry>,
+    pub options: GalleryOptions,
+    updates: Option<Sender<Update>>,
+    written: Receiver<(String, Result<()>)>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Gallery {
+    //------ captures/ in the XDG data directory ($XDG_DATA_HOME or ~/.local/share)
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(
+            dirs::data_dir()?
+                .join(env!("CARGO_PKG_NAME"))
+                .join("captures"),
+        )
+    }
+
+    //------ Lists the stored captures, folders that cannot be read are skipped
+    pub fn open(dir: &Path, options: GalleryOptions) -> Result<Gallery> {
+        let mut entries = Vec::new();
+        let folders = match fs::read_dir(dir) {
+            Ok(folders) => folders,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Gallery::with_entries(dir, entries, options))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        for folder in folders {
+            let folder = folder?;
+            let id = folder.file_name().to_string_lossy().into_owned();
+            match read_metadata(&folder.path()) {
+                Ok(stored) => entries.push(entry(id, &stored)),
+                Err(e) => log::warn!("gallery entry {} skipped: {}", id, e),
+            }
+        }
+        entries.sort_by_key(|entry| Reverse(entry.metadata.taken_at));
+        Ok(Gallery::with_entries(dir, entries, options))
+    }
+
+    fn with_entries(dir: &Path, entries: Vec<GalleryEntry>, options: GalleryOptions) -> Gallery {
+        let (updates, pending) = mpsc::channel();
+        let (done, written) = mpsc::channel();
+        Gallery {
+            dir: dir.to_path_buf(),
+            entries,
+            options,
+            updates: Some(updates),
+            written,
+            writer: Some(thread::spawn(move || write_updates(pending, done))),
+        }
+    }
+
+    pub fn entries(&self) -> &[GalleryEntry] {
+        &self.entries
+    }
+
+    pub fn thumbnail_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).join(THUMBNAIL_FILE)
+    }
+
+    //------ Stores a new capture, returns the id of its entry
+    pub fn add(&mut self, capture: &Capture) -> Result<String> {
+        let id = self.free_id(capture);
+        let folder = self.dir.join(&id);
+        fs::create_dir_all(&folder)?;
+        let redacted_only = self.options.redacted_only;
+        if let Err(e) = write_entry(&folder, capture, redacted_only, true) {
+            let _ = fs::remove_dir_all(&folder);
+            return Err(e);
+        }
+        self.entries.insert(0, entry(id.clone(), &stored(capture, redacted_only)));
+        Ok(id)
+    }
+
+    //------ Queues the edits of an entry for the writer thread, see written.
+    // The image is only rewritten when it holds the redactions.
+    pub fn update(&mut self, id: &str, capture: &Capture) -> Result<()> {
+        let folder = self.dir.join(id);
+        if !folder.is_dir() {
+            return Err(Error::Io(ErrorKind::NotFound.into()));
+        }
+        let redacted_only = self.options.redacted_only;
+        if let Some(listed) = self.entries.iter_mut().find(|e| e.id == id) {
+            *listed = entry(id.to_string(), &stored(capture, redacted_only));
+        }
+        let update = Update {
+            id: id.to_string(),
+            folder,
+            capture: capture.clone(),
+            redacted_only,
+        };
+        if let Some(updates) = &self.updates {
+            updates
+                .send(update)
+                .map_err(|_| Error::Io(std::io::Error::other("the gallery writer stopped")))?;
+        }
+        Ok(())
+    }
+
+    //------ Entries whose queued edits were written since the last call, with the result
+    pub fn written(&self) -> Vec<(String, Result<()>)> {
+        self.written.try_iter().collect()
+    }
+
+    //------ Deletes the oldest entries past max_entries, returns their ids
+    pub fn prune(&mut self) -> Result<Vec<String>> {
+        let max = self.options.max_entries;
+        if max == 0 || self.entries.len() <= max {
+            return Ok(Vec::new());
+        }
+        let old: Vec<String> = self.entries[max..].iter().map(|e| e.id.clone()).collect();
+        for id in &old {
+            self.delete(id)?;
+        }
+        Ok(old)
+    }
+
+    //------ Capture of an entry, with its edits
+    pub fn load(&self, id: &str) -> Result<Capture> {
+        let folder = self.dir.join(id);
+        let stored = read_metadata(&folder)?;
+        let image = image::open(folder.join(IMAGE_FILE))?.to_rgba8();
+        Ok(Capture {
+            image,
+            image_rect: stored.image_rect,
+            region: stored.region,
+            annotations: stored.annotations,
+            redactions: stored.redactions,
+            metadata: stored.metadata,
+        })
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<()> {
+        fs::remove_dir_all(self.dir.join(id))?;
+        self.entries.retain(|e| e.id != id);
+        Ok(())
+    }
+
+    // Milliseconds since the epoch, sorted like the captures; a suffix keeps ids unique
+    fn free_id(&self, capture: &Capture) -> String {
+        let millis = capture
+            .metadata
+            .taken_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let mut id = millis.to_string();
+        let mut suffix = 1;
+        while self.dir.join(&id).exists() {
+            suffix += 1;
+            id = format!("{}-{}", millis, suffix);
+        }
+        id
+    }
+}
+
+//------ Exported image shrunk to fit THUMBNAIL_SIZE
+pub fn thumbnail(capture: &Capture) -> Result<RgbaImage> {
+    let image = capture.rendered()?;
+    let scale = THUMBNAIL_SIZE as f32 / image.width().max(image.height()) as f32;
+    if scale >= 1.0 {
+        return Ok(image);
+    }
+    let width = ((image.width() as f32 * scale) as u32).max(1);
+    let height = ((image.height() as f32 * scale) as u32).max(1);
+    Ok(imageops::resize(&image, width, height, FilterType::Triangle))
+}
+
+impl Drop for Gallery {
+    fn drop(&mut self) {
+        // The writer ends once the queued edits are written
+        self.updates = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+// Redactions applied to the stored pixels are not listed again
+fn stored(capture: &Capture, redacted_only: bool) -> StoredCapture {
+    StoredCapture {
+        image_rect: capture.image_rect,
+        region: capture.region,
+        annotations: capture.annotations.clone(),
+        redactions: if redacted_only { Vec::new() } else { capture.redactions.clone() },
+        metadata: capture.metadata.clone(),
+    }
+}
+
+fn entry(id: String, stored: &StoredCapture) -> GalleryEntry {
+    GalleryEntry {
+        id,
+        metadata: stored.metadata.clone(),
+        region: stored.region,
+        edits: stored.annotations.len() + stored.redactions.len(),
+    }
+}
+
+// Writer thread, edits queued while it is busy replace the older ones of the same entry
+fn write_updates(pending: Receiver<Update>, done: Sender<(String, Result<()>)>) {
+    while let Ok(first) = pending.recv() {
+        let mut updates = vec![first];
+        for update in pending.try_iter() {
+            updates.retain(|queued| queued.id != update.id);
+            updates.push(update);
+        }
+        for update in updates {
+            let result =
+                write_entry(&update.folder, &update.capture, update.redacted_only, update.redacted_only);
+            let _ = done.send((update.id, result));
+        }
+    }
+}
+
+// Image when asked, metadata then thumbnail, all through a temporary file so a crash never
+// leaves half a file
+fn write_entry(folder: &Path, capture: &Capture, redacted_only: bool, image: bool) -> Result<()> {
+    if image {
+        let temporary = folder.join(format!("{}.tmp.png", IMAGE_FILE));
+        if redacted_only {
+            capture.redacted_image().save(&temporary)?;
+        } else {
+            capture.image.save(&temporary)?;
+        }
+        fs::rename(&temporary, folder.join(IMAGE_FILE))?;
+    }
+    let text = serde_json::to_string_pretty(&stored(capture, redacted_only))
+        .map_err(|e| Error::Io(std::io::Error::new(ErrorKind::InvalidData, e)))?;
+    let temporary = folder.join(format!("{}.tmp", METADATA_FILE));
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, folder.join(METADATA_FILE))?;
+    let temporary = folder.join(format!("{}.tmp.png", THUMBNAIL_FILE));
+    thumbnail(capture)?.save(&temporary)?;
+    fs::rename(&temporary, folder.join(THUMBNAIL_FILE))?;
+    Ok(())
+}
+
+fn read_metadata(folder: &Path) -> Result<StoredCapture> {
+    let text = fs::read_to_string(folder.join(METADATA_FILE))?;
+    serde_json::from_str(&text).map_err(|e| Error::Io(std::io::Error::new(ErrorKind::InvalidData, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redaction::{Method, RedactionStyle};
+    use emath::{Pos2, Vec2};
+    use image::Rgba;
+    use std::time::{Duration, Instant};
+    use std::{env, process};
+
+    #[test]
+    fn redacted_pixels_and_retention() {
+        let dir = env::temp_dir().join(format!("progetto_malnati-test-{}-gallery", process::id()));
+        let options = GalleryOptions {
+            redacted_only: true,
+            max_entries: 2,
+        };
+        let mut gallery = Gallery::open(&dir, options).unwrap();
+        let image = RgbaImage::from_pixel(8, 8, Rgba([200, 10, 10, 255]));
+        let mut capture = Capture::from_image(image, "test");
+        capture.redactions.push(Redaction {
+            area: Rect::from_min_size(Pos2::ZERO, Vec2::splat(4.0)),
+            method: Method::Fill,
+            style: RedactionStyle::default(),
+        });
+        let first = gallery.add(&capture).unwrap();
+        let stored = gallery.load(&first).unwrap();
+        assert!(stored.redactions.is_empty());
+        assert_eq!(stored.image, capture.redacted_image());
+        assert_ne!(stored.image, capture.image);
+
+        // Written by the gallery thread
+        capture.redactions.clear();
+        gallery.update(&first, &capture).unwrap();
+        let started = Instant::now();
+        let written = loop {
+            let written = gallery.written();
+            if !written.is_empty() || started.elapsed() > Duration::from_secs(5) {
+                break written;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(matches!(written.as_slice(), [(id, Ok(()))] if *id == first));
+        assert_eq!(gallery.load(&first).unwrap().image, capture.image);
+
+        gallery.add(&capture).unwrap();
+        gallery.add(&capture).unwrap();
+        assert_eq!(gallery.prune().unwrap(), vec![first.clone()]);
+        assert_eq!(gallery.entries().len(), 2);
+        assert!(!dir.join(&first).exists());
+        drop(gallery);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, Local};
+use eframe::egui::{self, CentralPanel, TopBottomPanel};
+use progetto_malnati::gallery::GalleryEntry;
+use progetto_malnati::keybidings::Action;
+
+use super::application::load_texture;
+use super::AppState;
+use super::MyApp;
+
+// Width of a gallery tile, thumbnails are shrunk to fit it
+const TILE_WIDTH: f32 = 180.0;
+
+//------ Buttons of a gallery tile
+enum TileCommand {
+    Open,
+    Export,
+    Delete,
+}
+
+impl MyApp {
+    //------ Stores a new capture in the gallery, edits follow in persist_gallery_entry
+    pub fn add_to_gallery(&mut self) {
+        let (Some(gallery), Some(tab)) = (self.gallery.as_mut(), self.tabs.get_mut(self.active_tab)) else {
+            return;
+        };
+        let result = gallery.add(&tab.capture);
+        tab.gallery_dirty = false;
+        let id = self.report(result);
+        if let Some(tab) = self.tab_mut() {
+            tab.gallery_entry = id;
+        }
+        self.prune_gallery();
+    }
+
+    //------ Queues the edits of the open captures for their entries, and takes the settings.
+    // The entries are written by the gallery thread, their thumbnails are reloaded when done.
+    pub fn persist_gallery_entry(&mut self) {
+        let Some(gallery) = self.gallery.as_mut() else {
+            return;
+        };
+        let mut results = Vec::new();
+        for tab in self.tabs.iter_mut().filter(|tab| tab.gallery_dirty) {
+            tab.gallery_dirty = false;
+            if let Some(id) = tab.gallery_entry.as_ref() {
+                results.push(gallery.update(id, &tab.capture));
+            }
+        }
+        for (id, result) in gallery.written() {
+            // An entry deleted meanwhile has nothing left to write to
+            if result.is_ok() || !gallery.entries().iter().any(|entry| entry.id == id) {
+                self.thumbnails.remove(&id);
+            } else {
+                results.push(result);
+            }
+        }
+        let options_changed = gallery.options != self.gallery_options;
+        gallery.options = self.gallery_options;
+        for result in results {
+            self.report(result);
+        }
+        if options_changed {
+            self.prune_gallery();
+        }
+    }
+
+    // Entries past the retention limit are deleted, their tabs stay open without them
+    fn prune_gallery(&mut self) {
+        let Some(gallery) = self.gallery.as_mut() else {
+            return;
+        };
+        let result = gallery.prune();
+        for id in self.report(result).unwrap_or_default() {
+            self.thumbnails.remove(&id);
+            for tab in self.tabs.iter_mut() {
+                if tab.gallery_entry.as_deref() == Some(id.as_str()) {
+                    tab.gallery_entry = None;
+                }
+            }
+        }
+    }
+
+    pub fn gallery_state_visuals(&mut self, ctx: &egui::Context) {
+        let entries: Vec<GalleryEntry> = self
+            .gallery
+            .as_ref()
+            .map(|gallery| gallery.entries().to_vec())
+            .unwrap_or_default();
+        TopBottomPanel::top("gallery navbar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                self.action_button(ui, ctx, "Go back", Action::Cancel);
+                ui.heading("Gallery");
+                ui.label(format!("{} captures", entries.len()));
+            });
+        });
+        let mut picked = None;
+        CentralPanel::default().show(ctx, |ui| {
+            if self.gallery.is_none() {
+                ui.label("Captures are not kept, there is no data directory to store them in");
+                return;
+            }
+            if entries.is_empty() {
+                ui.label("No capture yet, every new capture is listed here");
+                return;
+            }
+            egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for entry in &entries {
+                        if let Some(command) = self.gallery_tile(ctx, ui, entry) {
+                            picked = Some((command, entry.id.clone()));
+                        }
+                    }
+                });
+            });
+        });
+        match picked {
+            Some((TileCommand::Open, id)) => self.open_gallery_entry(ctx, &id),
+            Some((TileCommand::Export, id)) => self.export_gallery_entry(&id),
+            Some((TileCommand::Delete, id)) => self.delete_gallery_entry(&id),
+            None => {}
+        }
+    }
+
+    fn gallery_tile(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, entry: &GalleryEntry) -> Option<TileCommand> {
+        let mut command = None;
+        ui.group(|ui| {
+            ui.set_width(TILE_WIDTH);
+            ui.vertical(|ui| {
+                match self.thumbnail(ctx, &entry.id) {
+                    Some(texture) => {
+                        let size = texture.size_vec2();
+                        let scale = (TILE_WIDTH / size.x.max(size.y)).min(1.0);
+                        if ui
+                            .add(egui::ImageButton::new((texture.id(), size * scale)))
+                            .on_hover_text("Open in the editor")
+                            .clicked()
+                        {
+                            command = Some(TileCommand::Open);
+                        }
+                    }
+                    None => {
+                        ui.weak("no preview");
+                    }
+                }
+                let taken_at: DateTime<Local> = entry.metadata.taken_at.into();
+                ui.label(taken_at.format("%Y-%m-%d %H:%M:%S").to_string());
+                ui.weak(format!(
+                    "{}, {}×{}",
+                    entry.metadata.target_label,
+                    entry.region.width().round(),
+                    entry.region.height().round()
+                ));
+                if entry.edits > 0 {
+                    ui.weak(format!("{} edits", entry.edits));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Open").clicked() {
+                        command = Some(TileCommand::Open);
+                    }
+                    if ui.button("Export…").clicked() {
+                        command = Some(TileCommand::Export);
+                    }
+                    // Two clicks, nothing brings a deleted capture back
+                    if self.gallery_delete.as_deref() == Some(entry.id.as_str()) {
+                        if ui.button("Really delete?").clicked() {
+                            command = Some(TileCommand::Delete);
+                        }
+                    } else if ui.button("Delete").clicked() {
+                        self.gallery_delete = Some(entry.id.clone());
+                    }
+                });
+            });
+        });
+        command
+    }
+
+    // Loaded once, an entry without a readable thumbnail is not tried again
+    fn thumbnail(&mut self, ctx: &egui::Context, id: &str) -> Option<egui::TextureHandle> {
+        if let Some(texture) = self.thumbnails.get(id) {
+            return texture.clone();
+        }
+        let path = self.gallery.as_ref()?.thumbnail_path(id);
+        let texture = match image::open(&path) {
+            Ok(image) => Some(load_texture(ctx, id, &image.to_rgba8())),
+            Err(e) => {
+                log::warn!("thumbnail {}: {}", path.display(), e);
+                None
+            }
+        };
+        self.thumbnails.insert(id.to_string(), texture.clone());
+        texture
+    }
+
+    //------ Opens a past capture in a tab, its annotations and redactions stay editable.
+    // A capture already open is only brought to the front.
+    pub fn open_gallery_entry(&mut self, ctx: &egui::Context, id: &str) {
+        let open = self
+            .tabs
+            .iter()
+            .position(|tab| tab.gallery_entry.as_deref() == Some(id));
+        if let Some(index) = open {
+            self.select_tab(index);
+            self.state = AppState::MainApp;
+            return;
+        }
+        let Some(gallery) = self.gallery.as_ref() else {
+            return;
+        };
+        let result = gallery.load(id);
+        if let Some(capture) = self.report(result) {
+            self.open_tab(ctx, capture);
+            if let Some(tab) = self.tab_mut() {
+                tab.gallery_entry = Some(id.to_string());
+            }
+            self.state = AppState::MainApp;
+        }
+    }
+
+    //------ Save dialog for a past capture, the current one is left as it is
+    pub fn export_gallery_entry(&mut self, id: &str) {
+        let Some(gallery) = self.gallery.as_ref() else {
+            return;
+        };
+        let result = gallery
+            .load(id)
+            .and_then(|capture| self.save_as_dialog(&capture));
+        if let Some(Some(path)) = self.report(result) {
+            self.last_save_dir = path.parent().map(|dir| dir.to_path_buf());
+            self.notifications
+                .info(format!("Capture saved to {}", path.display()));
+        }
+    }
+
+    pub fn delete_gallery_entry(&mut self, id: &str) {
+        self.gallery_delete = None;
+        let Some(gallery) = self.gallery.as_mut() else {
+            return;
+        };
+        let result = gallery.delete(id);
+        if self.report(result).is_some() {
+            self.thumbnails.remove(id);
+            // Still open in its tab, but no longer stored
+            for tab in self.tabs.iter_mut() {
+                if tab.gallery_entry.as_deref() == Some(id) {
+                    tab.gallery_entry = None;
+                }
+            }
+            self.notifications.info("Capture deleted from the gallery");
+        }
+    }
+}
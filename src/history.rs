@@ -0,0 +1,257 @@
+use emath::{Rect, Vec2};
+
+use crate::annotations::Annotation;
+use crate::capture::{Capture, Transform};
+use crate::redaction::Redaction;
+
+//------ A reversible change to a capture
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Crop { before: Rect, after: Rect },
+    AddAnnotation(Annotation),
+    MoveAnnotation { index: usize, offset: Vec2 },
+    RemoveAnnotation { index: usize, annotation: Annotation },
+    AddRedaction(Redaction),
+    Transform(Transform),
+    // Everything drawn on the capture removed at once
+    Clear {
+        annotations: Vec<Annotation>,
+        redactions: Vec<Redaction>,
+    },
+}
+
+impl Edit {
+    pub fn apply(&self, capture: &mut Capture) {
+        match self {
+            Edit::Crop { after, .. } => capture.region = *after,
+            Edit::AddAnnotation(annotation) => capture.annotations.push(annotation.clone()),
+            Edit::MoveAnnotation { index, offset } => {
+                if let Some(annotation) = capture.annotations.get_mut(*index) {
+                    annotation.translate(*offset);
+                }
+            }
+            Edit::RemoveAnnotation { index, .. } => {
+                if *index < capture.annotations.len() {
+                    capture.annotations.remove(*index);
+                }
+            }
+            Edit::AddRedaction(redaction) => capture.redactions.push(redaction.clone()),
+            Edit::Transform(transform) => capture.transform(*transform),
+            Edit::Clear { .. } => {
+                capture.annotations.clear();
+                capture.redactions.clear();
+            }
+        }
+    }
+
+    pub fn revert(&self, capture: &mut Capture) {
+        match self {
+            Edit::Crop { before, .. } => capture.region = *before,
+            Edit::AddAnnotation(_) => {
+                capture.annotations.pop();
+            }
+            Edit::MoveAnnotation { index, offset } => {
+                if let Some(annotation) = capture.annotations.get_mut(*index) {
+                    annotation.translate(-*offset);
+                }
+            }
+            Edit::RemoveAnnotation { index, annotation } => {
+                let index = (*index).min(capture.annotations.len());
+                capture.annotations.insert(index, annotation.clone());
+            }
+            Edit::AddRedaction(_) => {
+                capture.redactions.pop();
+            }
+            Edit::Transform(transform) => capture.transform(transform.inverse()),
+            Edit::Clear {
+                annotations,
+                redactions,
+            } => {
+                capture.annotations = annotations.clone();
+                capture.redactions = redactions.clone();
+            }
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Edit::Crop { after, .. } => {
+                format!("Crop to {:.0} × {:.0}", after.width(), after.height())
+            }
+            Edit::AddAnnotation(annotation) => format!("Add {}", annotation.shape.name()),
+            Edit::MoveAnnotation { index, .. } => format!("Move annotation {}", index + 1),
+            Edit::RemoveAnnotation { annotation, .. } => {
+                format!("Delete {}", annotation.shape.name())
+            }
+            Edit::AddRedaction(redaction) => format!("{} area", redaction.method.name()),
+            Edit::Transform(transform) => transform.name().to_string(),
+            Edit::Clear { .. } => "Clear annotations".to_string(),
+        }
+    }
+}
+
+//------ Edits done on a capture, the first `applied` ones are in effect and the rest can be redone
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    edits: Vec<Edit>,
+    applied: usize,
+}
+
+impl History {
+    //------ Applies edit to capture, edits that were undone are forgotten
+    pub fn push(&mut self, capture: &mut Capture, edit: Edit) {
+        edit.apply(capture);
+        self.edits.truncate(self.applied);
+        self.edits.push(edit);
+        self.applied += 1;
+    }
+
+    pub fn undo(&mut self, capture: &mut Capture) -> bool {
+        if self.applied == 0 {
+            return false;
+        }
+        self.applied -= 1;
+        self.edits[self.applied].revert(capture);
+        true
+    }
+
+    pub fn redo(&mut self, capture: &mut Capture) -> bool {
+        let Some(edit) = self.edits.get(self.applied) else {
+            return false;
+        };
+        edit.apply(capture);
+        self.applied += 1;
+        true
+    }
+
+    //------ Undoes or redoes until exactly `step` edits are applied, 0 is the original capture
+    pub fn jump_to(&mut self, capture: &mut Capture, step: usize) {
+        let step = step.min(self.edits.len());
+        while self.applied > step {
+            self.undo(capture);
+        }
+        while self.applied < step {
+            self.redo(capture);
+        }
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.applied > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.applied < self.edits.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{Shape, Style};
+    use crate::redaction::Method;
+    use emath::{pos2, vec2};
+    use image::{Rgba, RgbaImage};
+
+    type State = (RgbaImage, Rect, Rect, Vec<Annotation>, Vec<Redaction>);
+
+    fn state(capture: &Capture) -> State {
+        (
+            capture.image.clone(),
+            capture.image_rect,
+            capture.region,
+            capture.annotations.clone(),
+            capture.redactions.clone(),
+        )
+    }
+
+    fn capture() -> Capture {
+        let image = RgbaImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 0, 255]));
+        Capture::from_image(image, "test")
+    }
+
+    fn annotation(shape: Shape) -> Annotation {
+        Annotation { shape, style: Style::default() }
+    }
+
+    #[test]
+    fn every_step_is_restored() {
+        let mut capture = capture();
+        let mut history = History::default();
+        let mut states = vec![state(&capture)];
+        let mut push = |capture: &mut Capture, edit: Edit| {
+            history.push(capture, edit);
+            states.push(state(capture));
+        };
+        let crop = Rect::from_min_max(pos2(1.0, 1.0), pos2(6.0, 4.0));
+        let before = capture.region;
+        push(&mut capture, Edit::Crop { before, after: crop });
+        let rectangle = Shape::Rectangle(Rect::from_min_max(pos2(2.0, 1.0), pos2(5.0, 3.0)));
+        push(&mut capture, Edit::AddAnnotation(annotation(rectangle)));
+        let arrow = Shape::Arrow { from: pos2(1.0, 1.0), to: pos2(6.0, 3.0) };
+        push(&mut capture, Edit::AddAnnotation(annotation(arrow)));
+        push(&mut capture, Edit::MoveAnnotation { index: 0, offset: vec2(1.0, 0.5) });
+        let redaction = Redaction {
+            area: Rect::from_min_max(pos2(0.0, 0.0), pos2(3.0, 2.0)),
+            method: Method::Fill,
+            style: Default::default(),
+        };
+        push(&mut capture, Edit::AddRedaction(redaction));
+        let annotation = capture.annotations[0].clone();
+        push(&mut capture, Edit::RemoveAnnotation { index: 0, annotation });
+        push(&mut capture, Edit::Transform(Transform::RotateClockwise));
+        let (annotations, redactions) = (capture.annotations.clone(), capture.redactions.clone());
+        push(&mut capture, Edit::Clear { annotations, redactions });
+
+        let last = history.edits().len();
+        assert_eq!(last, 8);
+        history.jump_to(&mut capture, 0);
+        assert_eq!(state(&capture), states[0]);
+        assert!(!history.can_undo());
+        history.jump_to(&mut capture, last);
+        assert_eq!(state(&capture), states[last]);
+        assert!(!history.can_redo());
+        // One step at a time, backwards then forwards
+        for step in (0..last).rev().chain(1..=last) {
+            history.jump_to(&mut capture, step);
+            assert_eq!(history.applied(), step);
+            assert_eq!(state(&capture), states[step], "step {step}");
+        }
+        history.jump_to(&mut capture, 3);
+        assert!(history.undo(&mut capture));
+        assert_eq!(state(&capture), states[2]);
+        assert!(history.redo(&mut capture));
+        assert_eq!(state(&capture), states[3]);
+    }
+
+    #[test]
+    fn pushing_after_undo_drops_the_redo_tail() {
+        let mut capture = capture();
+        let mut history = History::default();
+        for x in 1..=3 {
+            let shape = Shape::Line { from: pos2(0.0, 0.0), to: pos2(x as f32, 2.0) };
+            history.push(&mut capture, Edit::AddAnnotation(annotation(shape)));
+        }
+        history.undo(&mut capture);
+        history.undo(&mut capture);
+        assert!(history.can_redo());
+
+        let edit = Edit::Transform(Transform::FlipVertical);
+        history.push(&mut capture, edit.clone());
+        assert_eq!(history.edits().len(), 2);
+        assert_eq!(history.edits()[1], edit);
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut capture));
+        assert_eq!(capture.annotations.len(), 1);
+        history.jump_to(&mut capture, 0);
+        assert!(capture.annotations.is_empty());
+        assert_eq!(capture.image, self::capture().image);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::keybidings::{Action, Shortcut};
+use crate::keys::Key;
+
+//------ Shortcut that could not be grabbed, with the reason
+#[derive(Debug, Clone)]
+pub struct GrabFailure {
+    pub action: Action,
+    pub shortcut: Shortcut,
+    pub reason: String,
+}
+
+// X keysym of a key, letters are the lowercase ones the keyboard map lists first
+fn keysym(key: Key) -> Option<u32> {
+    let name = key.name();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_lowercase() as u32);
+        }
+    }
+    if let Some(number) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+        return Some(0xffbe + number - 1);
+    }
+    Some(match key {
+        Key::ArrowLeft => 0xff51,
+        Key::ArrowUp => 0xff52,
+        Key::ArrowRight => 0xff53,
+        Key::ArrowDown => 0xff54,
+        Key::Escape => 0xff1b,
+        Key::Tab => 0xff09,
+        Key::Backspace => 0xff08,
+        Key::Enter => 0xff0d,
+        Key::Space => 0x20,
+        Key::Insert => 0xff63,
+        Key::Delete => 0xffff,
+        Key::Home => 0xff50,
+        Key::End => 0xff57,
+        Key::PageUp => 0xff55,
+        Key::PageDown => 0xff56,
+        Key::Copy => 0x1008ff57,
+        Key::Cut => 0x1008ff58,
+        Key::Paste => 0x1008ff6d,
+        Key::Colon => 0x3a,
+        Key::Comma => 0x2c,
+        Key::Backslash => 0x5c,
+        Key::OpenBracket => 0x5b,
+        Key::CloseBracket => 0x5d,
+        Key::Backtick => 0x60,
+        Key::Minus => 0x2d,
+        Key::Period => 0x2e,
+        Key::Plus => 0x2b,
+        Key::Equals => 0x3d,
+        Key::Semicolon => 0x3b,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keysyms_of_letters_digits_and_named_keys() {
+        // Letters are the lowercase keysyms
+        assert_eq!(keysym(Key::A), Some(0x61));
+        assert_eq!(keysym(Key::Z), Some(0x7a));
+        assert_eq!(keysym(Key::Num0), Some(0x30));
+        assert_eq!(keysym(Key::F1), Some(0xffbe));
+        assert_eq!(keysym(Key::F12), Some(0xffc9));
+        assert_eq!(keysym(Key::F20), Some(0xffd1));
+        assert_eq!(keysym(Key::Escape), Some(0xff1b));
+        assert_eq!(keysym(Key::Plus), Some(0x2b));
+        assert_eq!(keysym(Key::ArrowDown), Some(0xff54));
+    }
+
+    #[test]
+    fn every_key_has_a_keysym() {
+        for key in Key::ALL {
+            assert!(keysym(key).is_some(), "{}", key.name());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use x11::HotkeyListener;
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+
+    use x11rb::connection::Connection;
+    use x11rb::errors::ReplyError;
+    use x11rb::protocol::xproto::{
+        ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, GrabMode, KeyButMask,
+        ModMask, Window, WindowClass,
+    };
+    use x11rb::protocol::{ErrorKind, Event};
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::COPY_FROM_PARENT;
+
+    use super::{keysym, GrabFailure};
+    use crate::error::{Error, Result};
+    use crate::keybidings::{Action, ModifierSet, Shortcut};
+
+    // Caps Lock and Num Lock must not change whether a shortcut matches
+    fn locks() -> [ModMask; 4] {
+        [
+            ModMask::from(0u16),
+            ModMask::LOCK,
+            ModMask::M2,
+            ModMask::LOCK | ModMask::M2,
+        ]
+    }
+
+    struct Grab {
+        action: Action,
+        keycode: u8,
+        modifiers: ModMask,
+    }
+
+    //------ Grabs shortcuts on the X11 root window and reports their presses.
+    // Keys are released when the listener is dropped.
+    pub struct HotkeyListener {
+        connection: Arc<RustConnection>,
+        root: Window,
+        // Receives a message when the listener is dropped, to end the thread
+        wake_window: Window,
+        grabs: Arc<Vec<Grab>>,
+        pressed: Receiver<Action>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl HotkeyListener {
+        //------ Grabs every shortcut it can, on_press runs on the listener thread after each press
+        pub fn start(
+            bindings: &[(Action, Shortcut)],
+            on_press: impl Fn() + Send + 'static,
+        ) -> Result<(HotkeyListener, Vec<GrabFailure>)> {
+            let (connection, screen) = x11rb::connect(None).map_err(hotkey_error)?;
+            let root = connection.setup().roots[screen].root;
+            let wake_window = connection.generate_id().map_err(hotkey_error)?;
+            connection
+                .create_window(
+                    COPY_FROM_PARENT as u8,
+                    wake_window,
+                    root,
+                    0,
+                    0,
+                    1,
+                    1,
+                    0,
+                    WindowClass::INPUT_ONLY,
+                    COPY_FROM_PARENT,
+                    &CreateWindowAux::new(),
+                )
+                .map_err(hotkey_error)?
+                .check()
+                .map_err(hotkey_error)?;
+
+            let mut grabs = Vec::new();
+            let mut failures = Vec::new();
+            for (action, shortcut) in bindings {
+                match grab(&connection, root, shortcut) {
+                    Ok(done) => grabs.extend(done.into_iter().map(|(keycode, modifiers)| Grab {
+                        action: *action,
+                        keycode,
+                        modifiers,
+                    })),
+                    Err(reason) => failures.push(GrabFailure {
+                        action: *action,
+                        shortcut: *shortcut,
+                        reason,
+                    }),
+                }
+            }
+            connection.flush().map_err(hotkey_error)?;
+
+            let connection = Arc::new(connection);
+            let grabs = Arc::new(grabs);
+            let (sender, pressed) = mpsc::channel();
+            let thread = {
+                let connection = connection.clone();
+                let grabs = grabs.clone();
+                thread::spawn(move || listen(&connection, &grabs, wake_window, sender, on_press))
+            };
+            Ok((
+                HotkeyListener {
+                    connection,
+                    root,
+                    wake_window,
+                    grabs,
+                    pressed,
+                    thread: Some(thread),
+                },
+                failures,
+            ))
+        }
+
+        //------ Next action pressed since the last call
+        pub fn try_recv(&self) -> Option<Action> {
+            self.pressed.try_recv().ok()
+        }
+    }
+
+    impl Drop for HotkeyListener {
+        fn drop(&mut self) {
+            for grab in self.grabs.iter() {
+                let _ = self.connection.ungrab_key(grab.keycode, self.root, grab.modifiers);
+            }
+            let wake = ClientMessageEvent::new(32, self.wake_window, 0u32, [0u32; 5]);
+            let _ = self
+                .connection
+                .send_event(false, self.wake_window, EventMask::NO_EVENT, wake);
+            let _ = self.connection.destroy_window(self.wake_window);
+            let _ = self.connection.flush();
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    // Every keycode producing the keysym, each with the lock combinations.
+    // Nothing stays grabbed when one of them fails.
+    fn grab(
+        connection: &RustConnection,
+        root: Window,
+        shortcut: &Shortcut,
+    ) -> std::result::Result<Vec<(u8, ModMask)>, String> {
+        if shortcut.then.is_some() {
+            return Err("chords cannot be global".to_string());
+        }
+        let keysym = keysym(shortcut.first.key)
+            .ok_or_else(|| format!("{} has no X11 key", shortcut.first.key.name()))?;
+        let keycodes = keycodes(connection, keysym).map_err(|e| e.to_string())?;
+        if keycodes.is_empty() {
+            return Err("the key is not on this keyboard layout".to_string());
+        }
+        let modifiers = mod_mask(shortcut.first.modifiers);
+        let mut done = Vec::new();
+        for keycode in keycodes {
+            for lock in locks() {
+                let mask = modifiers | lock;
+                let result = connection
+                    .grab_key(true, root, mask, keycode, GrabMode::ASYNC, GrabMode::ASYNC)
+                    .map_err(ReplyError::from)
+                    .and_then(|cookie| cookie.check());
+                match result {
+                    Ok(()) => done.push((keycode, mask)),
+                    Err(e) => {
+                        for (keycode, mask) in done {
+                            let _ = connection.ungrab_key(keycode, root, mask);
+                        }
+                        return Err(match e {
+                            ReplyError::X11Error(e) if e.error_kind == ErrorKind::Access => {
+                                "already taken by another application".to_string()
+                            }
+                            e => e.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(done)
+    }
+
+    fn keycodes(connection: &RustConnection, keysym: u32) -> std::result::Result<Vec<u8>, ReplyError> {
+        let setup = connection.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = connection
+            .get_keyboard_mapping(min, max - min + 1)?
+            .reply()?;
+        let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+        Ok(mapping
+            .keysyms
+            .chunks(per_keycode)
+            .enumerate()
+            // Unshifted and shifted levels, "+" is shifted on most layouts
+            .filter(|(_, syms)| syms.iter().take(2).any(|sym| *sym == keysym))
+            .map(|(index, _)| min + index as u8)
+            .collect())
+    }
+
+    fn mod_mask(modifiers: ModifierSet) -> ModMask {
+        let mut mask = ModMask::from(0u16);
+        for (held, bit) in [
+            (modifiers.ctrl, ModMask::CONTROL),
+            (modifiers.shift, ModMask::SHIFT),
+            (modifiers.alt, ModMask::M1),
+            (modifiers.super_key, ModMask::M4),
+        ] {
+            if held {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    fn listen(
+        connection: &RustConnection,
+        grabs: &[Grab],
+        wake_window: Window,
+        sender: Sender<Action>,
+        on_press: impl Fn(),
+    ) {
+        let relevant = u16::from(KeyButMask::SHIFT | KeyButMask::CONTROL | KeyButMask::MOD1 | KeyButMask::MOD4);
+        loop {
+            match connection.wait_for_event() {
+                Ok(Event::KeyPress(event)) => {
+                    let state = u16::from(event.state) & relevant;
+                    let action = grabs.iter().find(|grab| {
+                        grab.keycode == event.detail && u16::from(grab.modifiers) & relevant == state
+                    });
+                    if let Some(grab) = action {
+                        if sender.send(grab.action).is_err() {
+                            return;
+                        }
+                        on_press();
+                    }
+                }
+                Ok(Event::ClientMessage(event)) if event.window == wake_window => return,
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("global shortcuts stopped: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn hotkey_error(e: impl std::fmt::Display) -> Error {
+        Error::Hotkeys(e.to_string())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn modifiers_map_to_x11_masks() {
+            assert_eq!(mod_mask(ModifierSet::NONE), ModMask::from(0u16));
+            assert_eq!(mod_mask(ModifierSet::CTRL_SHIFT), ModMask::CONTROL | ModMask::SHIFT);
+            let alt_super = ModifierSet {
+                alt: true,
+                super_key: true,
+                ..ModifierSet::NONE
+            };
+            assert_eq!(mod_mask(alt_super), ModMask::M1 | ModMask::M4);
+        }
+    }
+}
+
+//------ Global shortcuts need X11, elsewhere starting the listener fails
+#[cfg(not(target_os = "linux"))]
+pub struct HotkeyListener;
+
+#[cfg(not(target_os = "linux"))]
+impl HotkeyListener {
+    pub fn start(
+        _bindings: &[(Action, Shortcut)],
+        _on_press: impl Fn() + Send + 'static,
+    ) -> crate::error::Result<(HotkeyListener, Vec<GrabFailure>)> {
+        Err(crate::error::Error::Hotkeys("only available on X11".to_string()))
+    }
+
+    pub fn try_recv(&self) -> Option<Action> {
+        None
+    }
+}
@@ -0,0 +1,74 @@
+use eframe::egui::{self, Align2, Color32, FontId, Id, LayerId, Order};
+use image::ImageFormat;
+use progetto_malnati::Capture;
+use rfd::FileDialog;
+use std::path::PathBuf;
+
+use super::AppState;
+use super::MyApp;
+
+// Opened images are not captures: no after-capture steps, and they are not kept in the gallery
+impl MyApp {
+    //------ Asks for image files, each one is opened in its own tab
+    pub fn open_files_dialog(&mut self, ctx: &egui::Context) {
+        let extensions: Vec<&str> = ImageFormat::all()
+            .filter(ImageFormat::reading_enabled)
+            .flat_map(|format| format.extensions_str().iter().copied())
+            .collect();
+        let directory = self.last_save_dir.as_ref().unwrap_or(&self.save_dir);
+        let files = FileDialog::new()
+            .add_filter("Images", &extensions)
+            .set_directory(directory)
+            .pick_files();
+        if let Some(paths) = files {
+            self.open_image_files(ctx, paths);
+        }
+    }
+
+    pub fn open_image_files(&mut self, ctx: &egui::Context, paths: Vec<PathBuf>) {
+        for path in paths {
+            match Capture::open(&path) {
+                Ok(capture) => {
+                    self.open_tab(ctx, capture);
+                    self.state = AppState::MainApp;
+                }
+                Err(e) => self
+                    .notifications
+                    .error(format!("Cannot open {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    pub fn paste_from_clipboard(&mut self, ctx: &egui::Context) {
+        let result = Capture::paste();
+        if let Some(capture) = self.report(result) {
+            self.open_tab(ctx, capture);
+            self.state = AppState::MainApp;
+        }
+    }
+
+    //------ Files dropped on the window, outside of a capture
+    pub fn check_dropped_files(&mut self, ctx: &egui::Context) {
+        if !matches!(self.state, AppState::MainApp | AppState::Gallery) {
+            return;
+        }
+        let (hovering, dropped) = ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone()));
+        if hovering {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file drop")));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, Color32::from_black_alpha(160));
+            painter.text(
+                screen.center(),
+                Align2::CENTER_CENTER,
+                "Drop images to open them",
+                FontId::proportional(24.0),
+                Color32::WHITE,
+            );
+        }
+        // Only files with a path, other drops carry bytes only on the web
+        let paths: Vec<PathBuf> = dropped.into_iter().filter_map(|file| file.path).collect();
+        if !paths.is_empty() {
+            self.open_image_files(ctx, paths);
+        }
+    }
+}
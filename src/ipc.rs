@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+
+//------ One JSON object per line on the control socket, answered by one Reply line
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Capture {
+        #[serde(default)]
+        mode: RemoteMode,
+        // Numbered from 1 as in list-monitors, the monitor chosen in the window when missing
+        monitor: Option<usize>,
+        // x, y, w, h relative to the monitor, captured without asking the user
+        region: Option<[f32; 4]>,
+        // Seconds, shown as the countdown of a delayed capture
+        #[serde(default)]
+        delay: u64,
+    },
+    GetLast,
+    Save {
+        path: PathBuf,
+        // Guessed from the path extension when missing
+        format: Option<String>,
+    },
+    Copy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteMode {
+    #[default]
+    FullScreen,
+    // The user selects the area, unless a region is given
+    Area,
+}
+
+//------ File holding a capture, as sent back to the client.
+// A temporary file is removed when the client disconnects
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureInfo {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    // SHA-1 of the file, in hexadecimal
+    pub sha1: String,
+}
+
+impl CaptureInfo {
+    pub fn of_file(path: &Path) -> Result<CaptureInfo> {
+        let bytes = fs::read(path)?;
+        let (width, height) = image::image_dimensions(path)?;
+        Ok(CaptureInfo {
+            path: path.to_path_buf(),
+            width,
+            height,
+            sha1: format!("{:x}", Sha1::digest(&bytes)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reply {
+    pub fn success(capture: CaptureInfo) -> Reply {
+        Reply {
+            ok: true,
+            capture: Some(capture),
+            error: None,
+        }
+    }
+
+    pub fn failure(error: impl ToString) -> Reply {
+        Reply {
+            ok: false,
+            capture: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl From<Result<CaptureInfo>> for Reply {
+    fn from(result: Result<CaptureInfo>) -> Reply {
+        match result {
+            Ok(capture) => Reply::success(capture),
+            Err(e) => Reply::failure(e),
+        }
+    }
+}
+
+//------ Socket in $XDG_RUNTIME_DIR, or in a directory of the user in the temporary directory
+// when it is not set
+pub fn socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(shared_temp_dir)
+        .join(format!("{}.sock", env!("CARGO_PKG_NAME")))
+}
+
+// Made private by the server, the temporary directory is shared by all the users
+#[cfg(unix)]
+fn shared_temp_dir() -> PathBuf {
+    // SAFETY: geteuid cannot fail and has no side effect
+    let user = unsafe { libc::geteuid() };
+    std::env::temp_dir().join(format!("{}-{}", env!("CARGO_PKG_NAME"), user))
+}
+
+#[cfg(not(unix))]
+fn shared_temp_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+#[cfg(unix)]
+pub use server::{IpcServer, PendingRequest};
+
+#[cfg(unix)]
+mod server {
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+
+    use super::{Reply, Request};
+    use crate::error::{Error, Result};
+    use crate::temp;
+
+    // Reply and the temporary file it names, if any
+    type Answer = (Reply, Option<PathBuf>);
+
+    //------ Request waiting for the app, the client is answered when reply is called.
+    // Dropping it answers with an error.
+    pub struct PendingRequest {
+        pub request: Request,
+        reply: Option<Sender<Answer>>,
+    }
+
+    impl PendingRequest {
+        pub fn reply(mut self, reply: Reply) {
+            if let Some(sender) = self.reply.take() {
+                let _ = sender.send((reply, None));
+            }
+        }
+
+        //------ Answers with a temporary file, removed once the client disconnects
+        pub fn reply_with_temporary(mut self, reply: Reply, file: PathBuf) {
+            let Some(sender) = self.reply.take() else {
+                let _ = fs::remove_file(&file);
+                return;
+            };
+            if let Err(mpsc::SendError((_, Some(file)))) = sender.send((reply, Some(file))) {
+                let _ = fs::remove_file(file);
+            }
+        }
+    }
+
+    impl Drop for PendingRequest {
+        fn drop(&mut self) {
+            if let Some(sender) = self.reply.take() {
+                let _ = sender.send((Reply::failure("the request was dropped"), None));
+            }
+        }
+    }
+
+    //------ Control socket, removed when the server is dropped
+    pub struct IpcServer {
+        path: PathBuf,
+        requests: Receiver<PendingRequest>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl IpcServer {
+        //------ Listens on path, on_request runs on the socket thread after each request.
+        // None when another instance is already listening on it
+        pub fn start(
+            path: &Path,
+            on_request: impl Fn() + Send + Sync + 'static,
+        ) -> Result<Option<IpcServer>> {
+            // Nobody else may create or replace the socket there
+            if let Some(dir) = path.parent() {
+                temp::make_private(dir)?;
+            }
+            // A socket left by a crashed instance is replaced, a live one is not
+            if UnixStream::connect(path).is_ok() {
+                return Ok(None);
+            }
+            let _ = fs::remove_file(path);
+            let listener = UnixListener::bind(path).map_err(|e| ipc_error(path, e))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+            let (sender, requests) = mpsc::channel();
+            let stop = Arc::new(AtomicBool::new(false));
+            let on_request: Arc<dyn Fn() + Send + Sync> = Arc::new(on_request);
+            let thread = {
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let Ok(stream) = stream else {
+                            continue;
+                        };
+                        let sender = sender.clone();
+                        let on_request = on_request.clone();
+                        thread::spawn(move || serve(stream, sender, on_request));
+                    }
+                })
+            };
+            Ok(Some(IpcServer {
+                path: path.to_path_buf(),
+                requests,
+                stop,
+                thread: Some(thread),
+            }))
+        }
+
+        //------ Next request received since the last call
+        pub fn try_recv(&self) -> Option<PendingRequest> {
+            self.requests.try_recv().ok()
+        }
+    }
+
+    impl Drop for IpcServer {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            // Wakes the accept loop up so it sees the flag
+            let _ = UnixStream::connect(&self.path);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    // Answers the requests of one client in order, until it disconnects
+    fn serve(stream: UnixStream, sender: Sender<PendingRequest>, on_request: Arc<dyn Fn() + Send + Sync>) {
+        let mut temporary = Vec::new();
+        answer_requests(stream, sender, on_request, &mut temporary);
+        // The client had the time to read them
+        for file in temporary {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    fn answer_requests(
+        stream: UnixStream,
+        sender: Sender<PendingRequest>,
+        on_request: Arc<dyn Fn() + Send + Sync>,
+        temporary: &mut Vec<PathBuf>,
+    ) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    let (reply, answer) = mpsc::channel();
+                    let pending = PendingRequest {
+                        request,
+                        reply: Some(reply),
+                    };
+                    if sender.send(pending).is_err() {
+                        return;
+                    }
+                    on_request();
+                    match answer.recv() {
+                        Ok((reply, file)) => {
+                            temporary.extend(file);
+                            reply
+                        }
+                        Err(_) => Reply::failure("the application is closing"),
+                    }
+                }
+                Err(e) => Reply::failure(format!("invalid request: {}", e)),
+            };
+            let Ok(text) = serde_json::to_string(&reply) else {
+                return;
+            };
+            if writeln!(writer, "{}", text).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn ipc_error(path: &Path, e: std::io::Error) -> Error {
+        Error::Ipc(format!("{}: {}", path.display(), e))
+    }
+}
+
+//------ Unix domain sockets only, elsewhere starting the server fails
+#[cfg(not(unix))]
+pub struct PendingRequest {
+    pub request: Request,
+}
+
+#[cfg(not(unix))]
+impl PendingRequest {
+    pub fn reply(self, _reply: Reply) {}
+
+    pub fn reply_with_temporary(self, _reply: Reply, file: PathBuf) {
+        let _ = fs::remove_file(file);
+    }
+}
+
+#[cfg(not(unix))]
+pub struct IpcServer;
+
+#[cfg(not(unix))]
+impl IpcServer {
+    pub fn start(
+        _path: &Path,
+        _on_request: impl Fn() + Send + Sync + 'static,
+    ) -> Result<Option<IpcServer>> {
+        Err(crate::error::Error::Ipc("only available on Unix".to_string()))
+    }
+
+    pub fn try_recv(&self) -> Option<PendingRequest> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::{env, process, thread};
+
+    #[test]
+    fn temporary_file_removed_on_disconnect() {
+        let dir = env::temp_dir().join(format!("progetto_malnati-test-{}-ipc", process::id()));
+        let socket = dir.join("test.sock");
+        let server = IpcServer::start(&socket, || {}).unwrap().unwrap();
+        // Another instance leaves the socket to the first one
+        assert!(IpcServer::start(&socket, || {}).unwrap().is_none());
+
+        let file = dir.join("last.txt");
+        fs::write(&file, "capture").unwrap();
+        let answering = {
+            let file = file.clone();
+            thread::spawn(move || loop {
+                if let Some(pending) = server.try_recv() {
+                    assert_eq!(pending.request, Request::GetLast);
+                    pending.reply_with_temporary(Reply::failure("not an image"), file);
+                    return server;
+                }
+                thread::yield_now();
+            })
+        };
+        let mut client = UnixStream::connect(&socket).unwrap();
+        writeln!(client, r#"{{"command": "get-last"}}"#).unwrap();
+        let mut line = String::new();
+        BufReader::new(client.try_clone().unwrap()).read_line(&mut line).unwrap();
+        assert!(line.contains("not an image"));
+        assert!(file.exists());
+        drop(client);
+
+        let server = answering.join().unwrap();
+        for _ in 0..100 {
+            if !file.exists() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!file.exists());
+        drop(server);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
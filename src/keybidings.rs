@@ -1,23 +1,517 @@
-use eframe::egui::Key;
-#[derive(Debug)]
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::keys::Key;
+
+//------ Modifier keys that must be held, all the others must be released.
+// egui reports Super only on macOS (as ⌘), elsewhere it can only be used by global hotkeys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ModifierSet {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl ModifierSet {
+    pub const NONE: ModifierSet = ModifierSet {
+        ctrl: false,
+        shift: false,
+        alt: false,
+        super_key: false,
+    };
+    pub const CTRL: ModifierSet = ModifierSet {
+        ctrl: true,
+        ..ModifierSet::NONE
+    };
+    pub const SHIFT: ModifierSet = ModifierSet {
+        shift: true,
+        ..ModifierSet::NONE
+    };
+    pub const CTRL_SHIFT: ModifierSet = ModifierSet {
+        ctrl: true,
+        shift: true,
+        ..ModifierSet::NONE
+    };
+
+    pub fn is_empty(&self) -> bool {
+        *self == ModifierSet::NONE
+    }
+}
+
+//------ One key pressed with a set of modifiers, e.g. Ctrl+Shift+Z
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    pub key: Key,
+    pub modifiers: ModifierSet,
+}
+
+impl KeyCombo {
+    pub fn new(modifiers: ModifierSet, key: Key) -> KeyCombo {
+        KeyCombo { key, modifiers }
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.modifiers;
+        for (held, name) in [
+            (m.ctrl, "Ctrl"),
+            (m.shift, "Shift"),
+            (m.alt, "Alt"),
+            (m.super_key, "Super"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = ModifierSet::NONE;
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        // "Ctrl++" binds the plus key
+        if text.ends_with("++") {
+            parts.pop();
+            parts.pop();
+            parts.push("+");
+        }
+        let key_name = parts.pop().unwrap_or_default();
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                "super" | "cmd" | "meta" | "win" => modifiers.super_key = true,
+                _ => return Err(format!("unknown modifier \"{}\" in \"{}\"", part, text)),
+            }
+        }
+        let key = Key::from_name(key_name)
+            .ok_or_else(|| format!("unknown key \"{}\" in \"{}\"", key_name, text))?;
+        Ok(KeyCombo { key, modifiers })
+    }
+}
+
+//------ A combination, or two in a row for a chord ("Ctrl+K S")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Shortcut {
+    pub first: KeyCombo,
+    pub then: Option<KeyCombo>,
+}
+
+impl Shortcut {
+    pub fn new(modifiers: ModifierSet, key: Key) -> Shortcut {
+        Shortcut {
+            first: KeyCombo::new(modifiers, key),
+            then: None,
+        }
+    }
+
+    pub fn chord(first: KeyCombo, then: KeyCombo) -> Shortcut {
+        Shortcut {
+            first,
+            then: Some(then),
+        }
+    }
+
+    //------ True when both cannot be told apart while typing them
+    pub fn overlaps(&self, other: &Shortcut) -> bool {
+        match (self.then, other.then) {
+            (None, None) | (Some(_), Some(_)) => self == other,
+            // A plain combination fires before the chord starting with it can go on
+            (None, Some(_)) | (Some(_), None) => self.first == other.first,
+        }
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.then {
+            Some(then) => write!(f, "{} {}", self.first, then),
+            None => write!(f, "{}", self.first),
+        }
+    }
+}
+
+impl FromStr for Shortcut {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let steps: Vec<&str> = text.split_whitespace().collect();
+        match steps[..] {
+            [first] => Ok(Shortcut {
+                first: first.parse()?,
+                then: None,
+            }),
+            [first, then] => Ok(Shortcut::chord(first.parse()?, then.parse()?)),
+            _ => Err(format!("\"{}\" must be one combination or a chord of two", text)),
+        }
+    }
+}
+
+impl TryFrom<String> for Shortcut {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Shortcut> for String {
+    fn from(shortcut: Shortcut) -> String {
+        shortcut.to_string()
+    }
+}
+
+//------ Everything the user can run, from a button, a shortcut or the command palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    New,
+    Fullscreen,
+    CaptureClipboard,
+    Delayed,
+    Record,
+    PauseRecording,
+    OpenFile,
+    Paste,
+    Area,
+    Save,
+    SaveAs,
+    Clipboard,
+    Crop,
+    Annotate,
+    Pin,
+    Undo,
+    Redo,
+    Clear,
+    EditHistory,
+    Log,
+    Settings,
+    Gallery,
+    NextTab,
+    PreviousTab,
+    CloseTab,
+    CloseSavedTabs,
+    CommandPalette,
+    Confirm,
+    Cancel,
+}
+
+impl Action {
+    pub const ALL: [Action; 29] = [
+        Action::New,
+        Action::Fullscreen,
+        Action::CaptureClipboard,
+        Action::Delayed,
+        Action::Record,
+        Action::PauseRecording,
+        Action::OpenFile,
+        Action::Paste,
+        Action::Area,
+        Action::Save,
+        Action::SaveAs,
+        Action::Clipboard,
+        Action::Crop,
+        Action::Annotate,
+        Action::Pin,
+        Action::Undo,
+        Action::Redo,
+        Action::Clear,
+        Action::EditHistory,
+        Action::Log,
+        Action::Settings,
+        Action::Gallery,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::CloseTab,
+        Action::CloseSavedTabs,
+        Action::CommandPalette,
+        Action::Confirm,
+        Action::Cancel,
+    ];
+
+    // Actions that can also be bound to a global shortcut
+    pub const GLOBAL: [Action; 3] = [Action::New, Action::Fullscreen, Action::CaptureClipboard];
+
+    //------ Stable name used in the settings file
+    pub fn id(&self) -> &'static str {
+        match self {
+            Action::New => "new",
+            Action::Fullscreen => "fullscreen",
+            Action::CaptureClipboard => "capture_clipboard",
+            Action::Delayed => "delayed",
+            Action::Record => "record",
+            Action::PauseRecording => "pause_recording",
+            Action::OpenFile => "open_file",
+            Action::Paste => "paste",
+            Action::Area => "area",
+            Action::Save => "save",
+            Action::SaveAs => "save_as",
+            Action::Clipboard => "clipboard",
+            Action::Crop => "crop",
+            Action::Annotate => "annotate",
+            Action::Pin => "pin",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Clear => "clear",
+            Action::EditHistory => "edit_history",
+            Action::Log => "log",
+            Action::Settings => "settings",
+            Action::Gallery => "gallery",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::CloseTab => "close_tab",
+            Action::CloseSavedTabs => "close_saved_tabs",
+            Action::CommandPalette => "command_palette",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::New => "New capture",
+            Action::Fullscreen => "Full screen capture",
+            Action::CaptureClipboard => "Capture full screen to clipboard",
+            Action::Delayed => "New capture after the delay",
+            Action::Record => "Record a screen area",
+            Action::PauseRecording => "Pause or resume the recording",
+            Action::OpenFile => "Open image file…",
+            Action::Paste => "Paste image from clipboard",
+            Action::Area => "Select an area",
+            Action::Save => "Save current capture",
+            Action::SaveAs => "Save current capture as…",
+            Action::Clipboard => "Copy image to clipboard",
+            Action::Crop => "Resize selection",
+            Action::Annotate => "Annotate capture",
+            Action::Pin => "Pin capture above other windows",
+            Action::Undo => "Undo last edit",
+            Action::Redo => "Redo edit",
+            Action::Clear => "Clear annotations and redactions",
+            Action::EditHistory => "Show edit history",
+            Action::Log => "Show notification log",
+            Action::Settings => "Open settings",
+            Action::Gallery => "Open capture gallery",
+            Action::NextTab => "Next tab",
+            Action::PreviousTab => "Previous tab",
+            Action::CloseTab => "Close tab",
+            Action::CloseSavedTabs => "Close all saved tabs",
+            Action::CommandPalette => "Command palette",
+            Action::Confirm => "Confirm (overlay, crop, annotations, stop recording)",
+            Action::Cancel => "Cancel / go back",
+        }
+    }
+
+    fn default_shortcut(&self, scope: Scope) -> Option<Shortcut> {
+        if scope == Scope::Global {
+            let super_shift = ModifierSet {
+                shift: true,
+                super_key: true,
+                ..ModifierSet::NONE
+            };
+            let key = match self {
+                Action::New => Key::A,
+                Action::Fullscreen => Key::F,
+                Action::CaptureClipboard => Key::C,
+                _ => return None,
+            };
+            return Some(Shortcut::new(super_shift, key));
+        }
+        let (modifiers, key) = match self {
+            Action::New => (ModifierSet::CTRL, Key::N),
+            Action::Fullscreen => (ModifierSet::CTRL, Key::F),
+            Action::Record => (ModifierSet::CTRL, Key::R),
+            Action::PauseRecording => (ModifierSet::NONE, Key::Space),
+            Action::OpenFile => (ModifierSet::CTRL, Key::O),
+            // egui swallows Ctrl+V when the clipboard holds no text, as with an image
+            Action::Paste => (ModifierSet::SHIFT, Key::Insert),
+            Action::Save => (ModifierSet::CTRL, Key::S),
+            Action::SaveAs => (ModifierSet::CTRL_SHIFT, Key::S),
+            Action::Clipboard => (ModifierSet::CTRL, Key::C),
+            Action::Crop => (ModifierSet::CTRL, Key::X),
+            Action::Annotate => (ModifierSet::CTRL, Key::E),
+            Action::Pin => (ModifierSet::CTRL_SHIFT, Key::P),
+            Action::Undo => (ModifierSet::CTRL, Key::Z),
+            Action::Redo => (ModifierSet::CTRL_SHIFT, Key::Z),
+            Action::CommandPalette => (ModifierSet::CTRL, Key::P),
+            Action::Gallery => (ModifierSet::CTRL, Key::G),
+            Action::NextTab => (ModifierSet::CTRL, Key::Tab),
+            Action::PreviousTab => (ModifierSet::CTRL_SHIFT, Key::Tab),
+            Action::CloseTab => (ModifierSet::CTRL, Key::W),
+            // Cancel and confirm need no modifier, they are meant for the overlays
+            Action::Confirm => (ModifierSet::NONE, Key::Enter),
+            Action::Cancel => (ModifierSet::NONE, Key::Escape),
+            Action::CaptureClipboard
+            | Action::Delayed
+            | Action::Area
+            | Action::Clear
+            | Action::EditHistory
+            | Action::Log
+            | Action::Settings
+            | Action::CloseSavedTabs => return None,
+        };
+        Some(Shortcut::new(modifiers, key))
+    }
+}
+
+//------ What a key press means, given the first half of a chord typed before
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyMatch {
+    Action(Action),
+    // First half of a chord, wait for the next combination
+    Pending(KeyCombo),
+    None,
+}
+
+//------ Where a shortcut works: in the window, or grabbed for the whole desktop while the
+// app is in the background. Global shortcuts are single combinations, a chord cannot be grabbed,
+// and hold a modifier, a bare key would be taken from every other application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Scope {
+    Window,
+    Global,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 2] = [Scope::Window, Scope::Global];
+
+    //------ Actions that can be bound in the scope
+    pub fn actions(&self) -> &'static [Action] {
+        match self {
+            Scope::Window => &Action::ALL,
+            Scope::Global => &Action::GLOBAL,
+        }
+    }
+
+    //------ Whether shortcut can be used in the scope at all, whatever the other bindings
+    pub fn allows(&self, shortcut: &Shortcut) -> Result<(), AssignError> {
+        match self {
+            Scope::Window => Ok(()),
+            Scope::Global if shortcut.then.is_some() => Err(AssignError::GlobalChord),
+            Scope::Global if shortcut.first.modifiers.is_empty() => {
+                Err(AssignError::GlobalWithoutModifier)
+            }
+            Scope::Global => Ok(()),
+        }
+    }
+}
+
+//------ Why a shortcut was not bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignError {
+    // Owned by another action
+    Taken(Action),
+    GlobalChord,
+    GlobalWithoutModifier,
+}
+
+impl fmt::Display for AssignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignError::Taken(owner) => write!(f, "already bound to \"{}\"", owner.name()),
+            AssignError::GlobalChord => write!(f, "a global shortcut cannot be a chord"),
+            AssignError::GlobalWithoutModifier => {
+                write!(f, "a global shortcut needs a modifier, e.g. Super")
+            }
+        }
+    }
+}
+
+//------ Shortcut of every action in every scope, stored as action id = "Ctrl+S", "" when unbound.
+// Global shortcuts go in a "global" table of the same form.
+// Ids missing from the settings file keep their default, unknown ids are ignored.
+#[derive(Debug, Clone, PartialEq)]
 pub struct KeyBindings {
-    pub save: Key,
-    pub cancel: Key,
-    pub new: Key,
-    pub crop: Key,
-    pub fullscreen: Key,
-    pub clipboard: Key,
+    shortcuts: BTreeMap<(Scope, Action), Option<Shortcut>>,
 }
 impl Default for KeyBindings {
     fn default() -> Self {
         Self {
-            save: Key::S,
-            cancel: Key::Z,
-            new: Key::N,
-            crop: Key::X,
-            fullscreen: Key::F,
-            clipboard: Key::C,
+            shortcuts: Scope::ALL
+                .into_iter()
+                .flat_map(|scope| scope.actions().iter().map(move |action| (scope, *action)))
+                .map(|(scope, action)| ((scope, action), action.default_shortcut(scope)))
+                .collect(),
+        }
+    }
+}
+
+// Table of one scope in the settings file
+fn stored_scope(bindings: &KeyBindings, scope: Scope) -> BTreeMap<&'static str, String> {
+    scope
+        .actions()
+        .iter()
+        .map(|action| {
+            let text = bindings.get(scope, *action).map(|s| s.to_string());
+            (action.id(), text.unwrap_or_default())
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Shortcut(String),
+    Scope(BTreeMap<String, String>),
+}
+
+impl Serialize for KeyBindings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let window = stored_scope(self, Scope::Window);
+        let mut map = serializer.serialize_map(Some(window.len() + 1))?;
+        for (id, text) in window {
+            map.serialize_entry(id, &text)?;
         }
+        map.serialize_entry("global", &stored_scope(self, Scope::Global))?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = BTreeMap::<String, Stored>::deserialize(deserializer)?;
+        let mut bindings = KeyBindings::default();
+        for (id, value) in stored {
+            let entries = match value {
+                Stored::Shortcut(text) => vec![(Scope::Window, id, text)],
+                Stored::Scope(table) if id == "global" => table
+                    .into_iter()
+                    .map(|(id, text)| (Scope::Global, id, text))
+                    .collect(),
+                Stored::Scope(_) => continue,
+            };
+            for (scope, id, text) in entries {
+                let Some(action) = Action::from_id(&id).filter(|a| scope.actions().contains(a))
+                else {
+                    continue;
+                };
+                let shortcut = match text.trim() {
+                    "" => None,
+                    text => {
+                        let shortcut: Shortcut = text.parse().map_err(serde::de::Error::custom)?;
+                        scope.allows(&shortcut).map_err(|e| {
+                            serde::de::Error::custom(format!("{} for {}: {}", text, id, e))
+                        })?;
+                        Some(shortcut)
+                    }
+                };
+                bindings.shortcuts.insert((scope, action), shortcut);
+            }
+        }
+        Ok(bindings)
     }
 }
 
@@ -25,11 +519,185 @@ impl KeyBindings {
     pub fn new() -> KeyBindings{
         KeyBindings::default()
     }
-    pub fn is_key_assigned(&self, key: Key) -> bool {
-        self.fullscreen == key
-            || self.new == key
-            || self.save == key
-            || self.cancel == key
-            || self.crop == key
+
+    pub fn get(&self, scope: Scope, action: Action) -> Option<Shortcut> {
+        self.shortcuts.get(&(scope, action)).copied().flatten()
+    }
+
+    //------ Action already using shortcut, or one overlapping it.
+    // A global shortcut is grabbed before the window sees the key, so scopes are checked against
+    // each other too, the same action may use the same shortcut in both.
+    pub fn conflict(&self, scope: Scope, action: Action, shortcut: &Shortcut) -> Option<Action> {
+        self.bound()
+            .filter(|((other_scope, other), _)| clashes((scope, action), (*other_scope, *other)))
+            .find(|(_, other)| other.overlaps(shortcut))
+            .map(|((_, other), _)| other)
+    }
+
+    //------ Binds shortcut to action, unless the scope refuses it or another action owns it
+    pub fn assign(
+        &mut self,
+        scope: Scope,
+        action: Action,
+        shortcut: Shortcut,
+    ) -> Result<(), AssignError> {
+        scope.allows(&shortcut)?;
+        match self.conflict(scope, action, &shortcut) {
+            Some(owner) => Err(AssignError::Taken(owner)),
+            None => {
+                self.shortcuts.insert((scope, action), Some(shortcut));
+                Ok(())
+            }
+        }
+    }
+
+    //------ Leaves the action reachable only from buttons and the command palette
+    pub fn unbind(&mut self, scope: Scope, action: Action) {
+        self.shortcuts.insert((scope, action), None);
+    }
+
+    //------ Every pair of actions sharing a shortcut, e.g. after editing the settings file by hand
+    pub fn conflicts(&self) -> Vec<(Shortcut, Action, Action)> {
+        let bound: Vec<((Scope, Action), Shortcut)> = self.bound().collect();
+        let mut pairs = Vec::new();
+        for (index, (first, shortcut)) in bound.iter().enumerate() {
+            for (second, other) in &bound[index + 1..] {
+                if clashes(*first, *second) && shortcut.overlaps(other) {
+                    pairs.push((*shortcut, first.1, second.1));
+                }
+            }
+        }
+        pairs
+    }
+
+    //------ Meaning of combo in the window, pending is the first half of a chord pressed just before
+    pub fn resolve(&self, pending: Option<KeyCombo>, combo: KeyCombo) -> KeyMatch {
+        let window = || self.scope(Scope::Window);
+        if let Some(pending) = pending {
+            let chord = window()
+                .find(|(_, shortcut)| shortcut.first == pending && shortcut.then == Some(combo));
+            if let Some((action, _)) = chord {
+                return KeyMatch::Action(action);
+            }
+        }
+        let single =
+            window().find(|(_, shortcut)| shortcut.then.is_none() && shortcut.first == combo);
+        if let Some((action, _)) = single {
+            return KeyMatch::Action(action);
+        }
+        if window().any(|(_, shortcut)| shortcut.then.is_some() && shortcut.first == combo) {
+            return KeyMatch::Pending(combo);
+        }
+        KeyMatch::None
+    }
+
+    //------ Bound shortcuts of one scope, e.g. the global ones to grab
+    pub fn scope(&self, scope: Scope) -> impl Iterator<Item = (Action, Shortcut)> + '_ {
+        self.bound()
+            .filter(move |((s, _), _)| *s == scope)
+            .map(|((_, action), shortcut)| (action, shortcut))
+    }
+
+    fn bound(&self) -> impl Iterator<Item = ((Scope, Action), Shortcut)> + '_ {
+        self.shortcuts
+            .iter()
+            .filter_map(|(binding, shortcut)| Some((*binding, (*shortcut)?)))
+    }
+}
+
+// Two bindings that must not share a shortcut
+fn clashes(first: (Scope, Action), second: (Scope, Action)) -> bool {
+    first != second && (first.0 == second.0 || first.1 != second.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcut(text: &str) -> Shortcut {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn scopes_are_written_and_read_back() {
+        let mut bindings = KeyBindings::default();
+        bindings.assign(Scope::Window, Action::Save, shortcut("Ctrl+K S")).unwrap();
+        bindings.unbind(Scope::Global, Action::Fullscreen);
+        let text = toml::to_string(&bindings).unwrap();
+        assert!(text.contains("save = \"Ctrl+K S\""));
+        assert!(text.contains("[global]"));
+        assert!(text.contains("new = \"Shift+Super+A\""));
+        assert_eq!(toml::from_str::<KeyBindings>(&text).unwrap(), bindings);
+    }
+
+    #[test]
+    fn unknown_and_out_of_scope_ids_are_ignored() {
+        let text = "save = \"Ctrl+W\"\nmystery = \"Ctrl+M\"\n[global]\nsave = \"Alt+S\"\n";
+        let bindings: KeyBindings = toml::from_str(text).unwrap();
+        assert_eq!(bindings.get(Scope::Window, Action::Save), Some(shortcut("Ctrl+W")));
+        assert_eq!(bindings.get(Scope::Global, Action::Save), None);
+        assert_eq!(
+            bindings.get(Scope::Global, Action::New),
+            KeyBindings::default().get(Scope::Global, Action::New)
+        );
+    }
+
+    #[test]
+    fn conflicts_are_checked_across_scopes() {
+        let mut bindings = KeyBindings::default();
+        // Same action in both scopes is fine
+        assert_eq!(bindings.assign(Scope::Global, Action::New, shortcut("Ctrl+N")), Ok(()));
+        // A global grab would hide the window shortcut of another action
+        assert_eq!(
+            bindings.assign(Scope::Global, Action::Fullscreen, shortcut("Ctrl+S")),
+            Err(AssignError::Taken(Action::Save))
+        );
+        assert_eq!(
+            bindings.assign(Scope::Window, Action::Save, shortcut("Shift+Super+C")),
+            Err(AssignError::Taken(Action::CaptureClipboard))
+        );
+        assert!(bindings.conflicts().is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn global_shortcuts_are_single_combinations_with_modifiers() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.assign(Scope::Global, Action::New, shortcut("Super+K N")),
+            Err(AssignError::GlobalChord)
+        );
+        assert_eq!(
+            bindings.assign(Scope::Global, Action::New, shortcut("F9")),
+            Err(AssignError::GlobalWithoutModifier)
+        );
+        assert_eq!(bindings, KeyBindings::default());
+        // Both are fine in the window
+        bindings.assign(Scope::Window, Action::Log, shortcut("Ctrl+K L")).unwrap();
+        bindings.assign(Scope::Window, Action::Settings, shortcut("F9")).unwrap();
+        bindings.assign(Scope::Global, Action::New, shortcut("Alt+F9")).unwrap();
+    }
+
+    #[test]
+    fn global_chords_and_bare_keys_are_refused_when_loading() {
+        for text in ["[global]\nnew = \"Super+K N\"\n", "[global]\nnew = \"F12\"\n"] {
+            let error = toml::from_str::<KeyBindings>(text).unwrap_err().to_string();
+            assert!(error.contains("global shortcut"), "{error}");
+        }
+        let window = "log = \"Ctrl+K L\"\nsettings = \"F9\"\n[global]\nnew = \"Alt+F12\"\n";
+        let bindings: KeyBindings = toml::from_str(window).unwrap();
+        assert_eq!(bindings.get(Scope::Global, Action::New), Some(shortcut("Alt+F12")));
+    }
+
+    #[test]
+    fn window_shortcuts_resolve_chords() {
+        let mut bindings = KeyBindings::default();
+        bindings.assign(Scope::Window, Action::Log, shortcut("Ctrl+K L")).unwrap();
+        let first = shortcut("Ctrl+K").first;
+        let second = shortcut("L").first;
+        assert_eq!(bindings.resolve(None, first), KeyMatch::Pending(first));
+        assert_eq!(bindings.resolve(Some(first), second), KeyMatch::Action(Action::Log));
+        // Global shortcuts are not handled by the window
+        let global = shortcut("Shift+Super+A").first;
+        assert_eq!(bindings.resolve(None, global), KeyMatch::None);
+    }
+}
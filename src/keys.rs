@@ -0,0 +1,391 @@
+//------ Keys that can be bound to actions. Names are the ones egui uses, so the interface
+// converts its keys with Key::from_name(key.name()).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    // Arrows
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+
+    Escape,
+    Tab,
+    Backspace,
+    Enter,
+    Space,
+
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+
+    Copy,
+    Cut,
+    Paste,
+
+    // Punctuation
+    Colon,
+    Comma,
+    Minus,
+    Period,
+    Plus,
+    Equals,
+    Semicolon,
+    Backslash,
+    OpenBracket,
+    CloseBracket,
+    Backtick,
+
+    // Digits, from the main row or the numpad
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+
+    // Letters
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+
+    // Function keys
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+}
+
+impl Key {
+    pub const ALL: [Key; 85] = [
+        Key::ArrowDown,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+        Key::ArrowUp,
+        Key::Escape,
+        Key::Tab,
+        Key::Backspace,
+        Key::Enter,
+        Key::Space,
+        Key::Insert,
+        Key::Delete,
+        Key::Home,
+        Key::End,
+        Key::PageUp,
+        Key::PageDown,
+        Key::Copy,
+        Key::Cut,
+        Key::Paste,
+        Key::Colon,
+        Key::Comma,
+        Key::Minus,
+        Key::Period,
+        Key::Plus,
+        Key::Equals,
+        Key::Semicolon,
+        Key::Backslash,
+        Key::OpenBracket,
+        Key::CloseBracket,
+        Key::Backtick,
+        Key::Num0,
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+        Key::Num6,
+        Key::Num7,
+        Key::Num8,
+        Key::Num9,
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+        Key::F1,
+        Key::F2,
+        Key::F3,
+        Key::F4,
+        Key::F5,
+        Key::F6,
+        Key::F7,
+        Key::F8,
+        Key::F9,
+        Key::F10,
+        Key::F11,
+        Key::F12,
+        Key::F13,
+        Key::F14,
+        Key::F15,
+        Key::F16,
+        Key::F17,
+        Key::F18,
+        Key::F19,
+        Key::F20,
+    ];
+
+    //------ Human-readable English name, e.g. "Down" for ArrowDown
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::ArrowDown => "Down",
+            Key::ArrowLeft => "Left",
+            Key::ArrowRight => "Right",
+            Key::ArrowUp => "Up",
+            Key::Escape => "Escape",
+            Key::Tab => "Tab",
+            Key::Backspace => "Backspace",
+            Key::Enter => "Enter",
+            Key::Space => "Space",
+            Key::Insert => "Insert",
+            Key::Delete => "Delete",
+            Key::Home => "Home",
+            Key::End => "End",
+            Key::PageUp => "PageUp",
+            Key::PageDown => "PageDown",
+            Key::Copy => "Copy",
+            Key::Cut => "Cut",
+            Key::Paste => "Paste",
+            Key::Colon => "Colon",
+            Key::Comma => "Comma",
+            Key::Minus => "Minus",
+            Key::Period => "Period",
+            Key::Plus => "Plus",
+            Key::Equals => "Equals",
+            Key::Semicolon => "Semicolon",
+            Key::Backslash => "Backslash",
+            Key::OpenBracket => "OpenBracket",
+            Key::CloseBracket => "CloseBracket",
+            Key::Backtick => "Backtick",
+            Key::Num0 => "0",
+            Key::Num1 => "1",
+            Key::Num2 => "2",
+            Key::Num3 => "3",
+            Key::Num4 => "4",
+            Key::Num5 => "5",
+            Key::Num6 => "6",
+            Key::Num7 => "7",
+            Key::Num8 => "8",
+            Key::Num9 => "9",
+            Key::A => "A",
+            Key::B => "B",
+            Key::C => "C",
+            Key::D => "D",
+            Key::E => "E",
+            Key::F => "F",
+            Key::G => "G",
+            Key::H => "H",
+            Key::I => "I",
+            Key::J => "J",
+            Key::K => "K",
+            Key::L => "L",
+            Key::M => "M",
+            Key::N => "N",
+            Key::O => "O",
+            Key::P => "P",
+            Key::Q => "Q",
+            Key::R => "R",
+            Key::S => "S",
+            Key::T => "T",
+            Key::U => "U",
+            Key::V => "V",
+            Key::W => "W",
+            Key::X => "X",
+            Key::Y => "Y",
+            Key::Z => "Z",
+            Key::F1 => "F1",
+            Key::F2 => "F2",
+            Key::F3 => "F3",
+            Key::F4 => "F4",
+            Key::F5 => "F5",
+            Key::F6 => "F6",
+            Key::F7 => "F7",
+            Key::F8 => "F8",
+            Key::F9 => "F9",
+            Key::F10 => "F10",
+            Key::F11 => "F11",
+            Key::F12 => "F12",
+            Key::F13 => "F13",
+            Key::F14 => "F14",
+            Key::F15 => "F15",
+            Key::F16 => "F16",
+            Key::F17 => "F17",
+            Key::F18 => "F18",
+            Key::F19 => "F19",
+            Key::F20 => "F20",
+        }
+    }
+
+    //------ Parses name() output, a few aliases and single characters ("-" or "Minus")
+    pub fn from_name(name: &str) -> Option<Key> {
+        Some(match name {
+            "ArrowDown" | "Down" => Key::ArrowDown,
+            "ArrowLeft" | "Left" => Key::ArrowLeft,
+            "ArrowRight" | "Right" => Key::ArrowRight,
+            "ArrowUp" | "Up" => Key::ArrowUp,
+            "Escape" | "Esc" => Key::Escape,
+            "Tab" => Key::Tab,
+            "Backspace" => Key::Backspace,
+            "Enter" | "Return" => Key::Enter,
+            "Space" | " " => Key::Space,
+            "Insert" | "Help" => Key::Insert,
+            "Delete" => Key::Delete,
+            "Home" => Key::Home,
+            "End" => Key::End,
+            "PageUp" => Key::PageUp,
+            "PageDown" => Key::PageDown,
+            "Copy" => Key::Copy,
+            "Cut" => Key::Cut,
+            "Paste" => Key::Paste,
+            "Colon" | ":" => Key::Colon,
+            "Comma" | "," => Key::Comma,
+            "Minus" | "-" | "−" => Key::Minus,
+            "Period" | "." => Key::Period,
+            "Plus" | "+" => Key::Plus,
+            "Equals" | "=" => Key::Equals,
+            "Semicolon" | ";" => Key::Semicolon,
+            "Backslash" | "\\" => Key::Backslash,
+            "OpenBracket" | "[" => Key::OpenBracket,
+            "CloseBracket" | "]" => Key::CloseBracket,
+            "Backtick" | "`" => Key::Backtick,
+            "0" => Key::Num0,
+            "1" => Key::Num1,
+            "2" => Key::Num2,
+            "3" => Key::Num3,
+            "4" => Key::Num4,
+            "5" => Key::Num5,
+            "6" => Key::Num6,
+            "7" => Key::Num7,
+            "8" => Key::Num8,
+            "9" => Key::Num9,
+            "a" | "A" => Key::A,
+            "b" | "B" => Key::B,
+            "c" | "C" => Key::C,
+            "d" | "D" => Key::D,
+            "e" | "E" => Key::E,
+            "f" | "F" => Key::F,
+            "g" | "G" => Key::G,
+            "h" | "H" => Key::H,
+            "i" | "I" => Key::I,
+            "j" | "J" => Key::J,
+            "k" | "K" => Key::K,
+            "l" | "L" => Key::L,
+            "m" | "M" => Key::M,
+            "n" | "N" => Key::N,
+            "o" | "O" => Key::O,
+            "p" | "P" => Key::P,
+            "q" | "Q" => Key::Q,
+            "r" | "R" => Key::R,
+            "s" | "S" => Key::S,
+            "t" | "T" => Key::T,
+            "u" | "U" => Key::U,
+            "v" | "V" => Key::V,
+            "w" | "W" => Key::W,
+            "x" | "X" => Key::X,
+            "y" | "Y" => Key::Y,
+            "z" | "Z" => Key::Z,
+            "F1" => Key::F1,
+            "F2" => Key::F2,
+            "F3" => Key::F3,
+            "F4" => Key::F4,
+            "F5" => Key::F5,
+            "F6" => Key::F6,
+            "F7" => Key::F7,
+            "F8" => Key::F8,
+            "F9" => Key::F9,
+            "F10" => Key::F10,
+            "F11" => Key::F11,
+            "F12" => Key::F12,
+            "F13" => Key::F13,
+            "F14" => Key::F14,
+            "F15" => Key::F15,
+            "F16" => Key::F16,
+            "F17" => Key::F17,
+            "F18" => Key::F18,
+            "F19" => Key::F19,
+            "F20" => Key::F20,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for key in Key::ALL {
+            assert_eq!(Key::from_name(key.name()), Some(key), "{}", key.name());
+        }
+        assert_eq!(Key::from_name("Esc"), Some(Key::Escape));
+        assert_eq!(Key::from_name("+"), Some(Key::Plus));
+        assert_eq!(Key::from_name("q"), Some(Key::Q));
+        assert_eq!(Key::from_name("Hyper"), None);
+    }
+}
@@ -0,0 +1,37 @@
+//! Capture, cropping, annotation, redaction, edit history, export, screen recording, file naming and key-binding model of the screen capture tool,
+//! independent from the egui interface: geometry and colours are the plain emath and ecolor types.
+//! The desktop integration (tray, notifications, control socket, global shortcuts) needs the
+//! `desktop` feature.
+pub mod annotations;
+pub mod capture;
+pub mod clipboard;
+#[cfg(feature = "desktop")]
+pub mod desktop_notification;
+pub mod error;
+pub mod export;
+pub mod gallery;
+pub mod history;
+#[cfg(feature = "desktop")]
+pub mod hotkeys;
+#[cfg(feature = "desktop")]
+pub mod ipc;
+pub mod keybidings;
+pub mod keys;
+pub mod monitors;
+pub mod naming;
+pub mod recording;
+pub mod redaction;
+pub mod temp;
+#[cfg(feature = "desktop")]
+pub mod tray;
+
+pub use annotations::{Annotation, Shape, Style, Tool};
+pub use capture::{Capture, CaptureMetadata, CaptureSource, Transform};
+pub use error::{Error, Result};
+pub use export::{ExportFormat, ExportOptions};
+pub use gallery::Gallery;
+pub use history::{Edit, History};
+pub use keybidings::{KeyBindings, Scope};
+pub use keys::Key;
+pub use monitors::{CaptureTarget, Monitor};
+pub use redaction::{Redaction, RedactionStyle};
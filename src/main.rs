@@ -1,90 +1,360 @@
-use eframe::egui::{self, Pos2, Rect, Vec2};
+use clap::Parser;
+use eframe::egui::{self, Pos2, Rect};
 use eframe::epaint::Rgba;
-mod keybidings;
-use keybidings::KeyBindings;
+use progetto_malnati::hotkeys::HotkeyListener;
+use progetto_malnati::keybidings::{Action, KeyCombo, Scope, Shortcut};
+use progetto_malnati::ipc::{self, IpcServer};
+use progetto_malnati::recording::Recorder;
+use progetto_malnati::gallery::GalleryOptions;
+use progetto_malnati::tray::TrayIcon;
+use progetto_malnati::{
+    annotations, capture, redaction, Capture, CaptureSource, CaptureTarget, ExportFormat,
+    ExportOptions, Gallery, KeyBindings, Monitor, RedactionStyle,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+mod actions;
+mod after_capture;
 mod app_visuals_states;
 mod application;
+mod cli;
+mod config;
+mod gallery_view;
+mod import;
+mod notifications;
+mod palette;
+mod pins;
+mod remote;
+mod screen_recording;
+mod tabs;
+use after_capture::{AfterCaptureConfig, CaptureMode};
+use config::{Config, RecordingConfig};
+use notifications::Notifications;
+use palette::CommandPalette;
+use pins::Pin;
+use remote::RemoteCapture;
+use screen_recording::RecordingExport;
+use tabs::Tab;
 
-fn main() -> Result<(), eframe::Error> {
+fn main() -> ExitCode {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    // Headless commands never open the window
+    let cli = cli::Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run(command);
+    }
+    // A broken settings file must not prevent the app from starting
+    let (config, problem) = match config::load() {
+        Ok((config, rejected)) if rejected.is_empty() => (config, None),
+        Ok((config, rejected)) => (
+            config,
+            Some(format!("Invalid settings ignored: {}", rejected.join(", "))),
+        ),
+        Err(e) => (Config::default(), Some(format!("Settings not loaded, {}", e))),
+    };
+    // Only the first of two actions sharing a shortcut would ever run
+    let clashes: Vec<String> = config
+        .key_bindings
+        .conflicts()
+        .iter()
+        .map(|(shortcut, first, second)| {
+            format!(
+                "{} is bound to both \"{}\" and \"{}\"",
+                shortcut,
+                first.name(),
+                second.name()
+            )
+        })
+        .collect();
+    let daemon = cli.daemon || config.window.daemon;
+    let problem = match (problem, clashes.is_empty()) {
+        (problem, true) => problem,
+        (None, false) => Some(clashes.join("\n")),
+        (Some(problem), false) => Some(format!("{}\n{}", problem, clashes.join("\n"))),
+    };
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_decorations(true)
+        .with_min_inner_size([400.0, 200.0])
+        .with_resizable(false)
+        .with_transparent(true)
+        .with_drag_and_drop(true);
+    if config.window.always_on_top {
+        viewport = viewport.with_always_on_top();
+    }
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_decorations(true)
-            .with_min_inner_size([400.0, 200.0])
-            .with_always_on_top()
-            .with_resizable(false)
-            .with_transparent(true),
-
+        viewport,
         ..Default::default()
     };
-    eframe::run_native(
+    let result = eframe::run_native(
         "Screen Capture",
         options,
-        Box::new(|_cc| Box::<MyApp>::default()),
-    )
+        Box::new(move |cc| {
+            let mut app = MyApp::default();
+            app.apply_config(config);
+            app.saved_config = app.config();
+            if let Some(problem) = problem {
+                app.notifications.error(problem);
+            }
+            if let Some(dir) = Gallery::default_dir() {
+                let result = Gallery::open(&dir, app.gallery_options);
+                app.gallery = app.report(result);
+            }
+            // Other programs ask for captures on it, see ipc::Request.
+            // The first instance keeps it, the others run without
+            if daemon || app.control_socket {
+                let repaint = cc.egui_ctx.clone();
+                let path = ipc::socket_path();
+                match IpcServer::start(&path, move || repaint.request_repaint()) {
+                    Ok(None) => log::info!("another instance listens on {}", path.display()),
+                    result => app.ipc = app.report(result).flatten(),
+                }
+            }
+            if daemon {
+                // Without a tray the hidden window could not be reached, it stays visible
+                let repaint = cc.egui_ctx.clone();
+                let result = TrayIcon::start(&TRAY_ACTIONS, move || repaint.request_repaint());
+                app.tray = app.report(result);
+                app.hide_on_start = app.tray.is_some();
+            }
+            Box::new(app)
+        }),
+    );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-#[derive(Debug)]
+// Offered in the tray menu
+const TRAY_ACTIONS: [Action; 5] = [
+    Action::New,
+    Action::Fullscreen,
+    Action::CaptureClipboard,
+    Action::Delayed,
+    Action::Record,
+];
+
+#[derive(Debug, Default, PartialEq)]
 enum AppState {
+    #[default]
     MainApp,
     NewCapture,
     Selection,
     Crop,
     Settings,
+    Countdown,
+    Freezing,
+    Annotate,
+    Gallery,
+    // The window is a small control panel while the selected area is recorded
+    Recording,
 }
-impl Default for AppState {
-    fn default() -> Self {
-        AppState::MainApp
+
+//------ Kind of capture started when the delay of "New capture after" expires
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+enum DelayedMode {
+    #[default]
+    FullScreen,
+    Area,
+}
+impl DelayedMode {
+    fn label(&self) -> &'static str {
+        match self {
+            DelayedMode::FullScreen => "Full screen",
+            DelayedMode::Area => "Area",
+        }
     }
 }
 
-#[derive(Debug)]
+//------ Part of the crop selection grabbed by the pointer
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum TouchedFrame {
     None,
     Bottom,
     Top,
     Right,
     Left,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    // Dragging the interior moves the whole selection
+    Inside,
+}
+
+//------ What dragging on the image does in the annotation editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditorTool {
+    // Picks an annotation to move or delete
+    Select,
+    Draw(annotations::Tool),
+    Redact(redaction::Method),
+}
+impl Default for EditorTool {
+    fn default() -> Self {
+        EditorTool::Draw(annotations::Tool::default())
+    }
 }
 
 struct MyApp {
     state: AppState,
     selected_area: [Pos2; 2],
-    texture: Option<egui::TextureHandle>,
-    capture: bool,
-    crop: bool,
-    image: Option<image::ImageBuffer<image::Rgba<u8>, Vec<u8>>>,
+    capture_requested: bool,
+    // Captures open in the editor, each with its own edits
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    // Unsaved tab whose close button was clicked, waiting for the confirmation
+    confirm_close: Option<usize>,
     area: bool,
-    button_position: Pos2,
-    dimensions: Vec2,
-    resizing: bool,
-    frame: TouchedFrame,
+    // Where the crop editor shows the image of the active tab
     display_rect: Rect,
     shrink_factor: f32,
     min_pos_top: Pos2,
     key_bindings: KeyBindings,
+    // First half of a chord and when it was pressed
+    pending_chord: Option<(KeyCombo, Instant)>,
+    // Action whose shortcut is being changed in the settings, true for a chord
+    recording_shortcut: Option<(Scope, Action, bool)>,
+    recorded_first: Option<KeyCombo>,
+    // Global scope of key_bindings is grabbed
+    global_hotkeys: bool,
+    hotkeys: Option<HotkeyListener>,
+    // Shortcuts the listener was started with, None when nothing is grabbed
+    grabbed_bindings: Option<Vec<(Action, Shortcut)>>,
+    // Next capture goes straight to the clipboard
+    copy_next_capture: bool,
+    command_palette: Option<CommandPalette>,
+    // Running in the tray: the window is hidden whenever there is nothing to capture or edit
+    tray: Option<TrayIcon>,
+    hide_on_start: bool,
+    // Set by a new capture: shown in the editor or the window gets out of the way
+    reveal_editor: Option<bool>,
+    ipc: Option<IpcServer>,
+    remote_capture: Option<RemoteCapture>,
+    // Quit from the tray, the close request must not just hide the window
+    quitting: bool,
     delay: u64,
+    delayed_mode: DelayedMode,
+    countdown_end: Option<Instant>,
+    // Area is selected on a still copy of the screen captured beforehand
+    freeze_frame: bool,
+    frozen: Option<Capture>,
+    frozen_texture: Option<egui::TextureHandle>,
+    capture_source: Box<dyn CaptureSource>,
+    monitors: Vec<Monitor>,
+    capture_target: CaptureTarget,
+    notifications: Notifications,
+    editor_tool: EditorTool,
+    annotation_style: annotations::Style,
+    annotation_text: String,
+    redaction_style: RedactionStyle,
+    show_edit_history: bool,
+    // "Save" writes here without asking, "Save as" opens the dialog
+    save_dir: PathBuf,
+    file_template: String,
+    save_format: ExportFormat,
+    after_capture: AfterCaptureConfig,
+    // Chooses the steps run on the next capture
+    capture_mode: CaptureMode,
+    export_options: ExportOptions,
+    last_save_dir: Option<PathBuf>,
+    always_on_top: bool,
+    // Start in the tray next time, the running mode is given by `tray`
+    daemon: bool,
+    // Listen on the control socket next time, always done in the background
+    control_socket: bool,
+    // Past captures, None when they cannot be stored
+    gallery: Option<Gallery>,
+    // Given to the gallery once the settings are done changing
+    gallery_options: GalleryOptions,
+    thumbnails: HashMap<String, Option<egui::TextureHandle>>,
+    // Entry whose delete button was clicked once
+    gallery_delete: Option<String>,
+    // Captures shown in their own always on top windows
+    pins: Vec<Pin>,
+    next_pin: u64,
+    recording: RecordingConfig,
+    // The area selected next is recorded instead of captured
+    record_next_selection: bool,
+    recorder: Option<Recorder>,
+    // Stopped recordings being encoded in the background
+    recording_exports: Vec<RecordingExport>,
+    // Last settings written to disk, compared every frame to detect changes
+    saved_config: Config,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
+        let capture_source = capture::default_source();
+        let monitors = capture_source.monitors().unwrap_or_default();
         Self {
             key_bindings: KeyBindings::new(),
+            pending_chord: None,
+            recording_shortcut: None,
+            recorded_first: None,
+            global_hotkeys: true,
+            hotkeys: None,
+            grabbed_bindings: None,
+            copy_next_capture: false,
+            command_palette: None,
+            tray: None,
+            hide_on_start: false,
+            reveal_editor: None,
+            quitting: false,
+            ipc: None,
+            remote_capture: None,
             state: AppState::MainApp,
-            button_position: Pos2::new(300.0, 300.0),
-            dimensions: Vec2::new(100.0, 100.0),
-            resizing: false,
-            frame: TouchedFrame::None,
             selected_area: [Pos2::ZERO, Pos2::ZERO],
-            texture: None,
-            capture: false,
-            image: None,
+            capture_requested: false,
+            tabs: Vec::new(),
+            active_tab: 0,
+            confirm_close: None,
             area: false,
-            crop: false,
             display_rect: egui::Rect::ZERO,
             shrink_factor: 0.0,
             min_pos_top: Pos2::ZERO,
             delay: 0,
+            delayed_mode: DelayedMode::default(),
+            countdown_end: None,
+            freeze_frame: false,
+            frozen: None,
+            frozen_texture: None,
+            capture_source,
+            monitors,
+            capture_target: CaptureTarget::default(),
+            notifications: Notifications::default(),
+            editor_tool: EditorTool::default(),
+            annotation_style: annotations::Style::default(),
+            annotation_text: String::new(),
+            redaction_style: RedactionStyle::default(),
+            show_edit_history: false,
+            save_dir: config::default_save_dir(),
+            file_template: progetto_malnati::naming::DEFAULT_TEMPLATE.to_string(),
+            save_format: ExportFormat::Png,
+            after_capture: AfterCaptureConfig::default(),
+            capture_mode: CaptureMode::default(),
+            export_options: ExportOptions::default(),
+            last_save_dir: None,
+            always_on_top: true,
+            daemon: false,
+            control_socket: false,
+            gallery: None,
+            gallery_options: GalleryOptions::default(),
+            thumbnails: HashMap::new(),
+            gallery_delete: None,
+            pins: Vec::new(),
+            next_pin: 0,
+            recording: RecordingConfig::default(),
+            record_next_selection: false,
+            recorder: None,
+            recording_exports: Vec::new(),
+            saved_config: Config::default(),
         }
     }
 }
@@ -95,9 +365,16 @@ impl eframe::App for MyApp {
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // eframe shows the window after the first frame, hidden again right away
+        if std::mem::take(&mut self.hide_on_start) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+        self.check_tray_commands(ctx);
+        self.check_remote_requests(ctx);
+        self.hide_on_close(ctx);
         self.check_shortcut_press(ctx);
-        let monitor_size: Vec2 = ctx.input(|i| i.viewport().monitor_size.unwrap());
-        let monitor_rect = Rect::from_min_size(Pos2::ZERO, monitor_size);
+        self.refresh_hotkeys(ctx);
+        self.check_hotkey_press(ctx);
         match self.state {
             AppState::MainApp => {
                 self.main_state_visuals(ctx);
@@ -109,11 +386,50 @@ impl eframe::App for MyApp {
                 self.selection_state_visuals(ctx);
             }
             AppState::Crop => {
-                self.crop_state_visuals(ctx, monitor_rect);
+                self.crop_state_visuals(ctx);
             }
             AppState::Settings => {
                 self.settings_state_visuals(ctx);
             }
+            AppState::Countdown => {
+                self.countdown_state_visuals(ctx);
+            }
+            AppState::Freezing => {
+                self.freezing_state_visuals(ctx);
+            }
+            AppState::Annotate => {
+                self.annotate_state_visuals(ctx);
+            }
+            AppState::Gallery => {
+                self.gallery_state_visuals(ctx);
+            }
+            AppState::Recording => {
+                self.recording_state_visuals(ctx);
+            }
+        }
+        self.check_recording_exports();
+        self.check_dropped_files(ctx);
+        self.close_palette_if_unavailable();
+        self.command_palette_window(ctx);
+        // Nothing must be drawn over the screen while it is captured
+        if !matches!(
+            self.state,
+            AppState::Selection | AppState::Freezing | AppState::Recording
+        ) {
+            self.notifications.show(ctx);
+        }
+        if matches!(self.state, AppState::MainApp | AppState::Annotate) {
+            self.edit_history_window(ctx);
+            self.close_tab_dialog(ctx);
+        }
+        if self.state == AppState::MainApp {
+            self.pins_window(ctx);
+        }
+        self.show_pins(ctx);
+        // Written once the change is done, not at every step of a dragged slider
+        if !ctx.input(|i| i.pointer.any_down()) {
+            self.persist_config();
+            self.persist_gallery_entry();
         }
     }
 }
@@ -0,0 +1,298 @@
+use emath::{Pos2, Rect, Vec2};
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::capture::CaptureSource;
+use crate::error::{Error, Result};
+
+//------ What the next capture covers: one monitor or every monitor stitched together
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CaptureTarget {
+    Monitor(usize),
+    AllScreens,
+}
+impl Default for CaptureTarget {
+    fn default() -> Self {
+        CaptureTarget::Monitor(0)
+    }
+}
+
+//------ Geometry of a monitor in virtual-desktop coordinates (logical points, origin may be negative)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Monitor {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl Monitor {
+    pub fn rect(&self) -> Rect {
+        Rect::from_min_size(
+            Pos2::new(self.x as f32, self.y as f32),
+            Vec2::new(self.width as f32, self.height as f32),
+        )
+    }
+
+    pub fn label(&self, index: usize) -> String {
+        let primary = if self.is_primary { " (primary)" } else { "" };
+        format!(
+            "Monitor {}{}: {}x{} at {},{}",
+            index + 1,
+            primary,
+            self.width,
+            self.height,
+            self.x,
+            self.y
+        )
+    }
+}
+
+pub fn target_label(monitors: &[Monitor], target: CaptureTarget) -> String {
+    match target {
+        CaptureTarget::Monitor(index) => match monitors.get(index) {
+            Some(monitor) => monitor.label(index),
+            None => format!("Monitor {}", index + 1),
+        },
+        CaptureTarget::AllScreens => "All screens".to_string(),
+    }
+}
+
+//------ Bounding box of every monitor
+pub fn virtual_desktop_rect(monitors: &[Monitor]) -> Rect {
+    monitors
+        .iter()
+        .map(Monitor::rect)
+        .reduce(|a, b| a.union(b))
+        .unwrap_or(Rect::ZERO)
+}
+
+//------ Area of the virtual desktop covered by the target
+pub fn target_rect(monitors: &[Monitor], target: CaptureTarget) -> Rect {
+    match target {
+        CaptureTarget::Monitor(index) => {
+            monitors.get(index).map(Monitor::rect).unwrap_or(Rect::ZERO)
+        }
+        CaptureTarget::AllScreens => virtual_desktop_rect(monitors),
+    }
+}
+
+pub fn capture_target(
+    source: &dyn CaptureSource,
+    monitors: &[Monitor],
+    target: CaptureTarget,
+) -> Result<RgbaImage> {
+    match target {
+        CaptureTarget::Monitor(index) => match monitors.get(index) {
+            Some(monitor) => source.capture_display(monitor),
+            None => Err(Error::MonitorNotFound(index)),
+        },
+        CaptureTarget::AllScreens => {
+            if monitors.is_empty() {
+                return Err(Error::NoMonitor);
+            }
+            let mut parts = Vec::new();
+            for monitor in monitors {
+                parts.push((monitor.rect(), source.capture_display(monitor)?));
+            }
+            Ok(stitch(&parts))
+        }
+    }
+}
+
+//------ Captures only part of the target, area is in virtual-desktop coordinates
+pub fn capture_area(
+    source: &dyn CaptureSource,
+    monitors: &[Monitor],
+    target: CaptureTarget,
+    area: Rect,
+) -> Result<RgbaImage> {
+    match target {
+        CaptureTarget::Monitor(index) => match monitors.get(index) {
+            Some(monitor) => source.capture_rect(monitor, area),
+            None => Err(Error::MonitorNotFound(index)),
+        },
+        CaptureTarget::AllScreens => {
+            if monitors.is_empty() {
+                return Err(Error::NoMonitor);
+            }
+            // Monitors outside the area are not captured, recordings ask for it at every frame
+            let mut parts = Vec::new();
+            for monitor in monitors {
+                let part = area.intersect(monitor.rect());
+                if part.is_positive() {
+                    parts.push((part, source.capture_rect(monitor, part)?));
+                }
+            }
+            if parts.is_empty() {
+                return Err(Error::EmptySelection);
+            }
+            Ok(stitch(&parts))
+        }
+    }
+}
+
+//------ Pastes every monitor image at its position in the virtual desktop.
+// Monitors can have different scale factors, so every image is brought to the
+// highest pixel density before being placed.
+pub fn stitch(parts: &[(Rect, RgbaImage)]) -> RgbaImage {
+    let desktop = parts
+        .iter()
+        .map(|(rect, _)| *rect)
+        .reduce(|a, b| a.union(b))
+        .unwrap_or(Rect::ZERO);
+    let scale = parts
+        .iter()
+        .map(|(rect, img)| img.width() as f32 / rect.width().max(1.0))
+        .fold(1.0, f32::max);
+
+    let mut canvas = RgbaImage::new(
+        (desktop.width() * scale).round() as u32,
+        (desktop.height() * scale).round() as u32,
+    );
+    for (rect, img) in parts {
+        let width = (rect.width() * scale).round() as u32;
+        let height = (rect.height() * scale).round() as u32;
+        let x = ((rect.min.x - desktop.min.x) * scale).round() as i64;
+        let y = ((rect.min.y - desktop.min.y) * scale).round() as i64;
+        if img.width() == width && img.height() == height {
+            imageops::replace(&mut canvas, img, x, y);
+        } else {
+            let resized = imageops::resize(img, width, height, imageops::FilterType::Triangle);
+            imageops::replace(&mut canvas, &resized, x, y);
+        }
+    }
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::cell::RefCell;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect::from_min_size(Pos2::new(x, y), Vec2::new(width, height))
+    }
+
+    #[test]
+    fn stitch_places_monitors_with_negative_origins() {
+        // Left monitor at -100,0 and a second one above the primary's top edge
+        let parts = [
+            (rect(-100.0, 0.0, 100.0, 50.0), RgbaImage::from_pixel(100, 50, RED)),
+            (rect(0.0, -20.0, 50.0, 50.0), RgbaImage::from_pixel(50, 50, GREEN)),
+        ];
+        let canvas = stitch(&parts);
+        assert_eq!(canvas.dimensions(), (150, 70));
+        assert_eq!(*canvas.get_pixel(0, 20), RED);
+        assert_eq!(*canvas.get_pixel(99, 69), RED);
+        assert_eq!(*canvas.get_pixel(100, 0), GREEN);
+        assert_eq!(*canvas.get_pixel(149, 49), GREEN);
+        // Not covered by any monitor
+        assert_eq!(canvas.get_pixel(0, 0).0[3], 0);
+        assert_eq!(canvas.get_pixel(149, 69).0[3], 0);
+    }
+
+    #[test]
+    fn stitch_scales_mixed_dpi_monitors_to_the_highest_density() {
+        // HiDPI monitor at scale factor 2 next to a scale factor 1 monitor
+        let parts = [
+            (rect(0.0, 0.0, 100.0, 50.0), RgbaImage::from_pixel(200, 100, RED)),
+            (rect(100.0, 0.0, 100.0, 50.0), RgbaImage::from_pixel(100, 50, GREEN)),
+        ];
+        let canvas = stitch(&parts);
+        assert_eq!(canvas.dimensions(), (400, 100));
+        assert_eq!(*canvas.get_pixel(199, 99), RED);
+        assert_eq!(*canvas.get_pixel(200, 0), GREEN);
+        assert_eq!(*canvas.get_pixel(399, 99), GREEN);
+    }
+
+    #[test]
+    fn target_rect_covers_every_monitor() {
+        let monitors = [
+            Monitor {
+                id: 0,
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                scale_factor: 1.0,
+                is_primary: true,
+            },
+            Monitor {
+                id: 1,
+                x: -1280,
+                y: -200,
+                width: 1280,
+                height: 1024,
+                scale_factor: 1.0,
+                is_primary: false,
+            },
+        ];
+        assert_eq!(
+            target_rect(&monitors, CaptureTarget::AllScreens),
+            rect(-1280.0, -200.0, 3200.0, 1280.0)
+        );
+        assert_eq!(
+            target_rect(&monitors, CaptureTarget::Monitor(1)),
+            monitors[1].rect()
+        );
+        assert_eq!(target_rect(&monitors, CaptureTarget::Monitor(2)), Rect::ZERO);
+    }
+
+    // Solid images, remembers which monitors were captured
+    struct CountingSource {
+        captured: RefCell<Vec<u32>>,
+    }
+
+    impl CaptureSource for CountingSource {
+        fn monitors(&self) -> Result<Vec<Monitor>> {
+            Ok(Vec::new())
+        }
+
+        fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage> {
+            self.capture_rect(monitor, monitor.rect())
+        }
+
+        fn capture_rect(&self, monitor: &Monitor, area: Rect) -> Result<RgbaImage> {
+            self.captured.borrow_mut().push(monitor.id);
+            let color = if monitor.id == 0 { RED } else { GREEN };
+            Ok(RgbaImage::from_pixel(area.width() as u32, area.height() as u32, color))
+        }
+    }
+
+    #[test]
+    fn area_of_all_screens_captures_the_monitors_under_it_only() {
+        let monitors: Vec<Monitor> = (0..3)
+            .map(|id| Monitor {
+                id,
+                x: id as i32 * 100,
+                y: 0,
+                width: 100,
+                height: 50,
+                scale_factor: 1.0,
+                is_primary: id == 0,
+            })
+            .collect();
+        let source = CountingSource {
+            captured: RefCell::new(Vec::new()),
+        };
+        let area = rect(90.0, 10.0, 20.0, 20.0);
+        let image = capture_area(&source, &monitors, CaptureTarget::AllScreens, area).unwrap();
+        assert_eq!(*source.captured.borrow(), vec![0, 1]);
+        assert_eq!(image.dimensions(), (20, 20));
+        assert_eq!(*image.get_pixel(9, 19), RED);
+        assert_eq!(*image.get_pixel(10, 0), GREEN);
+        let outside = rect(500.0, 0.0, 10.0, 10.0);
+        assert!(matches!(
+            capture_area(&source, &monitors, CaptureTarget::AllScreens, outside),
+            Err(Error::EmptySelection)
+        ));
+    }
+}
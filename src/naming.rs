@@ -0,0 +1,167 @@
+use chrono::{DateTime, Local};
+use image::RgbaImage;
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::capture::{Capture, CaptureMetadata};
+use crate::error::Result;
+use crate::export::ExportFormat;
+use crate::monitors::CaptureTarget;
+
+pub const DEFAULT_TEMPLATE: &str = "{date}_{time}_{monitor}_{w}x{h}_{counter}.{ext}";
+
+//------ Values the placeholders of a filename template are replaced with
+#[derive(Debug, Clone)]
+pub struct NameFields {
+    pub taken_at: SystemTime,
+    pub monitor: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: ExportFormat,
+}
+
+impl NameFields {
+    //------ Fields for saving image, the exported version of capture
+    pub fn for_capture(capture: &Capture, image: &RgbaImage, format: ExportFormat) -> NameFields {
+        NameFields {
+            taken_at: capture.metadata.taken_at,
+            monitor: source_name(&capture.metadata),
+            width: image.width(),
+            height: image.height(),
+            format,
+        }
+    }
+}
+
+//------ Short name of the target for file names, "monitor1" or "all"
+pub fn monitor_name(target: CaptureTarget) -> String {
+    match target {
+        CaptureTarget::Monitor(index) => format!("monitor{}", index + 1),
+        CaptureTarget::AllScreens => "all".to_string(),
+    }
+}
+
+//------ What {monitor} stands for: the target, or the file stem of an opened image
+pub fn source_name(metadata: &CaptureMetadata) -> String {
+    match &metadata.imported_from {
+        Some(name) => Path::new(name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| name.clone()),
+        None => monitor_name(metadata.target),
+    }
+}
+
+//------ Expands {date}, {time}, {monitor}, {w}, {h}, {counter} and {ext}.
+// A template without {ext} gets the extension appended.
+pub fn expand_template(template: &str, fields: &NameFields, counter: u32) -> String {
+    let taken_at: DateTime<Local> = fields.taken_at.into();
+    let ext = fields.format.extensions()[0];
+    let mut name = template
+        .replace("{date}", &taken_at.format("%Y-%m-%d").to_string())
+        .replace("{time}", &taken_at.format("%H-%M-%S").to_string())
+        .replace("{monitor}", &sanitize(&fields.monitor))
+        .replace("{w}", &fields.width.to_string())
+        .replace("{h}", &fields.height.to_string())
+        .replace("{counter}", &counter.to_string())
+        .replace("{ext}", ext);
+    if !template.contains("{ext}") {
+        name = format!("{}.{}", name, ext);
+    }
+    // Placeholders must not be able to point outside of the directory
+    sanitize_file_name(&name)
+}
+
+//------ Creates the first file in dir not taken yet, counting up from 1.
+// Templates without {counter} get "_2", "_3"... before the extension on collisions.
+// Files are created with create_new, so two saves never get the same name and
+// nothing already at the path (file or link) is followed.
+pub fn create_free_file(
+    dir: &Path,
+    template: &str,
+    fields: &NameFields,
+) -> Result<(PathBuf, File)> {
+    let uses_counter = template.contains("{counter}");
+    let mut counter = 1;
+    loop {
+        let name = expand_template(template, fields, counter);
+        let path = if uses_counter || counter == 1 {
+            dir.join(&name)
+        } else {
+            let name = Path::new(&name);
+            let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+            match name.extension() {
+                Some(ext) => dir.join(format!("{}_{}.{}", stem, counter, ext.to_string_lossy())),
+                None => dir.join(format!("{}_{}", stem, counter)),
+            }
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Keeps letters and digits, every other run of characters becomes "-"
+fn sanitize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    match name.trim_start_matches('.') {
+        "" => "screenshot".to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn fields() -> NameFields {
+        NameFields {
+            taken_at: SystemTime::now(),
+            monitor: "Monitor 1".to_string(),
+            width: 10,
+            height: 20,
+            format: ExportFormat::Png,
+        }
+    }
+
+    #[test]
+    fn taken_names_are_skipped() {
+        let dir = std::env::temp_dir().join(format!(
+            "progetto_malnati-test-{}-naming",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let (first, _) = create_free_file(&dir, "shot_{w}x{h}", &fields()).unwrap();
+        let (second, _) = create_free_file(&dir, "shot_{w}x{h}", &fields()).unwrap();
+        assert_eq!(first, dir.join("shot_10x20.png"));
+        assert_eq!(second, dir.join("shot_10x20_2.png"));
+
+        // A dangling link does not "exist", but must not be written through either
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("elsewhere"), dir.join("link_1.png")).unwrap();
+            let (path, _) = create_free_file(&dir, "link_{counter}", &fields()).unwrap();
+            assert_eq!(path, dir.join("link_2.png"));
+            assert!(!dir.join("elsewhere").exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Local};
+use eframe::egui::{self, Align2, Color32, RichText};
+use std::time::{Duration, Instant, SystemTime};
+
+// Informative toasts go away by themselves, errors stay until dismissed
+const INFO_TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    pub message: String,
+    pub time: SystemTime,
+}
+
+struct Toast {
+    entry: LogEntry,
+    shown_at: Instant,
+}
+
+//------ Toasts shown in the corner of the window plus the history of every message
+#[derive(Default)]
+pub struct Notifications {
+    toasts: Vec<Toast>,
+    history: Vec<LogEntry>,
+    pub show_history: bool,
+}
+
+impl Notifications {
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Level::Info, message.into());
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::error!("{}", message);
+        self.push(Level::Error, message);
+    }
+
+    fn push(&mut self, level: Level, message: String) {
+        let entry = LogEntry {
+            level,
+            message,
+            time: SystemTime::now(),
+        };
+        self.history.push(entry.clone());
+        self.toasts.push(Toast {
+            entry,
+            shown_at: Instant::now(),
+        });
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.toasts.retain(|toast| {
+            toast.entry.level == Level::Error || toast.shown_at.elapsed() < INFO_TOAST_DURATION
+        });
+        if self
+            .toasts
+            .iter()
+            .any(|toast| toast.entry.level == Level::Info)
+        {
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+
+        let mut dismissed = None;
+        egui::Area::new("toasts")
+            .anchor(Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (index, toast) in self.toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(level_text(toast.entry.level));
+                            ui.label(&toast.entry.message);
+                            if ui.small_button("✖").clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(index) = dismissed {
+            self.toasts.remove(index);
+        }
+
+        let mut open = self.show_history;
+        egui::Window::new("Log")
+            .open(&mut open)
+            .default_size([400.0, 200.0])
+            .show(ctx, |ui| {
+                if ui.button("Clear").clicked() {
+                    self.history.clear();
+                }
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for entry in &self.history {
+                            ui.horizontal(|ui| {
+                                ui.monospace(format_time(entry.time));
+                                ui.label(level_text(entry.level));
+                                ui.label(&entry.message);
+                            });
+                        }
+                    });
+            });
+        self.show_history = open;
+    }
+}
+
+fn level_text(level: Level) -> RichText {
+    match level {
+        Level::Info => RichText::new("ℹ").color(Color32::LIGHT_BLUE),
+        Level::Error => RichText::new("⚠").color(Color32::LIGHT_RED),
+    }
+}
+
+// HH:MM:SS in local time, as the clock of the desktop shows it
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%H:%M:%S").to_string()
+}
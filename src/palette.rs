@@ -0,0 +1,169 @@
+use eframe::egui::{self, Key, Modifiers};
+use progetto_malnati::keybidings::{Action, Scope};
+
+use super::actions::{self, REGISTRY};
+use super::MyApp;
+
+// Rows shown at once, typing narrows the list
+const MAX_RESULTS: usize = 10;
+
+//------ Ctrl+P list of every action valid in the current state, filtered while typing
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    query: String,
+    // Index in the filtered list
+    selected: usize,
+}
+
+//------ Score of text for a fuzzy query, None when the letters of query are not all in text in order.
+// Consecutive letters and letters starting a word count more, earlier matches win ties.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        if wanted.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|c| *c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if previous.is_none() {
+            score -= found.min(10) as i32;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+impl MyApp {
+    pub fn open_command_palette(&mut self) {
+        self.command_palette = Some(CommandPalette::default());
+    }
+
+    //------ Actions valid now that match the query, best first
+    fn palette_matches(&self, query: &str) -> Vec<Action> {
+        let mut matches: Vec<(i32, Action)> = REGISTRY
+            .iter()
+            .filter(|entry| entry.action != Action::CommandPalette)
+            .filter(|entry| entry.is_available(&self.state))
+            .filter_map(|entry| Some((fuzzy_score(query, entry.action.name())?, entry.action)))
+            .collect();
+        // Stable, equal scores keep the registry order
+        matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        matches.into_iter().map(|(_, action)| action).take(MAX_RESULTS).collect()
+    }
+
+    pub fn command_palette_window(&mut self, ctx: &egui::Context) {
+        let Some(palette) = self.command_palette.as_mut() else {
+            return;
+        };
+        // Navigation keys are taken before the text field sees them
+        let (down, up, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::Enter),
+                i.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if escape {
+            self.command_palette = None;
+            return;
+        }
+        let query = palette.query.clone();
+        let matches = self.palette_matches(&query);
+        let Some(palette) = self.command_palette.as_mut() else {
+            return;
+        };
+        if down {
+            palette.selected += 1;
+        }
+        if up {
+            palette.selected = palette.selected.saturating_sub(1);
+        }
+        palette.selected = palette.selected.min(matches.len().saturating_sub(1));
+
+        let mut chosen = enter.then(|| matches.get(palette.selected).copied()).flatten();
+        egui::Window::new("Command palette")
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .show(ctx, |ui| {
+                ui.set_width(360.0);
+                let field = ui.add(
+                    egui::TextEdit::singleline(&mut palette.query)
+                        .hint_text("Type an action…")
+                        .desired_width(f32::INFINITY),
+                );
+                field.request_focus();
+                if field.changed() {
+                    palette.selected = 0;
+                }
+                ui.separator();
+                if matches.is_empty() {
+                    ui.weak("No matching action here");
+                }
+                for (index, action) in matches.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .selectable_label(index == palette.selected, action.name())
+                            .clicked()
+                        {
+                            chosen = Some(*action);
+                        }
+                        if let Some(shortcut) = self.key_bindings.get(Scope::Window, *action) {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.weak(shortcut.to_string());
+                            });
+                        }
+                    });
+                }
+            });
+        if let Some(action) = chosen {
+            self.command_palette = None;
+            self.run_action(ctx, action);
+        }
+    }
+
+    //------ Closes the palette when the state it was opened in is left
+    pub fn close_palette_if_unavailable(&mut self) {
+        if self.command_palette.is_some()
+            && !actions::entry(Action::CommandPalette).is_available(&self.state)
+        {
+            self.command_palette = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_beats_scattered_letters() {
+        let prefix = fuzzy_score("clos", "Close tab").unwrap();
+        let scattered = fuzzy_score("clos", "Clear annotations and redactions").unwrap();
+        assert!(prefix > scattered, "{prefix} <= {scattered}");
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert_eq!(fuzzy_score("CLOSE", "close tab"), fuzzy_score("close", "Close Tab"));
+        assert!(fuzzy_score("SaVe", "Save as").is_some());
+    }
+
+    #[test]
+    fn missing_or_unordered_letters_do_not_match() {
+        assert_eq!(fuzzy_score("xyz", "Close tab"), None);
+        assert_eq!(fuzzy_score("tc", "Close tab"), None);
+    }
+}
@@ -0,0 +1,182 @@
+use eframe::egui::{
+    self, CentralPanel, Color32, Frame, Pos2, Rect, Sense, TextureHandle, Vec2, ViewportBuilder,
+    ViewportClass, ViewportCommand, ViewportId,
+};
+use progetto_malnati::{Error, Result};
+
+use super::application::load_texture;
+use super::AppState;
+use super::MyApp;
+
+// Limits of the mouse wheel zoom and of the opacity slider
+const MIN_SCALE: f32 = 0.1;
+const MAX_SCALE: f32 = 8.0;
+const MIN_OPACITY: f32 = 0.1;
+
+//------ Capture shown in its own borderless window, above every other window
+pub struct Pin {
+    id: ViewportId,
+    title: String,
+    texture: TextureHandle,
+    // Exported image size, in points at scale 1
+    size: Vec2,
+    scale: f32,
+    opacity: f32,
+    // Clicks go to the window below, the pin is then only controlled from the main window
+    click_through: bool,
+    // Where it is created, moved afterwards by dragging it
+    position: Pos2,
+    closed: bool,
+}
+
+impl MyApp {
+    //------ Pins the exported image of the active tab where it was captured
+    pub fn pin_capture(&mut self, ctx: &egui::Context) -> Result<()> {
+        let capture = self.capture().ok_or(Error::NothingCaptured)?;
+        let image = capture.rendered()?;
+        let position = capture.region.min;
+        // The pin covers the captured region, whatever the scale factor of the monitor
+        let points_per_pixel = capture.image_rect.width() / capture.image.width() as f32;
+        let title = match &capture.metadata.imported_from {
+            Some(name) => name.clone(),
+            None => capture.metadata.target_label.clone(),
+        };
+        self.next_pin += 1;
+        let id = ViewportId::from_hash_of(("pin", self.next_pin));
+        self.pins.push(Pin {
+            id,
+            title: format!("Pin {}: {}", self.next_pin, title),
+            // Shrunk to the largest texture allowed, the size stays the one of the region
+            texture: load_texture(ctx, "pin", &image),
+            size: Vec2::new(image.width() as f32, image.height() as f32) * points_per_pixel,
+            scale: 1.0,
+            opacity: 1.0,
+            click_through: false,
+            position,
+            closed: false,
+        });
+        Ok(())
+    }
+
+    //------ Draws every pin in its viewport, those closed meanwhile are dropped
+    pub fn show_pins(&mut self, ctx: &egui::Context) {
+        // They would end up in the capture or the recording
+        let capturing = matches!(
+            self.state,
+            AppState::NewCapture | AppState::Selection | AppState::Freezing | AppState::Recording
+        );
+        for pin in self.pins.iter_mut() {
+            let builder = ViewportBuilder::default()
+                .with_title(pin.title.clone())
+                .with_decorations(false)
+                .with_transparent(true)
+                .with_resizable(false)
+                .with_always_on_top()
+                .with_position(pin.position)
+                .with_inner_size(pin.size * pin.scale)
+                .with_mouse_passthrough(pin.click_through)
+                .with_visible(!capturing);
+            ctx.show_viewport_immediate(pin.id, builder, |ctx, class| {
+                // Backends without multiple windows draw the pin inside the main one
+                if class == ViewportClass::Embedded {
+                    let mut open = true;
+                    egui::Window::new(pin.title.clone())
+                        .id(egui::Id::new(pin.id))
+                        .open(&mut open)
+                        .resizable(false)
+                        .show(ctx, |ui| pin.contents(ctx, ui, false));
+                    pin.closed |= !open;
+                    return;
+                }
+                CentralPanel::default()
+                    .frame(Frame::none())
+                    .show(ctx, |ui| pin.contents(ctx, ui, true));
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    pin.closed = true;
+                }
+            });
+        }
+        self.pins.retain(|pin| !pin.closed);
+    }
+
+    //------ Settings of every pin, the only way back from click-through
+    pub fn pins_window(&mut self, ctx: &egui::Context) {
+        if self.pins.is_empty() {
+            return;
+        }
+        egui::Window::new("Pinned captures")
+            .default_pos([20.0, 80.0])
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("pins").num_columns(5).show(ui, |ui| {
+                    for pin in self.pins.iter_mut() {
+                        ui.label(&pin.title);
+                        ui.add(
+                            egui::Slider::new(&mut pin.scale, MIN_SCALE..=MAX_SCALE)
+                                .logarithmic(true)
+                                .text("scale"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut pin.opacity, MIN_OPACITY..=1.0).text("opacity"),
+                        );
+                        ui.checkbox(&mut pin.click_through, "click-through");
+                        if ui.button("Close").clicked() {
+                            pin.closed = true;
+                        }
+                        ui.end_row();
+                    }
+                });
+                if ui.button("Close all").clicked() {
+                    self.pins.clear();
+                }
+            });
+        self.pins.retain(|pin| !pin.closed);
+    }
+}
+
+impl Pin {
+    // Image filling the viewport: dragged to move, wheel to zoom, right click for the options
+    fn contents(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, own_window: bool) {
+        let rect = if own_window {
+            ui.max_rect()
+        } else {
+            let (rect, _) = ui.allocate_exact_size(self.size * self.scale, Sense::hover());
+            rect
+        };
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        ui.painter().image(
+            self.texture.id(),
+            rect,
+            uv,
+            Color32::WHITE.gamma_multiply(self.opacity),
+        );
+        let response = ui.interact(rect, ui.id().with("pin"), Sense::click_and_drag());
+        if own_window && response.drag_started() {
+            ctx.send_viewport_cmd(ViewportCommand::StartDrag);
+        }
+        if response.hovered() {
+            let scroll = ctx.input(|i| i.scroll_delta.y);
+            if scroll != 0.0 {
+                self.scale = (self.scale * (scroll / 400.0).exp()).clamp(MIN_SCALE, MAX_SCALE);
+            }
+        }
+        response.context_menu(|ui| {
+            ui.add(egui::Slider::new(&mut self.opacity, MIN_OPACITY..=1.0).text("opacity"));
+            if ui.button("Actual size").clicked() {
+                self.scale = 1.0;
+                ui.close_menu();
+            }
+            if ui
+                .checkbox(&mut self.click_through, "Click-through")
+                .on_hover_text("Turned off again from the main window")
+                .changed()
+            {
+                ui.close_menu();
+            }
+            if ui.button("Close").clicked() {
+                self.closed = true;
+                ui.close_menu();
+            }
+        });
+    }
+}
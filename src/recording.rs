@@ -0,0 +1,719 @@
+use emath::Rect;
+use image::codecs::webp::WebPEncoder;
+use image::{imageops, ColorType, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::capture;
+use crate::error::{Error, Result};
+use crate::export::{self, ExportOptions};
+use crate::monitors::{self, CaptureTarget, Monitor};
+use crate::temp;
+
+// How often a paused recording checks whether it was resumed or stopped
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+// Largest frame rate offered, capturing a screen rarely keeps up beyond it
+pub const MAX_FPS: u32 = 30;
+
+//------ Animated formats a recording can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum RecordingFormat {
+    #[default]
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 3] = [
+        RecordingFormat::Gif,
+        RecordingFormat::Apng,
+        RecordingFormat::WebP,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordingFormat::Gif => "Animated GIF",
+            RecordingFormat::Apng => "APNG",
+            RecordingFormat::WebP => "Animated WebP (lossless)",
+        }
+    }
+
+    //------ Extensions accepted for the format, the first one is used for new files
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            RecordingFormat::Gif => &["gif"],
+            RecordingFormat::Apng => &["png", "apng"],
+            RecordingFormat::WebP => &["webp"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<RecordingFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        RecordingFormat::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+}
+
+//------ Frame rate and the limits that end or spill a recording
+#[derive(Debug, Clone, Copy)]
+pub struct RecordingSettings {
+    pub fps: u32,
+    // Paused time is not counted
+    pub max_duration: Duration,
+    // Frames beyond it are written to a temporary directory
+    pub memory_limit: usize,
+}
+
+//------ Progress of a recording, as shown while it runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordingStatus {
+    // Distinct frames, a screen that did not change adds none
+    pub frames: usize,
+    pub elapsed: Duration,
+    // Bytes of the frames kept in memory
+    pub memory: usize,
+    pub spilled: usize,
+    // Maximum duration reached or capture failed, stop() returns what was recorded
+    pub finished: bool,
+}
+
+enum StoredImage {
+    Memory(RgbaImage),
+    Spilled(PathBuf),
+}
+
+struct StoredFrame {
+    image: StoredImage,
+    // Recording time it appeared at
+    at: Duration,
+}
+
+//------ Frames of a recording, in memory up to a limit and in a temporary directory past it.
+// The directory is removed with the store.
+struct FrameStore {
+    frames: Vec<StoredFrame>,
+    // Of every frame, in pixels
+    dimensions: (u32, u32),
+    // Time the last frame stops being shown
+    end: Duration,
+    memory: usize,
+    memory_limit: usize,
+    spill_dir: Option<PathBuf>,
+}
+
+impl FrameStore {
+    fn new(memory_limit: usize) -> FrameStore {
+        FrameStore {
+            frames: Vec::new(),
+            dimensions: (0, 0),
+            end: Duration::ZERO,
+            memory: 0,
+            memory_limit,
+            spill_dir: None,
+        }
+    }
+
+    fn push(&mut self, image: RgbaImage, at: Duration) -> Result<()> {
+        let size = image.as_raw().len();
+        if self.frames.is_empty() {
+            self.dimensions = image.dimensions();
+        }
+        let image = if self.memory + size <= self.memory_limit {
+            self.memory += size;
+            StoredImage::Memory(image)
+        } else {
+            // QOI is fast enough to keep up with the capture
+            let name = format!("{}.qoi", self.frames.len());
+            let path = self.spill_dir()?.join(name);
+            image.save_with_format(&path, ImageFormat::Qoi)?;
+            StoredImage::Spilled(path)
+        };
+        self.frames.push(StoredFrame { image, at });
+        Ok(())
+    }
+
+    fn spill_dir(&mut self) -> Result<&Path> {
+        if self.spill_dir.is_none() {
+            static RECORDINGS: AtomicUsize = AtomicUsize::new(0);
+            // The frames show the screen, other users must not read them
+            let dir = temp::dir()?.join(format!(
+                "recording-{}-{}",
+                std::process::id(),
+                RECORDINGS.fetch_add(1, Ordering::Relaxed)
+            ));
+            temp::make_private(&dir)?;
+            self.spill_dir = Some(dir);
+        }
+        Ok(self.spill_dir.as_deref().unwrap_or(Path::new("")))
+    }
+
+    fn spilled(&self) -> usize {
+        self.frames
+            .iter()
+            .filter(|frame| matches!(frame.image, StoredImage::Spilled(_)))
+            .count()
+    }
+
+    fn image(&self, index: usize) -> Result<Cow<'_, RgbaImage>> {
+        match &self.frames[index].image {
+            StoredImage::Memory(image) => Ok(Cow::Borrowed(image)),
+            StoredImage::Spilled(path) => Ok(Cow::Owned(image::open(path)?.to_rgba8())),
+        }
+    }
+
+    // Display time of a frame, in milliseconds from the start
+    fn span_millis(&self, index: usize) -> (u64, u64) {
+        let end = self.frames.get(index + 1).map_or(self.end, |next| next.at);
+        (self.frames[index].at.as_millis() as u64, end.as_millis() as u64)
+    }
+}
+
+impl Drop for FrameStore {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.spill_dir {
+            if let Err(e) = fs::remove_dir_all(dir) {
+                log::warn!("cannot remove {}: {}", dir.display(), e);
+            }
+        }
+    }
+}
+
+struct Shared {
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    status: Mutex<RecordingStatus>,
+}
+
+//------ Captures an area of the screen on its own thread until stopped
+pub struct Recorder {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Result<FrameStore>>>,
+}
+
+impl Recorder {
+    //------ Starts right away, area is in virtual-desktop coordinates like for capture_area
+    pub fn start(
+        monitors: Vec<Monitor>,
+        target: CaptureTarget,
+        area: Rect,
+        settings: RecordingSettings,
+    ) -> Recorder {
+        let shared = Arc::new(Shared {
+            paused: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            status: Mutex::new(RecordingStatus::default()),
+        });
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::spawn(move || {
+            let result = record(&thread_shared, &monitors, target, area, settings);
+            lock(&thread_shared.status).finished = true;
+            result
+        });
+        Recorder {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.shared.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        *lock(&self.shared.status)
+    }
+
+    //------ Waits for the last frame, fails if not even one could be captured
+    pub fn stop(mut self) -> Result<Recording> {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        let thread = self.thread.take().ok_or_else(|| recording_error("already stopped"))?;
+        let frames = thread
+            .join()
+            .map_err(|_| recording_error("the recording thread panicked"))??;
+        if frames.frames.is_empty() {
+            return Err(recording_error("no frame was recorded"));
+        }
+        Ok(Recording { frames })
+    }
+}
+
+impl Drop for Recorder {
+    // A recorder dropped without stop() is cancelled, its frames are thrown away
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn lock(status: &Mutex<RecordingStatus>) -> std::sync::MutexGuard<'_, RecordingStatus> {
+    status.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn recording_error(message: impl Into<String>) -> Error {
+    Error::Recording(message.into())
+}
+
+// Body of the recording thread. Time only runs while not paused, and a frame equal to the
+// previous one is not stored: the previous one is shown longer instead.
+fn record(
+    shared: &Shared,
+    monitors: &[Monitor],
+    target: CaptureTarget,
+    area: Rect,
+    settings: RecordingSettings,
+) -> Result<FrameStore> {
+    // The source cannot be sent to another thread, this one gets its own
+    let source = capture::default_source();
+    let interval = Duration::from_secs(1) / settings.fps.clamp(1, MAX_FPS);
+    let mut frames = FrameStore::new(settings.memory_limit);
+    let mut previous: Option<RgbaImage> = None;
+    let mut elapsed = Duration::ZERO;
+    let mut last_tick = Instant::now();
+    while !shared.stopped.load(Ordering::Relaxed) {
+        let tick = Instant::now();
+        if !shared.paused.load(Ordering::Relaxed) {
+            elapsed += tick - last_tick;
+        }
+        last_tick = tick;
+        if elapsed >= settings.max_duration {
+            elapsed = settings.max_duration;
+            break;
+        }
+        if shared.paused.load(Ordering::Relaxed) {
+            thread::sleep(PAUSE_POLL);
+            continue;
+        }
+
+        let mut image = match monitors::capture_area(source.as_ref(), monitors, target, area) {
+            Ok(image) => image,
+            // What was recorded so far is kept
+            Err(e) if !frames.frames.is_empty() => {
+                log::warn!("recording stopped: {}", e);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        // Rounding can change the captured size by a pixel, every frame keeps the first one's
+        if let Some(first) = &previous {
+            if image.dimensions() != first.dimensions() {
+                let (width, height) = first.dimensions();
+                image = imageops::resize(&image, width, height, imageops::FilterType::Triangle);
+            }
+        }
+        if previous.as_ref() != Some(&image) {
+            frames.push(image.clone(), elapsed)?;
+            previous = Some(image);
+        }
+        *lock(&shared.status) = RecordingStatus {
+            frames: frames.frames.len(),
+            elapsed,
+            memory: frames.memory,
+            spilled: frames.spilled(),
+            finished: false,
+        };
+
+        let spent = tick.elapsed();
+        if spent < interval {
+            thread::sleep(interval - spent);
+        }
+    }
+    // The last frame is shown for at least one interval
+    let last = frames.frames.last().map_or(Duration::ZERO, |frame| frame.at);
+    frames.end = elapsed.max(last + interval);
+    lock(&shared.status).elapsed = elapsed;
+    Ok(frames)
+}
+
+//------ Frames of a stopped recording, ready to be encoded
+pub struct Recording {
+    frames: FrameStore,
+}
+
+// Changed part of a frame, the pixels that did not change in it are transparent
+struct Patch {
+    x: u32,
+    y: u32,
+    image: RgbaImage,
+}
+
+impl Recording {
+    pub fn frame_count(&self) -> usize {
+        self.frames.frames.len()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frames.end
+    }
+
+    //------ Width and height of the frames, in pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames.dimensions
+    }
+
+    //------ Writes the recording as an animation that loops forever.
+    // Only the area that changed since the previous frame is stored for every frame.
+    // A file already at path is only replaced once the new one is complete.
+    pub fn encode(
+        &self,
+        path: &Path,
+        format: RecordingFormat,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        export::write_atomically(path, |out| match format {
+            RecordingFormat::Gif => self.encode_gif(out, options),
+            RecordingFormat::Apng => self.encode_apng(out, options),
+            RecordingFormat::WebP => self.encode_webp(out),
+        })
+    }
+
+    // Calls write with every frame patch and its display time, in milliseconds from the start
+    fn for_each_patch(
+        &self,
+        even_offsets: bool,
+        mut write: impl FnMut(Patch, (u64, u64)) -> Result<()>,
+    ) -> Result<()> {
+        let mut previous: Option<RgbaImage> = None;
+        for index in 0..self.frames.frames.len() {
+            let image = self.frames.image(index)?.into_owned();
+            let patch = patch(previous.as_ref(), &image, even_offsets);
+            write(patch, self.frames.span_millis(index))?;
+            previous = Some(image);
+        }
+        Ok(())
+    }
+
+    // Frames are drawn over the previous ones (dispose Keep), delays in hundredths of a second
+    fn encode_gif(&self, out: impl Write, options: &ExportOptions) -> Result<()> {
+        let (width, height) = self.dimensions();
+        let width = u16::try_from(width).map_err(|_| gif_too_large())?;
+        let height = u16::try_from(height).map_err(|_| gif_too_large())?;
+        let mut encoder =
+            gif::Encoder::new(out, width, height, &[]).map_err(export::from_gif)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(export::from_gif)?;
+        self.for_each_patch(false, |patch, (start, end)| {
+            let mut frame = export::gif_frame(&patch.image, options)?;
+            frame.left = patch.x as u16;
+            frame.top = patch.y as u16;
+            // Rounded from the start so the error does not add up, browsers slow down shorter delays
+            let delay = ((end + 5) / 10).saturating_sub((start + 5) / 10);
+            frame.delay = delay.clamp(2, u16::MAX as u64) as u16;
+            frame.dispose = gif::DisposalMethod::Keep;
+            encoder.write_frame(&frame).map_err(export::from_gif)
+        })?;
+        // Writes the trailer
+        encoder.into_inner()?;
+        Ok(())
+    }
+
+    // Frames are blended over the previous ones, delays in milliseconds
+    fn encode_apng(&self, out: impl Write, options: &ExportOptions) -> Result<()> {
+        let (width, height) = self.dimensions();
+        let mut encoder = png::Encoder::new(out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(match options.png_compression {
+            export::PngCompression::Fast => png::Compression::Fast,
+            export::PngCompression::Default => png::Compression::Default,
+            export::PngCompression::Best => png::Compression::Best,
+        });
+        encoder
+            .set_animated(self.frame_count() as u32, 0)
+            .map_err(from_png)?;
+        let mut writer = encoder.write_header().map_err(from_png)?;
+        self.for_each_patch(false, |patch, (start, end)| {
+            // Position first, the new size must fit the canvas at the current position
+            writer.set_frame_position(0, 0).map_err(from_png)?;
+            writer
+                .set_frame_dimension(patch.image.width(), patch.image.height())
+                .map_err(from_png)?;
+            writer.set_frame_position(patch.x, patch.y).map_err(from_png)?;
+            writer
+                .set_frame_delay((end - start).min(u16::MAX as u64) as u16, 1000)
+                .map_err(from_png)?;
+            writer.set_blend_op(png::BlendOp::Over).map_err(from_png)?;
+            writer.set_dispose_op(png::DisposeOp::None).map_err(from_png)?;
+            writer.write_image_data(patch.image.as_raw()).map_err(from_png)
+        })?;
+        writer.finish().map_err(from_png)
+    }
+
+    // Extended WebP container: VP8X, ANIM, then one ANMF chunk per frame holding a lossless
+    // VP8L bitstream. Frames are alpha blended over the previous ones, offsets must be even.
+    fn encode_webp(&self, mut out: impl Write) -> Result<()> {
+        const MAX_24: u64 = (1 << 24) - 1;
+        let (width, height) = self.dimensions();
+        if width as u64 > MAX_24 || height as u64 > MAX_24 {
+            return Err(recording_error("WebP images are limited to 16777215 pixels per side"));
+        }
+        let mut chunks = Vec::new();
+        let mut header = vec![0x10 | 0x02, 0, 0, 0];
+        header.extend_from_slice(&u24(width as u64 - 1));
+        header.extend_from_slice(&u24(height as u64 - 1));
+        push_chunk(&mut chunks, b"VP8X", &header);
+        // Transparent background, loops forever
+        push_chunk(&mut chunks, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+        self.for_each_patch(true, |patch, (start, end)| {
+            let mut encoded = Vec::new();
+            WebPEncoder::new_lossless(&mut encoded).encode(
+                patch.image.as_raw(),
+                patch.image.width(),
+                patch.image.height(),
+                ColorType::Rgba8,
+            )?;
+            let mut frame = Vec::new();
+            frame.extend_from_slice(&u24(patch.x as u64 / 2));
+            frame.extend_from_slice(&u24(patch.y as u64 / 2));
+            frame.extend_from_slice(&u24(patch.image.width() as u64 - 1));
+            frame.extend_from_slice(&u24(patch.image.height() as u64 - 1));
+            frame.extend_from_slice(&u24((end - start).min(MAX_24)));
+            // Blended, not disposed
+            frame.push(0);
+            frame.extend_from_slice(vp8l_chunk(&encoded)?);
+            push_chunk(&mut chunks, b"ANMF", &frame);
+            Ok(())
+        })?;
+        out.write_all(b"RIFF")?;
+        out.write_all(&(chunks.len() as u32 + 4).to_le_bytes())?;
+        out.write_all(b"WEBP")?;
+        out.write_all(&chunks)?;
+        Ok(())
+    }
+}
+
+//------ Smallest area that changed since previous, whole frame for the first one.
+// Pixels inside it that did not change are made transparent, they compress to almost nothing.
+fn patch(previous: Option<&RgbaImage>, image: &RgbaImage, even_offsets: bool) -> Patch {
+    let Some(previous) = previous else {
+        return Patch {
+            x: 0,
+            y: 0,
+            image: image.clone(),
+        };
+    };
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if previous.get_pixel(x, y) != pixel {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    // Stored frames always differ, but a single unchanged pixel keeps the frame valid anyway
+    if min_x > max_x {
+        (min_x, min_y, max_x, max_y) = (0, 0, 0, 0);
+    }
+    if even_offsets {
+        min_x -= min_x % 2;
+        min_y -= min_y % 2;
+    }
+    let mut changed =
+        imageops::crop_imm(image, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1).to_image();
+    for (x, y, pixel) in changed.enumerate_pixels_mut() {
+        if previous.get_pixel(min_x + x, min_y + y) == pixel {
+            *pixel = image::Rgba([0, 0, 0, 0]);
+        }
+    }
+    Patch {
+        x: min_x,
+        y: min_y,
+        image: changed,
+    }
+}
+
+fn u24(value: u64) -> [u8; 3] {
+    let bytes = (value as u32).to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+// RIFF chunks are padded to an even size
+fn push_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+// VP8L chunk, header included, of a simple lossless WebP file
+fn vp8l_chunk(webp: &[u8]) -> Result<&[u8]> {
+    let mut offset = 12;
+    while offset + 8 <= webp.len() {
+        let size = u32::from_le_bytes([
+            webp[offset + 4],
+            webp[offset + 5],
+            webp[offset + 6],
+            webp[offset + 7],
+        ]) as usize;
+        let end = (offset + 8 + size + size % 2).min(webp.len());
+        if &webp[offset..offset + 4] == b"VP8L" {
+            return Ok(&webp[offset..end]);
+        }
+        offset = end;
+    }
+    Err(recording_error("the WebP encoder wrote no VP8L chunk"))
+}
+
+fn gif_too_large() -> Error {
+    recording_error("GIF images are limited to 65535 pixels per side")
+}
+
+fn from_png(e: png::EncodingError) -> Error {
+    match e {
+        png::EncodingError::IoError(e) => Error::Io(e),
+        e => recording_error(format!("APNG: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPDecoder;
+    use image::{AnimationDecoder, Frame, Rgba};
+    use std::io::Cursor;
+    use std::{env, process};
+
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+    // Blue screen where a small square turns red, then a single pixel turns green
+    fn frames() -> Vec<RgbaImage> {
+        let first = RgbaImage::from_pixel(8, 6, BLUE);
+        let mut second = first.clone();
+        for (x, y) in [(3, 1), (4, 1), (3, 2), (4, 2)] {
+            second.put_pixel(x, y, RED);
+        }
+        let mut third = second.clone();
+        third.put_pixel(5, 4, GREEN);
+        vec![first, second, third]
+    }
+
+    // Frames at 0, 100 and 250 ms, the last one shown until 400 ms
+    fn recording(memory_limit: usize) -> Recording {
+        let mut store = FrameStore::new(memory_limit);
+        for (image, at) in frames().into_iter().zip([0, 100, 250]) {
+            store.push(image, Duration::from_millis(at)).unwrap();
+        }
+        store.end = Duration::from_millis(400);
+        Recording { frames: store }
+    }
+
+    fn encoded(format: RecordingFormat) -> Vec<u8> {
+        let dir = env::temp_dir().join(format!(
+            "progetto_malnati-test-{}-encode-{:?}",
+            process::id(),
+            format
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("recording.{}", format.extensions()[0]));
+        recording(usize::MAX)
+            .encode(&path, format, &ExportOptions::default())
+            .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        bytes
+    }
+
+    fn delays_ms(frames: &[Frame]) -> Vec<u32> {
+        frames
+            .iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                numer / denom
+            })
+            .collect()
+    }
+
+    // Every decoded frame is the whole canvas, with the earlier frames drawn under it
+    fn assert_composited(frames: &[Frame]) {
+        assert_eq!(frames.len(), 3);
+        for (decoded, expected) in frames.iter().zip(self::frames()) {
+            assert_eq!(*decoded.buffer(), expected);
+        }
+    }
+
+    #[test]
+    fn gif_keeps_frames_and_delays() {
+        let bytes = encoded(RecordingFormat::Gif);
+        let frames = GifDecoder::new(Cursor::new(bytes))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_composited(&frames);
+        // Hundredths of a second
+        assert_eq!(delays_ms(&frames), vec![100, 150, 150]);
+    }
+
+    #[test]
+    fn apng_keeps_frames_and_delays() {
+        let bytes = encoded(RecordingFormat::Apng);
+        let frames = PngDecoder::new(Cursor::new(bytes))
+            .unwrap()
+            .apng()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_composited(&frames);
+        assert_eq!(delays_ms(&frames), vec![100, 150, 150]);
+    }
+
+    #[test]
+    fn webp_keeps_frames_and_delays() {
+        let bytes = encoded(RecordingFormat::WebP);
+        assert_eq!(&bytes[..4], b"RIFF");
+        let riff_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        assert_eq!(riff_size as usize, bytes.len() - 8);
+        let decoder = WebPDecoder::new(Cursor::new(bytes)).unwrap();
+        assert!(decoder.has_animation());
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_composited(&frames);
+        assert_eq!(delays_ms(&frames), vec![100, 150, 150]);
+    }
+
+    #[test]
+    fn patch_keeps_only_the_changed_pixels() {
+        let frames = frames();
+        let patch = patch(Some(&frames[1]), &frames[2], false);
+        assert_eq!((patch.x, patch.y, patch.image.dimensions()), (5, 4, (1, 1)));
+        // Even offsets for WebP, the pixel that did not change is transparent
+        let even = super::patch(Some(&frames[0]), &frames[1], true);
+        assert_eq!((even.x, even.y, even.image.dimensions()), (2, 0, (3, 3)));
+        assert_eq!(even.image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(*even.image.get_pixel(1, 1), RED);
+    }
+
+    #[test]
+    fn frames_past_the_memory_limit_are_spilled() {
+        // Room for the first frame only
+        let recording = recording(8 * 6 * 4);
+        let store = &recording.frames;
+        assert_eq!(store.spilled(), 2);
+        let dir = store.spill_dir.clone().unwrap();
+        assert!(dir.is_dir());
+        for (index, expected) in frames().iter().enumerate() {
+            assert_eq!(*store.image(index).unwrap(), *expected);
+        }
+        drop(recording);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,220 @@
+use ecolor::Color32;
+use emath::{Pos2, Rect};
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+//------ Ways of hiding a region of the capture
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Method {
+    #[default]
+    Pixelate,
+    Blur,
+    Fill,
+}
+
+impl Method {
+    pub const ALL: [Method; 3] = [Method::Pixelate, Method::Blur, Method::Fill];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Pixelate => "Pixelate",
+            Method::Blur => "Blur",
+            Method::Fill => "Fill",
+        }
+    }
+}
+
+//------ Strength of each method, block size and blur sigma are in image pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RedactionStyle {
+    pub block_size: u32,
+    pub blur_sigma: f32,
+    pub fill: Color32,
+}
+
+impl Default for RedactionStyle {
+    fn default() -> Self {
+        RedactionStyle {
+            block_size: 12,
+            blur_sigma: 8.0,
+            fill: Color32::BLACK,
+        }
+    }
+}
+
+//------ A region hidden with method, area is in virtual-desktop coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redaction {
+    pub area: Rect,
+    pub method: Method,
+    pub style: RedactionStyle,
+}
+
+//------ Overwrites the redacted areas of img, which covers image_rect of the desktop.
+// Only the pixels of the area are read, nothing of the original is left in them.
+pub fn apply(img: &mut RgbaImage, image_rect: Rect, redactions: &[Redaction]) {
+    if redactions.is_empty() || image_rect.width() <= 0.0 {
+        return;
+    }
+    // Image may have more pixels than points on HiDPI monitors
+    let scale = img.width() as f32 / image_rect.width();
+    for redaction in redactions {
+        let to_pixels = |p: Pos2| ((p - image_rect.min) * scale).to_pos2();
+        let area = Rect::from_min_max(to_pixels(redaction.area.min), to_pixels(redaction.area.max));
+        // Partially covered pixels are redacted too
+        let left = area.min.x.floor().max(0.0) as u32;
+        let top = area.min.y.floor().max(0.0) as u32;
+        let right = (area.max.x.ceil().max(0.0) as u32).min(img.width());
+        let bottom = (area.max.y.ceil().max(0.0) as u32).min(img.height());
+        if right <= left || bottom <= top {
+            continue;
+        }
+        let (width, height) = (right - left, bottom - top);
+        match redaction.method {
+            Method::Pixelate => pixelate(img, left, top, width, height, redaction.style.block_size),
+            Method::Blur => {
+                let part = imageops::crop_imm(img, left, top, width, height).to_image();
+                let blurred = imageops::blur(&part, redaction.style.blur_sigma.max(0.5));
+                imageops::replace(img, &blurred, left as i64, top as i64);
+            }
+            Method::Fill => {
+                // Always opaque, a transparent fill would leave the pixels readable
+                let [r, g, b, _] = redaction.style.fill.to_srgba_unmultiplied();
+                for y in top..bottom {
+                    for x in left..right {
+                        img.put_pixel(x, y, Rgba([r, g, b, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Every block becomes the average of its pixels
+fn pixelate(img: &mut RgbaImage, left: u32, top: u32, width: u32, height: u32, block_size: u32) {
+    let block_size = block_size.max(2);
+    for block_y in (top..top + height).step_by(block_size as usize) {
+        for block_x in (left..left + width).step_by(block_size as usize) {
+            let right = (block_x + block_size).min(left + width);
+            let bottom = (block_y + block_size).min(top + height);
+            let mut sum = [0u64; 4];
+            for y in block_y..bottom {
+                for x in block_x..right {
+                    for (total, value) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+                        *total += value as u64;
+                    }
+                }
+            }
+            let count = ((right - block_x) * (bottom - block_y)) as u64;
+            let average = Rgba(sum.map(|total| (total / count) as u8));
+            for y in block_y..bottom {
+                for x in block_x..right {
+                    img.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emath::{pos2, vec2};
+
+    // Half transparent, it must be drawn opaque
+    const FILL: Color32 = Color32::from_rgba_premultiplied(5, 100, 15, 128);
+
+    // Every pixel different from its neighbours
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 7) as u8, (y * 11) as u8, (x * y) as u8, (100 + x + y) as u8])
+        })
+    }
+
+    fn redaction(area: Rect, method: Method) -> Redaction {
+        let style = RedactionStyle {
+            block_size: 4,
+            blur_sigma: 2.0,
+            fill: FILL,
+        };
+        Redaction { area, method, style }
+    }
+
+    fn opaque_fill() -> Rgba<u8> {
+        let [r, g, b, _] = FILL.to_srgba_unmultiplied();
+        Rgba([r, g, b, 255])
+    }
+
+    // Pixels of img outside left..right, top..bottom are those of original
+    fn untouched_outside(img: &RgbaImage, original: &RgbaImage, [left, top, right, bottom]: [u32; 4]) {
+        for (x, y, pixel) in img.enumerate_pixels() {
+            if x < left || x >= right || y < top || y >= bottom {
+                assert_eq!(pixel, original.get_pixel(x, y), "pixel {x},{y} changed");
+            }
+        }
+    }
+
+    #[test]
+    fn fill_is_opaque() {
+        let image_rect = Rect::from_min_size(pos2(100.0, 50.0), vec2(40.0, 30.0));
+        let area = Rect::from_min_max(pos2(110.0, 60.0), pos2(130.0, 76.0));
+        let mut img = noise(40, 30);
+        apply(&mut img, image_rect, &[redaction(area, Method::Fill)]);
+        for y in 10..26 {
+            for x in 10..30 {
+                assert_eq!(*img.get_pixel(x, y), opaque_fill());
+            }
+        }
+    }
+
+    #[test]
+    fn pixelated_blocks_are_uniform() {
+        let image_rect = Rect::from_min_size(pos2(100.0, 50.0), vec2(40.0, 30.0));
+        let area = Rect::from_min_max(pos2(110.0, 60.0), pos2(130.0, 77.0));
+        let mut img = noise(40, 30);
+        apply(&mut img, image_rect, &[redaction(area, Method::Pixelate)]);
+        // Blocks of 4 from the corner of the area, the last row is cut to 1
+        for y in 10..27 {
+            for x in 10..30 {
+                let corner = (10 + (x - 10) / 4 * 4, 10 + (y - 10) / 4 * 4);
+                assert_eq!(img.get_pixel(x, y), img.get_pixel(corner.0, corner.1));
+            }
+        }
+        assert_ne!(img.get_pixel(10, 10), img.get_pixel(14, 10));
+    }
+
+    #[test]
+    fn outside_of_the_area_is_untouched() {
+        let image_rect = Rect::from_min_size(pos2(100.0, 50.0), vec2(40.0, 30.0));
+        let area = Rect::from_min_max(pos2(110.0, 60.0), pos2(130.0, 76.0));
+        let original = noise(40, 30);
+        for method in Method::ALL {
+            let mut img = original.clone();
+            apply(&mut img, image_rect, &[redaction(area, method)]);
+            assert_ne!(img, original, "{} changed nothing", method.name());
+            untouched_outside(&img, &original, [10, 10, 30, 26]);
+        }
+    }
+
+    #[test]
+    fn hidpi_area_is_scaled_and_covers_partial_pixels() {
+        // Two pixels per point
+        let image_rect = Rect::from_min_size(pos2(100.0, 50.0), vec2(40.0, 30.0));
+        let area = Rect::from_min_max(pos2(110.25, 60.5), pos2(120.5, 70.25));
+        let original = noise(80, 60);
+        // In pixels 20.5..41 x 21..40.5
+        let covered = [20, 21, 41, 41];
+        for method in Method::ALL {
+            let mut img = original.clone();
+            apply(&mut img, image_rect, &[redaction(area, method)]);
+            untouched_outside(&img, &original, covered);
+        }
+        let mut img = original.clone();
+        apply(&mut img, image_rect, &[redaction(area, Method::Fill)]);
+        for y in 21..41 {
+            for x in 20..41 {
+                assert_eq!(*img.get_pixel(x, y), opaque_fill());
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use eframe::egui::{self, Rect, Vec2};
+use progetto_malnati::ipc::{CaptureInfo, PendingRequest, RemoteMode, Reply, Request};
+use progetto_malnati::{export, CaptureTarget, Error, Result};
+use std::path::{Path, PathBuf};
+
+use super::after_capture::CaptureMode;
+use super::AppState;
+use super::MyApp;
+
+//------ Capture asked on the control socket, answered when it is taken, cancelled or failed
+pub struct RemoteCapture {
+    request: PendingRequest,
+    mode: RemoteMode,
+    // Desktop coordinates, None to capture the whole target or let the user select
+    region: Option<Rect>,
+    // Monitor chosen in the window, given back once the request is done
+    previous_target: CaptureTarget,
+}
+
+impl MyApp {
+    //------ Requests received on the control socket since the last frame
+    pub fn check_remote_requests(&mut self, ctx: &egui::Context) {
+        while let Some(pending) = self.ipc.as_ref().and_then(|server| server.try_recv()) {
+            match pending.request.clone() {
+                Request::Capture {
+                    mode,
+                    monitor,
+                    region,
+                    delay,
+                } => self.start_remote_capture(ctx, pending, mode, monitor, region, delay),
+                Request::GetLast => {
+                    let result = self.save_temporary();
+                    reply_with_temporary(pending, result);
+                }
+                Request::Save { path, format } => {
+                    let result = self.save_capture_to(&path, format.as_deref());
+                    pending.reply(result.and_then(|_| CaptureInfo::of_file(&path)).into());
+                }
+                Request::Copy => {
+                    let result = self.copy_to_clipboard().and_then(|_| self.save_temporary());
+                    reply_with_temporary(pending, result);
+                }
+            }
+        }
+    }
+
+    fn start_remote_capture(
+        &mut self,
+        ctx: &egui::Context,
+        pending: PendingRequest,
+        mode: RemoteMode,
+        monitor: Option<usize>,
+        region: Option<[f32; 4]>,
+        delay: u64,
+    ) {
+        // The user may be selecting an area or editing, captures are not queued
+        if self.remote_capture.is_some() || self.state != AppState::MainApp {
+            pending.reply(Reply::failure("busy, another capture is in progress"));
+            return;
+        }
+        let previous_target = self.capture_target;
+        let region = match self.remote_target(monitor, region) {
+            Ok(region) => region,
+            Err(e) => {
+                self.capture_target = previous_target;
+                pending.reply(Reply::failure(e));
+                return;
+            }
+        };
+        self.remote_capture = Some(RemoteCapture {
+            request: pending,
+            mode,
+            region,
+            previous_target,
+        });
+        if delay == 0 {
+            self.run_remote_capture(ctx);
+            return;
+        }
+        if let Err(e) = self.start_countdown(ctx, delay) {
+            self.answer_remote_capture(Reply::failure(&e));
+            self.notifications.error(e.to_string());
+        }
+    }
+
+    // Picks the monitor of the request and turns its region into desktop coordinates
+    fn remote_target(&mut self, monitor: Option<usize>, region: Option<[f32; 4]>) -> Result<Option<Rect>> {
+        self.refresh_monitors()?;
+        if let Some(number) = monitor {
+            if number == 0 || number > self.monitors.len() {
+                return Err(Error::Ipc(format!(
+                    "monitor {} does not exist, see list-monitors",
+                    number
+                )));
+            }
+            self.capture_target = CaptureTarget::Monitor(number - 1);
+        }
+        match region {
+            Some([x, y, w, h]) if w > 0.0 && h > 0.0 => {
+                let origin = self.target_rect().min;
+                Ok(Some(Rect::from_min_size(
+                    origin + Vec2::new(x, y),
+                    Vec2::new(w, h),
+                )))
+            }
+            Some(_) => Err(Error::EmptySelection),
+            None => Ok(None),
+        }
+    }
+
+    //------ Starts the requested capture, as the buttons of the window would
+    pub fn run_remote_capture(&mut self, ctx: &egui::Context) {
+        let Some(remote) = self.remote_capture.as_ref() else {
+            return;
+        };
+        let (mode, region) = (remote.mode, remote.region);
+        if let Err(e) = self.set_new_capture_window(ctx) {
+            self.answer_remote_capture(Reply::failure(&e));
+            self.notifications.error(e.to_string());
+            self.restore_main_window(ctx);
+            self.state = AppState::MainApp;
+            return;
+        }
+        match (mode, region) {
+            (_, Some(region)) => {
+                // A selection already made, straight to the capture
+                self.set_capture_mode(CaptureMode::Area);
+                self.selected_area = [region.min, region.max];
+                self.state = AppState::Selection;
+                self.capture_requested = true;
+                ctx.request_repaint();
+            }
+            (RemoteMode::FullScreen, None) => self.handle_fullscreen_capture(ctx),
+            (RemoteMode::Area, None) => {
+                self.area = true;
+                self.begin_area_selection(ctx);
+            }
+        }
+    }
+
+    //------ Replies to the capture request in progress, if any
+    pub fn answer_remote_capture(&mut self, reply: Reply) {
+        if let Some(remote) = self.remote_capture.take() {
+            self.capture_target = remote.previous_target;
+            remote.request.reply(reply);
+        }
+    }
+
+    //------ Replies with the file holding the new capture, a temporary one when it was not saved
+    pub fn answer_remote_capture_with(&mut self, file: Option<PathBuf>) {
+        if self.remote_capture.is_none() {
+            return;
+        }
+        if let Some(path) = file {
+            self.answer_remote_capture(CaptureInfo::of_file(&path).into());
+            return;
+        }
+        let result = self.save_temporary();
+        if let Some(remote) = self.remote_capture.take() {
+            self.capture_target = remote.previous_target;
+            reply_with_temporary(remote.request, result);
+        }
+    }
+
+    // Saves to the exact path of a request, the format guessed from its extension when missing
+    fn save_capture_to(&mut self, path: &Path, format: Option<&str>) -> Result<()> {
+        let capture = self.capture().ok_or(Error::NothingCaptured)?;
+        let format = export::resolve_format(path, format)?;
+        let image = capture.rendered()?;
+        export::save_image(&image, path, format, &self.export_options)?;
+        self.mark_saved();
+        Ok(())
+    }
+}
+
+// The file is removed once the client has the reply
+fn reply_with_temporary(pending: PendingRequest, file: Result<PathBuf>) {
+    match file {
+        Ok(path) => pending.reply_with_temporary(CaptureInfo::of_file(&path).into(), path),
+        Err(e) => pending.reply(Reply::failure(e)),
+    }
+}
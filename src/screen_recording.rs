@@ -0,0 +1,239 @@
+use eframe::egui::{self, CentralPanel, Rect, Vec2};
+use progetto_malnati::keybidings::Action;
+use progetto_malnati::naming::{self, NameFields};
+use progetto_malnati::recording::{Recorder, Recording, RecordingFormat, RecordingSettings};
+use progetto_malnati::{ExportFormat, Result};
+use rfd::FileDialog;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use super::AppState;
+use super::MyApp;
+
+// Control panel the window shrinks to while recording
+const PANEL_SIZE: Vec2 = Vec2::new(300.0, 90.0);
+
+//------ Stopped recording being written to a file, on its own thread
+pub struct RecordingExport {
+    path: PathBuf,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl MyApp {
+    //------ Area selection as for a capture, the selected area is then recorded
+    pub fn begin_screen_recording(&mut self, ctx: &egui::Context) {
+        let result = self.set_new_capture_window(ctx);
+        if self.report(result).is_some() {
+            self.record_next_selection = true;
+            self.begin_area_selection(ctx);
+        }
+    }
+
+    //------ Records area (virtual-desktop coordinates) until stopped or the maximum duration
+    pub fn start_screen_recording(&mut self, ctx: &egui::Context, area: Rect) {
+        self.record_next_selection = false;
+        self.frozen = None;
+        self.frozen_texture = None;
+        let settings = RecordingSettings {
+            fps: self.recording.fps,
+            max_duration: Duration::from_secs(self.recording.max_duration),
+            memory_limit: (self.recording.memory_limit as usize).saturating_mul(1024 * 1024),
+        };
+        self.recorder = Some(Recorder::start(
+            self.monitors.clone(),
+            self.capture_target,
+            area,
+            settings,
+        ));
+        self.show_indicator_window(ctx, self.panel_rect(area));
+        self.state = AppState::Recording;
+    }
+
+    // First corner of the target where the panel does not end up in the recording
+    fn panel_rect(&self, area: Rect) -> Rect {
+        let target = self.target_rect().shrink(20.0);
+        let corners = [
+            Rect::from_min_size(target.right_top() - Vec2::new(PANEL_SIZE.x, 0.0), PANEL_SIZE),
+            Rect::from_min_size(target.right_bottom() - PANEL_SIZE, PANEL_SIZE),
+            Rect::from_min_size(target.left_bottom() - Vec2::new(0.0, PANEL_SIZE.y), PANEL_SIZE),
+            Rect::from_min_size(target.left_top(), PANEL_SIZE),
+        ];
+        corners
+            .into_iter()
+            .find(|corner| !corner.intersects(area))
+            .unwrap_or(corners[0])
+    }
+
+    //------ Elapsed time, frames and memory, with the pause, stop and cancel buttons
+    pub fn recording_state_visuals(&mut self, ctx: &egui::Context) {
+        let Some(recorder) = self.recorder.as_ref() else {
+            self.state = AppState::MainApp;
+            return;
+        };
+        let status = recorder.status();
+        let paused = recorder.is_paused();
+        // Maximum duration reached, or the screen could not be captured any more
+        if status.finished {
+            self.stop_screen_recording(ctx);
+            return;
+        }
+        CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading(format!(
+                    "{} {} / {}",
+                    if paused { "Paused" } else { "● Recording" },
+                    clock(status.elapsed),
+                    clock(Duration::from_secs(self.recording.max_duration))
+                ));
+                let mut details = format!(
+                    "{} frames, {} MB in memory",
+                    status.frames,
+                    status.memory / (1024 * 1024)
+                );
+                if status.spilled > 0 {
+                    details.push_str(&format!(", {} on disk", status.spilled));
+                }
+                ui.label(details);
+                ui.horizontal(|ui| {
+                    let pause = if paused { "Resume" } else { "Pause" };
+                    self.action_button(ui, ctx, pause, Action::PauseRecording);
+                    self.action_button(ui, ctx, "Stop and save…", Action::Confirm);
+                    self.action_button(ui, ctx, "Cancel", Action::Cancel);
+                });
+            });
+        });
+        ctx.request_repaint_after(Duration::from_millis(200));
+    }
+
+    pub fn toggle_recording_pause(&mut self) {
+        if let Some(recorder) = self.recorder.as_ref() {
+            recorder.set_paused(!recorder.is_paused());
+        }
+    }
+
+    //------ Throws the frames away
+    pub fn cancel_screen_recording(&mut self, ctx: &egui::Context) {
+        self.recorder = None;
+        self.restore_main_window(ctx);
+        self.state = AppState::MainApp;
+        self.notifications.info("Recording cancelled");
+    }
+
+    //------ Stops the recording and asks where to save it
+    pub fn stop_screen_recording(&mut self, ctx: &egui::Context) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let elapsed = recorder.status().elapsed;
+        let result = recorder.stop();
+        self.restore_main_window(ctx);
+        self.state = AppState::MainApp;
+        let Some(recording) = self.report(result) else {
+            return;
+        };
+        if elapsed >= Duration::from_secs(self.recording.max_duration) {
+            self.notifications
+                .info("Maximum recording duration reached, the recording was stopped");
+        }
+        self.save_recording_dialog(ctx, recording);
+    }
+
+    // The configured format is offered first, the file is encoded in the background
+    fn save_recording_dialog(&mut self, ctx: &egui::Context, recording: Recording) {
+        let (width, height) = recording.dimensions();
+        let fields = NameFields {
+            taken_at: SystemTime::now(),
+            monitor: naming::monitor_name(self.capture_target),
+            width,
+            height,
+            format: file_format(self.recording.format),
+        };
+        let mut dialog = FileDialog::new();
+        let formats = std::iter::once(self.recording.format).chain(
+            RecordingFormat::ALL
+                .into_iter()
+                .filter(|f| *f != self.recording.format),
+        );
+        for format in formats {
+            dialog = dialog.add_filter(format.name(), format.extensions());
+        }
+        let directory = self.last_save_dir.as_ref().unwrap_or(&self.save_dir);
+        let file = dialog
+            .set_file_name(naming::expand_template(&self.file_template, &fields, 1))
+            .set_directory(directory)
+            .save_file();
+        let Some(mut path) = file else {
+            self.notifications.info("Recording discarded");
+            return;
+        };
+        // The file confirmed in the dialog is the one written. An extension is only added when
+        // none was typed, and then an existing file is not replaced: nobody was asked about it
+        let format = RecordingFormat::from_path(&path).unwrap_or(self.recording.format);
+        if path.extension().is_none() {
+            let stem = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let extension = format.extensions()[0];
+            path.set_file_name(format!("{}.{}", stem, extension));
+            let mut counter = 1;
+            while path.exists() {
+                counter += 1;
+                path.set_file_name(format!("{}-{}.{}", stem, counter, extension));
+            }
+        }
+        self.last_save_dir = path.parent().map(|dir| dir.to_path_buf());
+        self.notifications.info(format!(
+            "Encoding {} frames to {}",
+            recording.frame_count(),
+            path.display()
+        ));
+        let options = self.export_options;
+        let target = path.clone();
+        let repaint = ctx.clone();
+        // Written through a temporary file, a failed encoding leaves nothing behind
+        let thread = thread::spawn(move || {
+            let result = recording.encode(&target, format, &options);
+            repaint.request_repaint();
+            result
+        });
+        self.recording_exports.push(RecordingExport { path, thread });
+    }
+
+    //------ Reports the recordings whose encoding ended
+    pub fn check_recording_exports(&mut self) {
+        let (done, running): (Vec<_>, Vec<_>) = std::mem::take(&mut self.recording_exports)
+            .into_iter()
+            .partition(|export| export.thread.is_finished());
+        self.recording_exports = running;
+        for export in done {
+            match export.thread.join() {
+                Ok(Ok(())) => self
+                    .notifications
+                    .info(format!("Recording saved to {}", export.path.display())),
+                Ok(Err(e)) => self.notifications.error(format!(
+                    "Cannot save the recording to {}: {}",
+                    export.path.display(),
+                    e
+                )),
+                Err(_) => self.notifications.error(format!(
+                    "Encoding of {} stopped unexpectedly",
+                    export.path.display()
+                )),
+            }
+        }
+    }
+}
+
+// Still image format sharing the extension, for {ext} in the file name template
+fn file_format(format: RecordingFormat) -> ExportFormat {
+    match format {
+        RecordingFormat::Gif => ExportFormat::Gif,
+        RecordingFormat::Apng => ExportFormat::Png,
+        RecordingFormat::WebP => ExportFormat::WebP,
+    }
+}
+
+// m:ss
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,278 @@
+use chrono::{DateTime, Local};
+use eframe::egui::{self, Pos2, TextureHandle, Vec2};
+use progetto_malnati::keybidings::Action;
+use progetto_malnati::{Annotation, Capture, History, Redaction};
+
+use super::application::load_texture;
+use super::MyApp;
+use super::TouchedFrame;
+
+//------ Selection of the crop editor, in display points
+#[derive(Debug, Clone, Copy)]
+pub struct CropState {
+    pub button_position: Pos2,
+    pub dimensions: Vec2,
+    // Still in capture coordinates, scaled to the displayed image on the next frame
+    pub rescale: bool,
+    pub resizing: bool,
+    pub frame: TouchedFrame,
+    pub drag_aspect_ratio: f32,
+}
+
+impl Default for CropState {
+    fn default() -> Self {
+        CropState {
+            button_position: Pos2::new(300.0, 300.0),
+            dimensions: Vec2::new(100.0, 100.0),
+            rescale: false,
+            resizing: false,
+            frame: TouchedFrame::None,
+            drag_aspect_ratio: 1.0,
+        }
+    }
+}
+
+//------ A capture open in the editor, with everything that belongs to it only
+pub struct Tab {
+    pub capture: Capture,
+    pub texture: Option<TextureHandle>,
+    // Edits of this capture, undone and redone in their own order
+    pub history: History,
+    pub selected_annotation: Option<usize>,
+    // Offset of the selected annotation while it is dragged
+    pub moving: Option<Vec2>,
+    // Shape being drawn, pushed to the capture when the drag ends
+    pub drawing: Option<Annotation>,
+    pub redacting: Option<Redaction>,
+    pub crop: CropState,
+    // Entry of the capture in the gallery, its edits are written back to it
+    pub gallery_entry: Option<String>,
+    pub gallery_dirty: bool,
+    // Written to a file since the last edit, closed by "Close saved tabs"
+    pub saved: bool,
+}
+
+impl Tab {
+    pub fn new(ctx: &egui::Context, capture: Capture) -> Tab {
+        let mut tab = Tab {
+            capture,
+            texture: None,
+            history: History::default(),
+            selected_annotation: None,
+            moving: None,
+            drawing: None,
+            redacting: None,
+            crop: CropState::default(),
+            gallery_entry: None,
+            gallery_dirty: false,
+            saved: false,
+        };
+        tab.refresh_texture(ctx);
+        tab
+    }
+
+    //------ Redactions are shown as they will be exported
+    pub fn refresh_texture(&mut self, ctx: &egui::Context) {
+        self.texture = Some(load_texture(ctx, "screenshot", &self.capture.redacted_image()));
+    }
+
+    pub fn after_history_change(&mut self, ctx: &egui::Context) {
+        // Indices may point to other annotations now
+        self.selected_annotation = None;
+        self.moving = None;
+        self.gallery_dirty = true;
+        self.saved = false;
+        self.refresh_texture(ctx);
+    }
+
+    // Neither written to a file nor kept in the gallery, closing it loses the capture
+    fn would_be_lost(&self) -> bool {
+        !self.saved && self.gallery_entry.is_none()
+    }
+
+    // Time it was taken or name of the opened image, a dot while it has unsaved changes
+    fn title(&self) -> String {
+        let metadata = &self.capture.metadata;
+        let mut title = match &metadata.imported_from {
+            Some(name) => name.clone(),
+            None => DateTime::<Local>::from(metadata.taken_at).format("%H:%M:%S").to_string(),
+        };
+        if !self.saved {
+            title.push_str(" •");
+        }
+        title
+    }
+}
+
+impl MyApp {
+    pub fn tab(&self) -> Option<&Tab> {
+        self.tabs.get(self.active_tab)
+    }
+
+    pub fn tab_mut(&mut self) -> Option<&mut Tab> {
+        self.tabs.get_mut(self.active_tab)
+    }
+
+    //------ Capture of the active tab
+    pub fn capture(&self) -> Option<&Capture> {
+        self.tab().map(|tab| &tab.capture)
+    }
+
+    //------ The capture of the active tab was written to a file
+    pub fn mark_saved(&mut self) {
+        if let Some(tab) = self.tab_mut() {
+            tab.saved = true;
+        }
+    }
+
+    //------ Opens the capture in a new tab and makes it the active one
+    pub fn open_tab(&mut self, ctx: &egui::Context, capture: Capture) {
+        self.tabs.push(Tab::new(ctx, capture));
+        self.select_tab(self.tabs.len() - 1);
+    }
+
+    pub fn select_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() || index == self.active_tab {
+            return;
+        }
+        // A shape half drawn stays with the tab it was started in, unfinished
+        if let Some(tab) = self.tab_mut() {
+            tab.drawing = None;
+            tab.redacting = None;
+            tab.moving = None;
+        }
+        self.active_tab = index;
+    }
+
+    //------ Next (step 1) or previous (step -1) tab, going round at both ends
+    pub fn cycle_tab(&mut self, step: isize) {
+        let count = self.tabs.len() as isize;
+        if count > 1 {
+            let index = (self.active_tab as isize + step).rem_euclid(count);
+            self.select_tab(index as usize);
+        }
+    }
+
+    //------ The capture stays in the gallery, its edits are written there first.
+    // A capture that would be lost is only closed once the user confirms it.
+    pub fn close_tab(&mut self, index: usize) {
+        match self.tabs.get(index) {
+            Some(tab) if tab.would_be_lost() => self.confirm_close = Some(index),
+            Some(_) => self.close_tab_now(index),
+            None => {}
+        }
+    }
+
+    fn close_tab_now(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        self.persist_gallery_entry();
+        self.tabs.remove(index);
+        // The index asked about may point to another tab now
+        self.confirm_close = None;
+        if index < self.active_tab || self.active_tab >= self.tabs.len() {
+            self.active_tab = self.active_tab.saturating_sub(1);
+        }
+    }
+
+    //------ Asks whether the unsaved capture chosen by close_tab can be thrown away
+    pub fn close_tab_dialog(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.confirm_close else {
+            return;
+        };
+        let Some(tab) = self.tabs.get(index) else {
+            self.confirm_close = None;
+            return;
+        };
+        let title = tab.title();
+        let mut answer = None;
+        egui::Window::new("Close without saving?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The capture {} was not saved and is not in the gallery, it will be lost.",
+                    title.trim_end_matches(" •")
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Close").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        match answer {
+            Some(true) => self.close_tab_now(index),
+            Some(false) => self.confirm_close = None,
+            None => {}
+        }
+    }
+
+    pub fn close_saved_tabs(&mut self) {
+        let before = self.tabs.len();
+        let active_closed = self.tab().is_none_or(|tab| tab.saved);
+        let closed_before = self.tabs.iter().take(self.active_tab).filter(|tab| tab.saved).count();
+        self.tabs.retain(|tab| !tab.saved);
+        self.confirm_close = None;
+        // The active tab stays active if it is still open, otherwise the last one is
+        self.active_tab = if active_closed {
+            self.tabs.len().saturating_sub(1)
+        } else {
+            self.active_tab - closed_before
+        };
+        let closed = before - self.tabs.len();
+        self.notifications.info(match closed {
+            0 => "No saved tab to close".to_string(),
+            1 => "1 saved tab closed".to_string(),
+            n => format!("{} saved tabs closed", n),
+        });
+    }
+
+    //------ One button per open capture, with a close button
+    pub fn tab_bar(&mut self, ctx: &egui::Context) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        let mut selected = None;
+        let mut closed = None;
+        let mut close_saved = false;
+        egui::TopBottomPanel::top("capture tabs").show(ctx, |ui| {
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, tab) in self.tabs.iter().enumerate() {
+                        let region = tab.capture.region;
+                        let response = ui
+                            .selectable_label(index == self.active_tab, tab.title())
+                            .on_hover_text(format!(
+                                "{}, {}×{}",
+                                tab.capture.metadata.target_label,
+                                region.width().round(),
+                                region.height().round()
+                            ));
+                        if response.clicked() {
+                            selected = Some(index);
+                        }
+                        if ui.small_button("×").on_hover_text("Close").clicked() {
+                            closed = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    close_saved = ui.button("Close saved").clicked();
+                });
+            });
+        });
+        if let Some(index) = selected {
+            self.select_tab(index);
+        }
+        if let Some(index) = closed {
+            self.close_tab(index);
+        }
+        if close_saved {
+            self.run_action(ctx, Action::CloseSavedTabs);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+//------ Directory of the temporary captures and recordings, readable by the user only.
+// In $XDG_RUNTIME_DIR, or in the cache directory when it is not set, never in the shared /tmp
+pub fn dir() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(runtime) => runtime.join(env!("CARGO_PKG_NAME")),
+        None => dirs::cache_dir()
+            .ok_or_else(|| Error::Io(io::Error::new(io::ErrorKind::NotFound, "no cache directory")))?
+            .join(env!("CARGO_PKG_NAME"))
+            .join("tmp"),
+    };
+    make_private(&dir)?;
+    Ok(dir)
+}
+
+//------ Creates dir if missing, then checks that it is a directory of the user, closed to others.
+// A directory created by someone else (or a symlink to one) is refused, not reused
+#[cfg(unix)]
+pub fn make_private(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    // SAFETY: geteuid cannot fail and has no side effect
+    let user = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != user || metadata.mode() & 0o077 != 0 {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory of the user", dir.display()),
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn make_private(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::{env, process};
+
+    #[test]
+    fn private_directory() {
+        let root = env::temp_dir().join(format!("progetto_malnati-test-{}-private", process::id()));
+        let dir = root.join("tmp");
+        make_private(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        // Created before by someone, open to the other users
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(make_private(&dir).is_err());
+        fs::remove_dir(&dir).unwrap();
+        // Symlink to a private directory elsewhere
+        let elsewhere = root.join("elsewhere");
+        make_private(&elsewhere).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, &dir).unwrap();
+        assert!(make_private(&dir).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use crate::keybidings::Action;
+
+//------ What was picked in the tray icon or its menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayCommand {
+    Run(Action),
+    // Brings back the editor window
+    Show,
+    Quit,
+}
+
+#[cfg(target_os = "linux")]
+pub use sni::TrayIcon;
+
+#[cfg(target_os = "linux")]
+mod sni {
+    use ksni::blocking::{Handle, TrayMethods};
+    use ksni::menu::StandardItem;
+    use ksni::{MenuItem, ToolTip};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+
+    use super::TrayCommand;
+    use crate::error::{Error, Result};
+    use crate::keybidings::Action;
+
+    struct Item {
+        actions: Vec<Action>,
+        commands: Sender<TrayCommand>,
+        // Wakes the app up, the window may be hidden
+        notify: Arc<dyn Fn() + Send + Sync>,
+    }
+
+    impl Item {
+        fn send(&self, command: TrayCommand) {
+            if self.commands.send(command).is_ok() {
+                (self.notify)();
+            }
+        }
+    }
+
+    impl ksni::Tray for Item {
+        fn id(&self) -> String {
+            env!("CARGO_PKG_NAME").into()
+        }
+
+        fn title(&self) -> String {
+            "Screen Capture".into()
+        }
+
+        fn icon_name(&self) -> String {
+            "camera-photo".into()
+        }
+
+        fn tool_tip(&self) -> ToolTip {
+            ToolTip {
+                title: "Screen Capture".into(),
+                ..Default::default()
+            }
+        }
+
+        fn activate(&mut self, _x: i32, _y: i32) {
+            self.send(TrayCommand::Show);
+        }
+
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            let mut items: Vec<MenuItem<Self>> = self
+                .actions
+                .iter()
+                .map(|action| {
+                    let action = *action;
+                    StandardItem {
+                        label: action.name().into(),
+                        activate: Box::new(move |item: &mut Self| {
+                            item.send(TrayCommand::Run(action))
+                        }),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect();
+            items.push(MenuItem::Separator);
+            items.push(
+                StandardItem {
+                    label: "Open editor".into(),
+                    activate: Box::new(|item: &mut Self| item.send(TrayCommand::Show)),
+                    ..Default::default()
+                }
+                .into(),
+            );
+            items.push(
+                StandardItem {
+                    label: "Quit".into(),
+                    icon_name: "application-exit".into(),
+                    activate: Box::new(|item: &mut Self| item.send(TrayCommand::Quit)),
+                    ..Default::default()
+                }
+                .into(),
+            );
+            items
+        }
+    }
+
+    //------ StatusNotifierItem with the capture actions, removed when dropped
+    pub struct TrayIcon {
+        handle: Handle<Item>,
+        commands: Receiver<TrayCommand>,
+    }
+
+    impl TrayIcon {
+        //------ Fails when the desktop shows no tray (no StatusNotifierWatcher)
+        pub fn start(
+            actions: &[Action],
+            on_command: impl Fn() + Send + Sync + 'static,
+        ) -> Result<TrayIcon> {
+            let (sender, commands) = mpsc::channel();
+            let item = Item {
+                actions: actions.to_vec(),
+                commands: sender,
+                notify: Arc::new(on_command),
+            };
+            let handle = item
+                .spawn()
+                .map_err(|e| Error::Tray(e.to_string()))?;
+            Ok(TrayIcon { handle, commands })
+        }
+
+        //------ Next command picked since the last call
+        pub fn try_recv(&self) -> Option<TrayCommand> {
+            self.commands.try_recv().ok()
+        }
+    }
+
+    impl Drop for TrayIcon {
+        fn drop(&mut self) {
+            self.handle.shutdown().wait();
+        }
+    }
+}
+
+//------ The tray needs a StatusNotifierItem host, only looked for on Linux
+#[cfg(not(target_os = "linux"))]
+pub struct TrayIcon;
+
+#[cfg(not(target_os = "linux"))]
+impl TrayIcon {
+    pub fn start(
+        _actions: &[Action],
+        _on_command: impl Fn() + Send + Sync + 'static,
+    ) -> crate::error::Result<TrayIcon> {
+        Err(crate::error::Error::Tray("only available on Linux".to_string()))
+    }
+
+    pub fn try_recv(&self) -> Option<TrayCommand> {
+        None
+    }
+}
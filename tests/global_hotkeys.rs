@@ -0,0 +1,71 @@
+//! Grabs a global shortcut and presses it through XTest.
+//! Needs an X server, run with e.g. `xvfb-run cargo test -- --ignored`.
+#![cfg(all(target_os = "linux", feature = "desktop"))]
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use progetto_malnati::hotkeys::HotkeyListener;
+use progetto_malnati::keybidings::{Action, Shortcut};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt, KEY_PRESS_EVENT, KEY_RELEASE_EVENT};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+const CONTROL_L: u32 = 0xffe3;
+const ALT_L: u32 = 0xffe9;
+const F12: u32 = 0xffc9;
+
+// First keycode producing keysym without shift
+fn keycode(connection: &RustConnection, keysym: u32) -> u8 {
+    let setup = connection.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = connection
+        .get_keyboard_mapping(min, max - min + 1)
+        .unwrap()
+        .reply()
+        .unwrap();
+    let per_keycode = mapping.keysyms_per_keycode as usize;
+    let index = mapping
+        .keysyms
+        .chunks(per_keycode)
+        .position(|syms| syms.first() == Some(&keysym))
+        .expect("keysym not in the keyboard map");
+    min + index as u8
+}
+
+fn fake_key(connection: &RustConnection, kind: u8, keycode: u8) {
+    let root = connection.setup().roots[0].root;
+    connection
+        .xtest_fake_input(kind, keycode, x11rb::CURRENT_TIME, root, 0, 0, 0)
+        .unwrap();
+}
+
+#[test]
+#[ignore = "needs an X server with the XTEST extension (Xvfb)"]
+fn grabbed_shortcut_is_reported() {
+    let shortcut: Shortcut = "Ctrl+Alt+F12".parse().unwrap();
+    let (listener, failures) = HotkeyListener::start(&[(Action::Fullscreen, shortcut)], || {})
+        .expect("no X server, set DISPLAY");
+    assert!(failures.is_empty(), "{:?}", failures);
+
+    let (connection, _) = x11rb::connect(None).unwrap();
+    let keys = [CONTROL_L, ALT_L, F12].map(|keysym| keycode(&connection, keysym));
+    for key in keys {
+        fake_key(&connection, KEY_PRESS_EVENT, key);
+    }
+    for key in keys.into_iter().rev() {
+        fake_key(&connection, KEY_RELEASE_EVENT, key);
+    }
+    connection.flush().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(action) = listener.try_recv() {
+            assert_eq!(action, Action::Fullscreen);
+            break;
+        }
+        assert!(Instant::now() < deadline, "shortcut press not reported");
+        thread::sleep(Duration::from_millis(20));
+    }
+}